@@ -0,0 +1,11 @@
+create table event_log (
+  id bigserial primary key not null,
+
+  origin jsonb not null,
+  event jsonb not null,
+
+  created_at timestamptz not null default now()
+);
+
+create index event_log_created_at_idx
+  on event_log (created_at);
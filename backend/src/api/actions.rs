@@ -1,10 +1,15 @@
 use std::sync::Arc;
 
 use crate::homectl_core::state::AppState;
-use homectl_types::{action::Action, event::Message};
+use homectl_types::{
+    action::Action,
+    api::ApiScope,
+    event::Message,
+    event_log::{Event, EventOrigin},
+};
 use warp::Filter;
 
-use super::with_state;
+use super::{auth::with_scope, with_state};
 
 pub fn actions(
     app_state: &Arc<AppState>,
@@ -22,11 +27,20 @@ fn post_action(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("trigger")
         .and(warp::post())
+        .and(with_scope(app_state, ApiScope::Control))
         .and(warp::body::json())
         .and(with_state(app_state))
         .map(|action: Action, app_state: Arc<AppState>| {
+            let message = Message::Action(action);
+            app_state.event_log.log(
+                EventOrigin::Api,
+                Event::Message {
+                    message: message.clone(),
+                },
+            );
+
             let sender = app_state.sender.clone();
-            sender.send(Message::Action(action));
+            sender.send(message);
 
             Ok(warp::reply::json(&()))
         })
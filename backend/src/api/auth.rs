@@ -0,0 +1,92 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use homectl_types::{api::ApiScope, event::Message};
+use warp::{http::StatusCode, reject::Reject, Filter, Rejection};
+
+use crate::homectl_core::state::AppState;
+
+use super::with_state;
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    InsufficientScope,
+}
+
+impl Reject for AuthError {}
+
+/// Resolves the scope of the token provided either in an `Authorization:
+/// Bearer <token>` header or a `token` query parameter (browsers can't set
+/// headers on WebSocket connections). Rejects the request if an invalid token
+/// was provided, or if no token was provided while authentication is enabled.
+pub fn with_token_scope(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (ApiScope,), Error = Rejection> + Clone {
+    with_state(app_state)
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(
+            |app_state: Arc<AppState>,
+             header: Option<String>,
+             query: HashMap<String, String>| async move {
+                if !app_state.api_config.auth_enabled() {
+                    return Ok(ApiScope::Admin);
+                }
+
+                let token = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .or_else(|| query.get("token").map(String::as_str))
+                    .ok_or_else(|| warp::reject::custom(AuthError::MissingToken))?;
+
+                app_state
+                    .api_config
+                    .find_token_scope(token.trim())
+                    .ok_or_else(|| warp::reject::custom(AuthError::InvalidToken))
+            },
+        )
+}
+
+/// Rejects the request unless it was made with a token granting at least the
+/// required scope.
+pub fn with_scope(
+    app_state: &Arc<AppState>,
+    required: ApiScope,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    with_token_scope(app_state)
+        .and_then(move |scope: ApiScope| async move {
+            if scope >= required {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(AuthError::InsufficientScope))
+            }
+        })
+        .untuple_one()
+}
+
+/// Scope required for sending given message over the WebSocket API
+pub fn required_message_scope(msg: &Message) -> ApiScope {
+    match msg {
+        Message::Action(_) | Message::SetDeviceState { .. } => ApiScope::Control,
+        _ => ApiScope::Admin,
+    }
+}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl warp::Reply, Infallible> {
+    let status = match err.find::<AuthError>() {
+        Some(AuthError::MissingToken) | Some(AuthError::InvalidToken) => StatusCode::UNAUTHORIZED,
+        Some(AuthError::InsufficientScope) => StatusCode::FORBIDDEN,
+        None if err.find::<warp::cors::CorsForbidden>().is_some() => StatusCode::FORBIDDEN,
+        None if err.is_not_found() => StatusCode::NOT_FOUND,
+        None if err.find::<warp::reject::MethodNotAllowed>().is_some() => {
+            StatusCode::METHOD_NOT_ALLOWED
+        }
+        None => StatusCode::BAD_REQUEST,
+    };
+
+    Ok(warp::reply::with_status(
+        status.canonical_reason().unwrap_or_default(),
+        status,
+    ))
+}
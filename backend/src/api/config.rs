@@ -0,0 +1,37 @@
+use std::{convert::Infallible, sync::Arc};
+
+use homectl_types::api::ApiScope;
+use warp::{http::StatusCode, Filter};
+
+use crate::homectl_core::state::AppState;
+
+use super::{auth::with_scope, with_state};
+
+pub fn config(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("config").and(reload_config(app_state))
+}
+
+fn reload_config(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("reload")
+        .and(warp::post())
+        .and(with_scope(app_state, ApiScope::Admin))
+        .and(with_state(app_state))
+        .and_then(reload_config_impl)
+}
+
+async fn reload_config_impl(app_state: Arc<AppState>) -> Result<impl warp::Reply, Infallible> {
+    match app_state.reload_config().await {
+        Ok(()) => Ok(warp::reply::with_status(
+            warp::reply::json(&()),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("{:?}", e)),
+            StatusCode::BAD_REQUEST,
+        )),
+    }
+}
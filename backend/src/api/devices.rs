@@ -1,27 +1,56 @@
 use std::{convert::Infallible, sync::Arc};
 
-use homectl_types::device::{Device, DeviceId};
-use warp::Filter;
+use chrono::{DateTime, Utc};
+use homectl_types::{
+    api::ApiScope,
+    device::{Device, DeviceHistoryEntry, DeviceId, DeviceKey},
+    event::Message,
+    event_log::{Event, EventOrigin},
+    integration::IntegrationId,
+};
+use warp::{http::StatusCode, Filter};
 
-use crate::homectl_core::state::AppState;
+use crate::{db::actions::db_get_device_history, homectl_core::state::AppState};
 
-use super::with_state;
+use super::{auth::with_scope, with_state};
 
 #[derive(serde::Serialize)]
 pub struct DevicesResponse {
     devices: Vec<Device>,
 }
 
+#[derive(serde::Serialize)]
+pub struct DeviceHistoryResponse {
+    history: Vec<DeviceHistoryEntry>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeviceHistoryQuery {
+    /// Defaults to 24 hours before `to`
+    from: Option<DateTime<Utc>>,
+
+    /// Defaults to current time
+    to: Option<DateTime<Utc>>,
+
+    /// Downsample history to at most one entry per this many seconds
+    resolution_secs: Option<i64>,
+}
+
 pub fn devices(
     app_state: &Arc<AppState>,
 ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-    warp::path("devices").and(get_devices(app_state).or(put_device(app_state)))
+    warp::path("devices").and(
+        get_device_history(app_state)
+            .or(get_devices(app_state))
+            .or(put_device(app_state)),
+    )
 }
 
 fn get_devices(
     app_state: &Arc<AppState>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::get()
+        .and(with_scope(app_state, ApiScope::Read))
         .and(with_state(app_state))
         .map(|app_state: Arc<AppState>| {
             let devices = app_state.devices.get_devices();
@@ -39,6 +68,7 @@ fn put_device(
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("devices" / DeviceId)
         .and(warp::put())
+        .and(with_scope(app_state, ApiScope::Control))
         .and(warp::body::json())
         .and(with_state(app_state))
         .and_then(put_device_impl)
@@ -54,6 +84,16 @@ async fn put_device_impl(
         return Ok(warp::reply::json(&DevicesResponse { devices: vec![] }));
     }
 
+    app_state.event_log.log(
+        EventOrigin::Api,
+        Event::Message {
+            message: Message::SetDeviceState {
+                device: device.clone(),
+                set_scene: true,
+            },
+        },
+    );
+
     let mut devices = app_state.devices.clone();
     devices.set_device_state(&device, true, false, false).await;
 
@@ -64,3 +104,145 @@ async fn put_device_impl(
 
     Ok(warp::reply::json(&response))
 }
+
+fn get_device_history(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(IntegrationId / DeviceId / "history")
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(warp::query::<DeviceHistoryQuery>())
+        .and_then(get_device_history_impl)
+}
+
+async fn get_device_history_impl(
+    integration_id: IntegrationId,
+    device_id: DeviceId,
+    query: DeviceHistoryQuery,
+) -> Result<impl warp::Reply, Infallible> {
+    let to = query.to.unwrap_or_else(Utc::now);
+    let from = query.from.unwrap_or(to - chrono::Duration::hours(24));
+    let key = DeviceKey::new(integration_id, device_id);
+
+    let resolution_ms = match query.resolution_secs {
+        Some(secs) => match secs.checked_mul(1000).filter(|ms| *ms > 0) {
+            Some(resolution_ms) => Some(resolution_ms),
+            None => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&format!("Invalid resolution_secs {}", secs)),
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
+        },
+        None => None,
+    };
+
+    match db_get_device_history(&key, from, to).await {
+        Ok(history) => {
+            let history = match resolution_ms {
+                Some(resolution_ms) => downsample_history(history, resolution_ms),
+                None => history,
+            };
+
+            let response = DeviceHistoryResponse { history };
+
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("{:?}", e)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+
+/// Keeps only the most recent entry within each time bucket of given length,
+/// i.e. the state each bucket ended up in
+fn downsample_history(
+    history: Vec<DeviceHistoryEntry>,
+    resolution_ms: i64,
+) -> Vec<DeviceHistoryEntry> {
+    let mut result: Vec<DeviceHistoryEntry> = vec![];
+    let mut prev_bucket = None;
+
+    for entry in history {
+        let bucket = entry
+            .recorded_at
+            .timestamp_millis()
+            .div_euclid(resolution_ms);
+
+        if prev_bucket == Some(bucket) {
+            result.pop();
+        }
+
+        prev_bucket = Some(bucket);
+        result.push(entry);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use homectl_types::device::{DeviceState, OnOffDevice};
+
+    fn entry(secs: i64, power: bool) -> DeviceHistoryEntry {
+        DeviceHistoryEntry {
+            scene_id: None,
+            state: DeviceState::OnOffDevice(OnOffDevice { power }),
+            recorded_at: Utc.timestamp(secs, 0),
+        }
+    }
+
+    fn timestamps(history: &[DeviceHistoryEntry]) -> Vec<i64> {
+        history
+            .iter()
+            .map(|entry| entry.recorded_at.timestamp())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_last_entry_of_each_bucket() {
+        let history = vec![
+            entry(0, true),
+            entry(100, false),
+            entry(299, true),
+            entry(300, false),
+            entry(301, true),
+            entry(900, false),
+        ];
+
+        let downsampled = downsample_history(history, 300 * 1000);
+
+        assert_eq!(timestamps(&downsampled), vec![299, 301, 900]);
+        assert_eq!(
+            downsampled[0].state,
+            DeviceState::OnOffDevice(OnOffDevice { power: true })
+        );
+    }
+
+    #[test]
+    fn keeps_sparse_history_intact() {
+        let history = vec![entry(0, true), entry(1000, false), entry(2000, true)];
+
+        assert_eq!(
+            timestamps(&downsample_history(history, 60 * 1000)),
+            vec![0, 1000, 2000]
+        );
+        assert!(downsample_history(vec![], 60 * 1000).is_empty());
+    }
+
+    #[test]
+    fn handles_timestamps_before_epoch() {
+        let history = vec![entry(-61, true), entry(-1, false), entry(0, true)];
+
+        assert_eq!(
+            timestamps(&downsample_history(history, 60 * 1000)),
+            vec![-61, -1, 0]
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::{convert::Infallible, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use homectl_types::{api::ApiScope, event_log::EventLogEntry};
+use warp::{http::StatusCode, Filter};
+
+use crate::{db::actions::db_get_event_log, homectl_core::state::AppState};
+
+use super::auth::with_scope;
+
+#[derive(serde::Serialize)]
+pub struct EventsResponse {
+    events: Vec<EventLogEntry>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EventsQuery {
+    /// Defaults to 24 hours before `to`
+    from: Option<DateTime<Utc>>,
+
+    /// Defaults to current time
+    to: Option<DateTime<Utc>>,
+}
+
+pub fn events(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("events").and(get_events(app_state))
+}
+
+fn get_events(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(warp::query::<EventsQuery>())
+        .and_then(get_events_impl)
+}
+
+/// Returns the time range to fetch events from, filling in defaults for
+/// missing bounds
+fn event_range(query: &EventsQuery, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let to = query.to.unwrap_or(now);
+    let from = query.from.unwrap_or(to - chrono::Duration::hours(24));
+
+    (from, to)
+}
+
+async fn get_events_impl(query: EventsQuery) -> Result<impl warp::Reply, Infallible> {
+    let (from, to) = event_range(&query, Utc::now());
+
+    match db_get_event_log(from, to).await {
+        Ok(events) => Ok(warp::reply::with_status(
+            warp::reply::json(&EventsResponse { events }),
+            StatusCode::OK,
+        )),
+        Err(e) => Ok(warp::reply::with_status(
+            warp::reply::json(&format!("{:?}", e)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use homectl_types::event_log::{Event, EventOrigin};
+
+    fn query(from: Option<i64>, to: Option<i64>) -> EventsQuery {
+        EventsQuery {
+            from: from.map(|secs| Utc.timestamp(secs, 0)),
+            to: to.map(|secs| Utc.timestamp(secs, 0)),
+        }
+    }
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn defaults_to_last_day() {
+        let now = Utc.timestamp(10 * DAY, 0);
+
+        assert_eq!(
+            event_range(&query(None, None), now),
+            (Utc.timestamp(9 * DAY, 0), now)
+        );
+    }
+
+    #[test]
+    fn defaults_to_day_before_given_end() {
+        let now = Utc.timestamp(10 * DAY, 0);
+
+        assert_eq!(
+            event_range(&query(None, Some(5 * DAY)), now),
+            (Utc.timestamp(4 * DAY, 0), Utc.timestamp(5 * DAY, 0))
+        );
+    }
+
+    #[test]
+    fn defaults_to_now_after_given_start() {
+        let now = Utc.timestamp(10 * DAY, 0);
+
+        assert_eq!(
+            event_range(&query(Some(DAY), None), now),
+            (Utc.timestamp(DAY, 0), now)
+        );
+    }
+
+    #[test]
+    fn keeps_given_range() {
+        let now = Utc.timestamp(10 * DAY, 0);
+
+        assert_eq!(
+            event_range(&query(Some(DAY), Some(2 * DAY)), now),
+            (Utc.timestamp(DAY, 0), Utc.timestamp(2 * DAY, 0))
+        );
+    }
+
+    #[test]
+    fn serializes_entries_with_kinds() {
+        let entry = EventLogEntry {
+            origin: EventOrigin::WebSocket { user_id: 1 },
+            event: Event::Error {
+                message: serde_json::from_value(serde_json::json!({
+                    "Action": { "action": "ActivateScene", "scene_id": "evening" }
+                }))
+                .unwrap(),
+                error: "Scene not found".to_string(),
+            },
+            created_at: Utc.timestamp(0, 0),
+        };
+
+        let json = serde_json::to_value(&entry).unwrap();
+
+        assert_eq!(json["origin"]["kind"], "web_socket");
+        assert_eq!(json["origin"]["user_id"], 1);
+        assert_eq!(json["event"]["kind"], "error");
+        assert_eq!(json["event"]["error"], "Scene not found");
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use homectl_types::{
+    api::ApiScope,
+    group::{FlattenedGroupsConfig, GroupId},
+};
+use warp::{http::StatusCode, Filter};
+
+use crate::homectl_core::state::AppState;
+
+use super::{auth::with_scope, with_state};
+
+#[derive(serde::Serialize)]
+pub struct GroupsResponse {
+    groups: FlattenedGroupsConfig,
+}
+
+pub fn groups(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("groups").and(get_groups(app_state).or(get_group(app_state)))
+}
+
+fn get_groups(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|app_state: Arc<AppState>| {
+            let devices = app_state.devices.get_devices();
+
+            let response = GroupsResponse {
+                groups: app_state.groups.get_flattened_groups(&devices),
+            };
+
+            Ok(warp::reply::json(&response))
+        })
+}
+
+fn get_group(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(GroupId)
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|group_id: GroupId, app_state: Arc<AppState>| {
+            let devices = app_state.devices.get_devices();
+            let groups = app_state.groups.get_flattened_groups(&devices);
+
+            match groups.get(&group_id) {
+                Some(group) => warp::reply::with_status(warp::reply::json(group), StatusCode::OK),
+                None => warp::reply::with_status(warp::reply::json(&()), StatusCode::NOT_FOUND),
+            }
+        })
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use homectl_types::{api::ApiScope, integration::IntegrationsStatus};
+use warp::Filter;
+
+use crate::homectl_core::state::AppState;
+
+use super::{auth::with_scope, with_state};
+
+#[derive(serde::Serialize)]
+pub struct IntegrationsResponse {
+    integrations: IntegrationsStatus,
+}
+
+pub fn integrations(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("integrations").and(get_integrations(app_state))
+}
+
+fn get_integrations(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|app_state: Arc<AppState>| {
+            let response = IntegrationsResponse {
+                integrations: app_state.integrations.get_status(),
+            };
+
+            Ok(warp::reply::json(&response))
+        })
+}
@@ -0,0 +1,29 @@
+use std::{convert::Infallible, sync::Arc};
+
+use homectl_types::api::ApiScope;
+use warp::{http::StatusCode, Filter};
+
+use crate::homectl_core::{metrics::render_metrics, state::AppState};
+
+use super::{auth::with_scope, with_state};
+
+pub fn metrics(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .and_then(get_metrics)
+}
+
+async fn get_metrics(app_state: Arc<AppState>) -> Result<impl warp::Reply, Infallible> {
+    match render_metrics(&app_state).await {
+        Ok(metrics) => Ok(warp::reply::with_status(metrics, StatusCode::OK)),
+        Err(e) => Ok(warp::reply::with_status(
+            format!("{:?}", e),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )),
+    }
+}
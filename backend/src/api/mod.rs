@@ -1,37 +1,223 @@
-use std::sync::Arc;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
 
 use crate::AppState;
 
 mod actions;
+mod auth;
+mod config;
 mod devices;
+mod events;
+mod groups;
+mod integrations;
+mod metrics;
+mod routines;
+mod scenes;
 mod ws;
 
+use self::config::*;
 use actions::*;
 use devices::*;
+use events::*;
+use groups::*;
+use integrations::*;
+use metrics::*;
+use routines::*;
+use scenes::*;
 
-use anyhow::Result;
-use warp::Filter;
+use anyhow::{anyhow, Context, Result};
+use homectl_types::api::ServerConfig;
+use warp::{http::Uri, Filter};
 
 use self::ws::ws;
 
 pub fn with_state(
     app_state: &Arc<AppState>,
-) -> impl Filter<Extract = (Arc<AppState>,), Error = std::convert::Infallible> + Clone {
+) -> impl Filter<Extract = (Arc<AppState>,), Error = Infallible> + Clone {
     let app_state = app_state.clone();
     warp::any().map(move || app_state.clone())
 }
 
 // Example of warp usage: https://github.com/seanmonstar/warp/blob/master/examples/todos.rs
-pub fn init_api(app_state: &Arc<AppState>) -> Result<()> {
-    let api = warp::path("api")
-        .and(warp::path("v1"))
-        .and(devices(app_state).or(actions(app_state)));
+pub fn init_api(app_state: &Arc<AppState>, server_config: ServerConfig) -> Result<()> {
+    if server_config.tls_cert_path.is_some() != server_config.tls_key_path.is_some() {
+        return Err(anyhow!(
+            "Both tls_cert_path and tls_key_path must be set to enable TLS"
+        ));
+    }
+
+    let api = warp::path("api").and(warp::path("v1")).and(
+        devices(app_state)
+            .or(actions(app_state))
+            .or(scenes(app_state))
+            .or(groups(app_state))
+            .or(routines(app_state))
+            .or(events(app_state))
+            .or(integrations(app_state))
+            .or(config(app_state)),
+    );
 
     let ws = ws(app_state);
 
+    let routes = ws.or(metrics(app_state)).or(api);
+
+    let cors = match server_config.cors_allowed_origins.as_deref() {
+        Some(allowed_origins) => Some(mk_cors(allowed_origins)?),
+        None => None,
+    };
+
     tokio::spawn(async move {
-        warp::serve(ws.or(api)).run(([127, 0, 0, 1], 45289)).await;
+        match cors {
+            // Rejections are recovered before applying CORS so that browsers
+            // can also read error responses such as 401 Unauthorized
+            Some(cors) => {
+                serve(
+                    routes
+                        .recover(auth::handle_rejection)
+                        .with(cors)
+                        .recover(auth::handle_rejection),
+                    server_config,
+                )
+                .await
+            }
+            None => serve(routes.recover(auth::handle_rejection), server_config).await,
+        }
     });
 
     Ok(())
 }
+
+/// warp panics on allowed origins that are not of the form
+/// `scheme://host[:port]`, so they are checked here first.
+fn validate_origin(origin: &str) -> Result<()> {
+    let uri = origin
+        .parse::<Uri>()
+        .with_context(|| format!("Invalid CORS origin {:?}", origin))?;
+
+    let is_origin = origin.contains("://")
+        && uri.scheme().is_some()
+        && uri.authority().is_some()
+        && uri.path() == "/"
+        && uri.query().is_none();
+
+    if is_origin {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid CORS origin {:?}, expected scheme://host[:port]",
+            origin
+        ))
+    }
+}
+
+fn mk_cors(allowed_origins: &[String]) -> Result<warp::cors::Cors> {
+    let cors = warp::cors()
+        .allow_methods(vec!["GET", "PUT", "POST", "DELETE"])
+        .allow_headers(vec!["authorization", "content-type"]);
+
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        return Ok(cors.allow_any_origin().build());
+    }
+
+    for origin in allowed_origins {
+        validate_origin(origin)?;
+    }
+
+    Ok(cors
+        .allow_origins(allowed_origins.iter().map(String::as_str))
+        .build())
+}
+
+async fn serve<F>(routes: F, config: ServerConfig)
+where
+    F: Filter<Error = Infallible> + Clone + Send + Sync + 'static,
+    F::Extract: warp::Reply,
+{
+    let addr = SocketAddr::new(config.address, config.port);
+
+    match (config.tls_cert_path, config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            println!("API listening on https://{}", addr);
+
+            warp::serve(routes)
+                .tls()
+                .cert_path(cert_path)
+                .key_path(key_path)
+                .run(addr)
+                .await
+        }
+        _ => {
+            println!("API listening on http://{}", addr);
+
+            warp::serve(routes).run(addr).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_origins() {
+        assert!(validate_origin("http://192.168.1.10:8080").is_ok());
+        assert!(validate_origin("https://homectl.example.com").is_ok());
+    }
+
+    #[test]
+    fn rejects_non_origins() {
+        assert!(validate_origin("").is_err());
+        assert!(validate_origin("192.168.1.10:8080").is_err());
+        assert!(validate_origin("homectl.example.com").is_err());
+        assert!(validate_origin("http://homectl.example.com/path").is_err());
+        assert!(validate_origin("http://homectl.example.com/?query").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_allowed_origins() {
+        let allowed_origins = vec!["http://localhost:8080".to_string(), "localhost".to_string()];
+
+        assert!(mk_cors(&allowed_origins).is_err());
+    }
+
+    async fn cors_request(
+        allowed_origins: &[&str],
+        origin: &str,
+    ) -> warp::http::Response<warp::hyper::body::Bytes> {
+        let allowed_origins: Vec<String> = allowed_origins.iter().map(|s| s.to_string()).collect();
+        let routes = warp::any()
+            .map(warp::reply)
+            .with(mk_cors(&allowed_origins).unwrap());
+
+        warp::test::request()
+            .method("OPTIONS")
+            .header("origin", origin)
+            .header("access-control-request-method", "PUT")
+            .reply(&routes)
+            .await
+    }
+
+    #[tokio::test]
+    async fn allows_configured_origins() {
+        let res = cors_request(&["http://localhost:8080"], "http://localhost:8080").await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "http://localhost:8080"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_origins() {
+        let res = cors_request(&["http://localhost:8080"], "http://evil.example.com").await;
+
+        assert_eq!(res.status(), 403);
+    }
+
+    #[tokio::test]
+    async fn allows_any_origin_with_wildcard() {
+        let res = cors_request(&["*"], "http://evil.example.com").await;
+
+        assert_eq!(res.status(), 200);
+    }
+}
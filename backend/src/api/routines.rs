@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use homectl_types::{api::ApiScope, rule::RoutinesConfig};
+use warp::Filter;
+
+use crate::homectl_core::state::AppState;
+
+use super::{auth::with_scope, with_state};
+
+#[derive(serde::Serialize)]
+pub struct RoutinesResponse {
+    routines: RoutinesConfig,
+}
+
+pub fn routines(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("routines").and(get_routines(app_state))
+}
+
+fn get_routines(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|app_state: Arc<AppState>| {
+            let response = RoutinesResponse {
+                routines: app_state.rules.get_routines(),
+            };
+
+            Ok(warp::reply::json(&response))
+        })
+}
@@ -0,0 +1,173 @@
+use std::{convert::Infallible, sync::Arc};
+
+use homectl_types::{
+    api::ApiScope,
+    event::Message,
+    event_log::{Event, EventOrigin},
+    scene::{SceneConfig, SceneId, ScenesConfig},
+};
+use warp::{http::StatusCode, Filter, Reply};
+
+use crate::{
+    db::actions::{db_delete_scene, db_store_scene},
+    homectl_core::state::AppState,
+};
+
+use super::{auth::with_scope, with_state};
+
+#[derive(serde::Serialize)]
+pub struct ScenesResponse {
+    scenes: ScenesConfig,
+}
+
+pub fn scenes(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("scenes").and(
+        get_scenes(app_state)
+            .or(get_scene(app_state))
+            .or(put_scene(app_state))
+            .or(delete_scene(app_state)),
+    )
+}
+
+fn get_scenes(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path::end()
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|app_state: Arc<AppState>| {
+            let response = ScenesResponse {
+                scenes: app_state.scenes.get_scenes(),
+            };
+
+            Ok(warp::reply::json(&response))
+        })
+}
+
+fn get_scene(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(SceneId)
+        .and(warp::get())
+        .and(with_scope(app_state, ApiScope::Read))
+        .and(with_state(app_state))
+        .map(|scene_id: SceneId, app_state: Arc<AppState>| {
+            match app_state.scenes.find_scene(&scene_id) {
+                Some(scene) => warp::reply::with_status(warp::reply::json(&scene), StatusCode::OK),
+                None => warp::reply::with_status(warp::reply::json(&()), StatusCode::NOT_FOUND),
+            }
+        })
+}
+
+fn put_scene(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(SceneId)
+        .and(warp::put())
+        .and(with_scope(app_state, ApiScope::Admin))
+        .and(warp::body::json())
+        .and(with_state(app_state))
+        .and_then(put_scene_impl)
+}
+
+/// Stores scene in the database, responds with the stored scene
+async fn put_scene_impl(
+    scene_id: SceneId,
+    config: SceneConfig,
+    app_state: Arc<AppState>,
+) -> Result<impl warp::Reply, Infallible> {
+    if app_state.scenes.is_config_scene(&scene_id) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!(
+                "Scene {} is defined in Settings.toml and can't be modified",
+                scene_id
+            )),
+            StatusCode::CONFLICT,
+        ));
+    }
+
+    let status = match app_state.scenes.find_scene(&scene_id) {
+        Some(_) => StatusCode::OK,
+        None => StatusCode::CREATED,
+    };
+
+    if let Err(e) = db_store_scene(&scene_id, &config).await {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!("{:?}", e)),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+
+    app_state.event_log.log(
+        EventOrigin::Api,
+        Event::Message {
+            message: Message::StoreScene {
+                scene_id,
+                config: config.clone(),
+            },
+        },
+    );
+
+    app_state.scenes.refresh_db_scenes().await;
+    app_state.send_state_ws(None).await;
+
+    Ok(warp::reply::with_status(warp::reply::json(&config), status))
+}
+
+fn delete_scene(
+    app_state: &Arc<AppState>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!(SceneId)
+        .and(warp::delete())
+        .and(with_scope(app_state, ApiScope::Admin))
+        .and(with_state(app_state))
+        .and_then(delete_scene_impl)
+}
+
+async fn delete_scene_impl(
+    scene_id: SceneId,
+    app_state: Arc<AppState>,
+) -> Result<warp::reply::Response, Infallible> {
+    if app_state.scenes.is_config_scene(&scene_id) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!(
+                "Scene {} is defined in Settings.toml and can't be deleted",
+                scene_id
+            )),
+            StatusCode::CONFLICT,
+        )
+        .into_response());
+    }
+
+    match db_delete_scene(&scene_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(
+                warp::reply::with_status(warp::reply::json(&()), StatusCode::NOT_FOUND)
+                    .into_response(),
+            )
+        }
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&format!("{:?}", e)),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+            .into_response())
+        }
+    }
+
+    app_state.event_log.log(
+        EventOrigin::Api,
+        Event::Message {
+            message: Message::DeleteScene { scene_id },
+        },
+    );
+
+    app_state.scenes.refresh_db_scenes().await;
+    app_state.send_state_ws(None).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
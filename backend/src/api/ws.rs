@@ -1,8 +1,15 @@
-use super::with_state;
+use super::{
+    auth::{required_message_scope, with_token_scope},
+    with_state,
+};
 use crate::AppState;
 use futures::SinkExt;
 use futures_util::{StreamExt, TryFutureExt};
-use homectl_types::websockets::WebSocketRequest;
+use homectl_types::{
+    api::ApiScope,
+    event_log::{Event, EventOrigin},
+    websockets::WebSocketRequest,
+};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
@@ -20,15 +27,18 @@ pub fn ws(
     warp::path("ws")
         // The `ws()` filter will prepare the Websocket handshake.
         .and(warp::ws())
+        .and(with_token_scope(app_state))
         .and(with_state(app_state))
-        .map(|ws: warp::ws::Ws, app_state: Arc<AppState>| {
-            // This will call our function if the handshake succeeds.
-            ws.on_upgrade(move |socket| user_connected(socket, app_state))
-        })
+        .map(
+            |ws: warp::ws::Ws, scope: ApiScope, app_state: Arc<AppState>| {
+                // This will call our function if the handshake succeeds.
+                ws.on_upgrade(move |socket| user_connected(socket, scope, app_state))
+            },
+        )
 }
 
 // https://github.com/seanmonstar/warp/blob/master/examples/websockets_chat.rs
-async fn user_connected(ws: WebSocket, app_state: Arc<AppState>) {
+async fn user_connected(ws: WebSocket, scope: ApiScope, app_state: Arc<AppState>) {
     // Use a counter to assign a new unique ID for this user.
     let my_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
 
@@ -56,6 +66,7 @@ async fn user_connected(ws: WebSocket, app_state: Arc<AppState>) {
 
     // Send snapshot of current state
     app_state.send_state_ws(Some(my_id)).await;
+    app_state.integrations.send_status_ws(Some(my_id)).await;
 
     // Let AppState handle incoming user messages
     while let Some(result) = user_ws_rx.next().await {
@@ -71,7 +82,20 @@ async fn user_connected(ws: WebSocket, app_state: Arc<AppState>) {
         let msg = json.and_then(|json| serde_json::from_str::<WebSocketRequest>(json).ok());
 
         if let Some(WebSocketRequest::Message(msg)) = msg {
-            app_state.sender.send(msg);
+            if scope >= required_message_scope(&msg) {
+                app_state.event_log.log(
+                    EventOrigin::WebSocket { user_id: my_id },
+                    Event::Message {
+                        message: msg.clone(),
+                    },
+                );
+                app_state.sender.send(msg);
+            } else {
+                eprintln!(
+                    "websocket message rejected due to insufficient scope (uid={})",
+                    my_id
+                );
+            }
         }
     }
 
@@ -1,6 +1,10 @@
 use super::get_db_connection;
 use anyhow::Result;
-use homectl_types::device::{Device, DeviceKey, DeviceRow, DeviceState};
+use chrono::{DateTime, Utc};
+use homectl_types::device::{
+    Device, DeviceHistoryEntry, DeviceHistoryRow, DeviceKey, DeviceRow, DeviceState,
+};
+use homectl_types::event_log::{Event, EventLogEntry, EventLogRow, EventOrigin};
 use homectl_types::integration::IntegrationId;
 use homectl_types::scene::ScenesConfig;
 use homectl_types::scene::{SceneConfig, SceneId};
@@ -69,6 +73,106 @@ pub async fn db_find_device(key: &DeviceKey) -> Result<Device> {
     Ok(device)
 }
 
+pub async fn db_insert_device_history(device: &Device) -> Result<()> {
+    let db = get_db_connection().await?;
+
+    sqlx::query!(
+        r#"
+            insert into device_history (integration_id, device_id, scene_id, state)
+            values ($1, $2, $3, $4)
+        "#,
+        &device.integration_id.to_string(),
+        &device.id.to_string(),
+        device.get_scene_id().map(|id| id.to_string()),
+        Json(device.state.clone()) as _
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn db_get_device_history(
+    key: &DeviceKey,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<DeviceHistoryEntry>> {
+    let db = get_db_connection().await?;
+
+    let rows = sqlx::query_as!(
+        DeviceHistoryRow,
+        r#"
+            select
+                scene_id,
+                state as "state: Json<DeviceState>",
+                recorded_at
+            from device_history
+            where integration_id = $1
+              and device_id = $2
+              and recorded_at >= $3
+              and recorded_at <= $4
+            order by recorded_at
+        "#,
+        &key.integration_id.to_string(),
+        &key.device_id.to_string(),
+        from,
+        to
+    )
+    .fetch_all(db)
+    .await?;
+
+    let entries = rows.into_iter().map(|row| row.into()).collect();
+
+    Ok(entries)
+}
+
+pub async fn db_insert_event_log(entry: &EventLogEntry) -> Result<()> {
+    let db = get_db_connection().await?;
+
+    sqlx::query!(
+        r#"
+            insert into event_log (origin, event, created_at)
+            values ($1, $2, $3)
+        "#,
+        Json(entry.origin.clone()) as _,
+        Json(entry.event.clone()) as _,
+        entry.created_at
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn db_get_event_log(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<EventLogEntry>> {
+    let db = get_db_connection().await?;
+
+    let rows = sqlx::query_as!(
+        EventLogRow,
+        r#"
+            select
+                origin as "origin: Json<EventOrigin>",
+                event as "event: Json<Event>",
+                created_at
+            from event_log
+            where created_at >= $1
+              and created_at <= $2
+            order by created_at
+        "#,
+        from,
+        to
+    )
+    .fetch_all(db)
+    .await?;
+
+    let entries = rows.into_iter().map(|row| row.into()).collect();
+
+    Ok(entries)
+}
+
 pub async fn db_get_neato_last_run(
     integration_id: &IntegrationId,
 ) -> Result<chrono::NaiveDateTime> {
@@ -156,24 +260,25 @@ pub async fn db_store_scene(scene_id: &SceneId, config: &SceneConfig) -> Result<
         scene_id.to_string(),
         Json(config) as _
     )
-    .fetch_one(db)
+    .execute(db)
     .await?;
 
     Ok(())
 }
 
-pub async fn db_delete_scene(scene_id: &SceneId) -> Result<()> {
+/// Returns whether a scene with given id was found and deleted
+pub async fn db_delete_scene(scene_id: &SceneId) -> Result<bool> {
     let db = get_db_connection().await?;
 
-    sqlx::query!(
+    let result = sqlx::query!(
         r#"
             delete from scenes
             where scene_id = $1
         "#,
         scene_id.to_string(),
     )
-    .fetch_one(db)
+    .execute(db)
     .await?;
 
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
@@ -0,0 +1,745 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
+
+use homectl_types::{
+    action::{Action, DeviceTargets},
+    device::DeviceId,
+    group::{GroupId, GroupsConfig},
+    integration::IntegrationId,
+    presence::{PersonId, RoomId, PRESENCE_INTEGRATION_ID},
+    rule::{MotionLighting, Rule},
+    scene::{SceneDescriptor, SceneDeviceConfig, SceneId, ScenesConfig},
+    solar::TimeOfDay,
+};
+
+use serde_path_to_error::Segment;
+
+use super::{
+    config::{Config, OpaqueIntegrationsConfigs},
+    integrations::validate_integration_config,
+};
+
+/// A problem found in the config, along with the TOML path of the offending
+/// value
+#[derive(Debug)]
+pub struct ConfigProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Quotes TOML keys that can't be written as bare keys, e.g. device names
+/// containing spaces
+fn toml_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if is_bare {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+/// Formats a path to a value as reported by serde_path_to_error in the same
+/// way as paths of other problems
+fn toml_path(path: &serde_path_to_error::Path) -> String {
+    let mut out = String::new();
+
+    for segment in path {
+        match segment {
+            Segment::Seq { index } => out.push_str(&format!("[{}]", index)),
+            Segment::Map { key } | Segment::Enum { variant: key } => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(&toml_key(key));
+            }
+            Segment::Unknown => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push('?');
+            }
+        }
+    }
+
+    out
+}
+
+/// Converts an error from reading the config into a problem. Errors from
+/// deserializing the config point at the offending value, other errors (such
+/// as TOML syntax errors) refer to the config file as a whole.
+pub fn read_error_problem(error: &anyhow::Error) -> ConfigProblem {
+    let path_error = error.downcast_ref::<serde_path_to_error::Error<::config::ConfigError>>();
+    let path = path_error
+        .map(|path_error| toml_path(path_error.path()))
+        .unwrap_or_default();
+
+    match path_error {
+        Some(path_error) if !path.is_empty() => ConfigProblem {
+            path,
+            message: path_error.inner().to_string(),
+        },
+        _ => ConfigProblem {
+            path: String::from("Settings.toml"),
+            message: format!("{:#}", error),
+        },
+    }
+}
+
+struct ConfigChecker<'a> {
+    config: &'a Config,
+    groups: GroupsConfig,
+
+    /// Scenes from both the config file and the database
+    scene_ids: HashSet<SceneId>,
+
+    problems: Vec<ConfigProblem>,
+}
+
+/// Checks that all references between integrations, groups, scenes, presence,
+/// manual overrides and routines point to something that exists, and that each
+/// integration accepts its config. Returns every problem found instead of
+/// stopping at the first one.
+///
+/// Device names can't be checked here since devices are discovered by
+/// integrations at runtime.
+pub fn check_config(
+    config: &Config,
+    opaque_integrations_configs: &OpaqueIntegrationsConfigs,
+    db_scenes: &ScenesConfig,
+) -> Vec<ConfigProblem> {
+    let scenes = config.scenes.clone().unwrap_or_default();
+
+    let mut checker = ConfigChecker {
+        config,
+        groups: config.groups.clone().unwrap_or_default(),
+        scene_ids: scenes.keys().chain(db_scenes.keys()).cloned().collect(),
+        problems: vec![],
+    };
+
+    checker.check_integrations(opaque_integrations_configs);
+    checker.check_groups();
+    checker.check_scenes(&scenes);
+    checker.check_presence();
+    checker.check_manual_override();
+    checker.check_routines();
+
+    checker
+        .problems
+        .sort_by(|a, b| a.path.cmp(&b.path).then(a.message.cmp(&b.message)));
+
+    checker.problems
+}
+
+impl<'a> ConfigChecker<'a> {
+    fn report(&mut self, path: &str, message: String) {
+        self.problems.push(ConfigProblem {
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn check_integrations(&mut self, opaque_integrations_configs: &OpaqueIntegrationsConfigs) {
+        let config = self.config;
+        let integrations = config.integrations.iter().flatten();
+
+        for (integration_id, integration_config) in integrations {
+            let path = format!("integrations.{}", toml_key(&integration_id.to_string()));
+
+            let result = opaque_integrations_configs
+                .get(integration_id)
+                .map(|opaque_config| {
+                    validate_integration_config(
+                        &integration_config.plugin,
+                        integration_id,
+                        opaque_config,
+                    )
+                });
+
+            match result {
+                Some(Ok(())) => {}
+                Some(Err(e)) => self.report(&path, format!("{:#}", e)),
+                None => self.report(&path, "missing integration config".to_string()),
+            }
+        }
+    }
+
+    fn check_integration_exists(&mut self, path: &str, integration_id: &IntegrationId) {
+        // Virtual devices of the presence subsystem may be referenced directly
+        let is_presence = integration_id.to_string() == PRESENCE_INTEGRATION_ID;
+
+        let exists = self
+            .config
+            .integrations
+            .as_ref()
+            .map(|integrations| integrations.contains_key(integration_id))
+            .unwrap_or(false);
+
+        let exists = exists || is_presence;
+
+        if !exists {
+            self.report(path, format!("unknown integration {}", integration_id));
+        }
+    }
+
+    fn check_device_link(
+        &mut self,
+        path: &str,
+        integration_id: &IntegrationId,
+        device_id: &Option<DeviceId>,
+        name: &Option<String>,
+    ) {
+        self.check_integration_exists(path, integration_id);
+
+        if device_id.is_none() && name.is_none() {
+            self.report(path, "either device_id or name must be given".to_string());
+        }
+    }
+
+    fn check_group_exists(&mut self, path: &str, group_id: &GroupId) {
+        if !self.groups.contains_key(group_id) {
+            self.report(path, format!("unknown group {}", group_id));
+        }
+    }
+
+    fn check_person_exists(&mut self, path: &str, person_id: &PersonId) {
+        let exists = self
+            .config
+            .presence
+            .as_ref()
+            .and_then(|presence| presence.people.as_ref())
+            .map(|people| people.contains_key(person_id))
+            .unwrap_or(false);
+
+        if !exists {
+            self.report(path, format!("unknown person {}", person_id));
+        }
+    }
+
+    fn check_room_exists(&mut self, path: &str, room_id: &RoomId) {
+        let exists = self
+            .config
+            .presence
+            .as_ref()
+            .and_then(|presence| presence.rooms.as_ref())
+            .map(|rooms| rooms.contains_key(room_id))
+            .unwrap_or(false);
+
+        if !exists {
+            self.report(path, format!("unknown room {}", room_id));
+        }
+    }
+
+    fn check_presence(&mut self) {
+        let config = self.config;
+        let presence = match &config.presence {
+            Some(presence) => presence,
+            None => return,
+        };
+
+        let people = presence.people.iter().flatten().map(|(person_id, person)| {
+            (
+                format!("presence.people.{}", toml_key(&person_id.to_string())),
+                &person.devices,
+            )
+        });
+
+        let rooms = presence.rooms.iter().flatten().map(|(room_id, room)| {
+            (
+                format!("presence.rooms.{}", toml_key(&room_id.to_string())),
+                &room.devices,
+            )
+        });
+
+        for (path, devices) in people.chain(rooms) {
+            for (i, link) in devices.iter().enumerate() {
+                self.check_device_link(
+                    &format!("{}.devices[{}]", path, i),
+                    &link.integration_id,
+                    &link.device_id,
+                    &link.name,
+                );
+            }
+        }
+    }
+
+    fn check_manual_override(&mut self) {
+        let config = self.config;
+        let manual_override = match &config.manual_override {
+            Some(manual_override) => manual_override,
+            None => return,
+        };
+
+        for group_id in manual_override.groups.iter().flatten().map(|(id, _)| id) {
+            let path = format!("manual_override.groups.{}", toml_key(&group_id.to_string()));
+            self.check_group_exists(&path, group_id);
+        }
+
+        for (i, policy) in manual_override.devices.iter().flatten().enumerate() {
+            self.check_device_link(
+                &format!("manual_override.devices[{}]", i),
+                &policy.device.integration_id,
+                &policy.device.device_id,
+                &policy.device.name,
+            );
+        }
+    }
+
+    fn check_scene_exists(&mut self, path: &str, scene_id: &SceneId) {
+        if !self.scene_ids.contains(scene_id) {
+            self.report(path, format!("unknown scene {}", scene_id));
+        }
+    }
+
+    fn check_groups(&mut self) {
+        let groups = self.groups.clone();
+
+        for (group_id, group) in &groups {
+            let path = format!("groups.{}", toml_key(&group_id.to_string()));
+
+            for (i, link) in group.devices.iter().flatten().enumerate() {
+                self.check_device_link(
+                    &format!("{}.devices[{}]", path, i),
+                    &link.integration_id,
+                    &link.device_id,
+                    &link.name,
+                );
+            }
+
+            for (i, link) in group.groups.iter().flatten().enumerate() {
+                self.check_group_exists(&format!("{}.groups[{}]", path, i), &link.group_id);
+            }
+
+            if is_group_in_cycle(&groups, group_id, group_id, &mut HashSet::new()) {
+                self.report(
+                    &format!("{}.groups", path),
+                    "group links form a cycle".to_string(),
+                );
+            }
+        }
+    }
+
+    fn check_scenes(&mut self, scenes: &ScenesConfig) {
+        for (scene_id, scene) in scenes {
+            let path = format!("scenes.{}", toml_key(&scene_id.to_string()));
+
+            for (integration_id, devices) in scene.devices.iter().flatten() {
+                let integration_path =
+                    format!("{}.devices.{}", path, toml_key(&integration_id.to_string()));
+
+                self.check_integration_exists(&integration_path, integration_id);
+
+                for (device_name, device_config) in devices {
+                    let device_path = format!("{}.{}", integration_path, toml_key(device_name));
+                    self.check_scene_device_config(&device_path, device_config);
+                }
+            }
+
+            for (group_id, device_config) in scene.groups.iter().flatten() {
+                let group_path = format!("{}.groups.{}", path, toml_key(&group_id.to_string()));
+
+                self.check_group_exists(&group_path, group_id);
+                self.check_scene_device_config(&group_path, device_config);
+            }
+        }
+    }
+
+    fn check_scene_device_config(&mut self, path: &str, device_config: &SceneDeviceConfig) {
+        match device_config {
+            SceneDeviceConfig::SceneDeviceLink(link) => {
+                self.check_device_link(path, &link.integration_id, &link.device_id, &link.name)
+            }
+            SceneDeviceConfig::SceneLink(descriptor) => {
+                self.check_scene_descriptor(path, descriptor)
+            }
+            SceneDeviceConfig::SceneDeviceState(_) => {}
+        }
+    }
+
+    fn check_scene_descriptor(&mut self, path: &str, descriptor: &SceneDescriptor) {
+        self.check_scene_exists(path, &descriptor.scene_id);
+    }
+
+    fn check_routines(&mut self) {
+        let config = self.config;
+        let routines = config.routines.iter().flatten();
+
+        for (routine_id, routine) in routines {
+            let path = format!("routines.{}", toml_key(&routine_id.to_string()));
+
+            for (i, rule) in routine.rules.iter().enumerate() {
+                self.check_rule(&format!("{}.rules[{}]", path, i), rule);
+            }
+
+            for (i, action) in routine.actions.iter().enumerate() {
+                self.check_action(&format!("{}.actions[{}]", path, i), action);
+            }
+
+            if let Some(motion_lighting) = &routine.motion_lighting {
+                self.check_motion_lighting(&path, motion_lighting);
+
+                if !routine.actions.is_empty() {
+                    self.report(
+                        &path,
+                        "actions are ignored for motion lighting routines".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_motion_lighting(&mut self, path: &str, motion_lighting: &MotionLighting) {
+        let path = format!("{}.motion_lighting", path);
+
+        if motion_lighting.sensors.is_empty() {
+            self.report(&path, "at least one sensor must be given".to_string());
+        }
+
+        for (i, link) in motion_lighting.sensors.iter().enumerate() {
+            self.check_device_link(
+                &format!("{}.sensors[{}]", path, i),
+                &link.integration_id,
+                &link.device_id,
+                &link.name,
+            );
+        }
+
+        self.check_group_exists(&format!("{}.group_id", path), &motion_lighting.group_id);
+        self.check_scene_exists(&format!("{}.on_scene", path), &motion_lighting.on_scene);
+        self.check_scene_exists(&format!("{}.off_scene", path), &motion_lighting.off_scene);
+    }
+
+    fn check_rule(&mut self, path: &str, rule: &Rule) {
+        match rule {
+            Rule::Sensor(rule) => {
+                self.check_device_link(path, &rule.integration_id, &rule.device_id, &rule.name)
+            }
+            Rule::Device(rule) => {
+                self.check_device_link(path, &rule.integration_id, &rule.device_id, &rule.name);
+
+                if let Some(scene_id) = &rule.scene {
+                    self.check_scene_exists(path, scene_id);
+                }
+            }
+            Rule::Group(rule) => {
+                self.check_group_exists(path, &rule.group_id);
+
+                if let Some(scene_id) = &rule.scene {
+                    self.check_scene_exists(path, scene_id);
+                }
+            }
+            Rule::SomeoneHome(_) => {}
+            Rule::Person(rule) => self.check_person_exists(path, &rule.person),
+            Rule::Room(rule) => self.check_room_exists(path, &rule.room),
+            Rule::Schedule(rule) => self.check_time_of_day(path, &rule.time),
+            Rule::TimeWindow(rule) => {
+                for time in rule.after.iter().chain(rule.before.iter()) {
+                    self.check_time_of_day(path, time);
+                }
+            }
+            Rule::Any(rule) => {
+                for (i, rule) in rule.any.iter().enumerate() {
+                    self.check_rule(&format!("{}.any[{}]", path, i), rule);
+                }
+            }
+            Rule::All(rule) => {
+                for (i, rule) in rule.all.iter().enumerate() {
+                    self.check_rule(&format!("{}.all[{}]", path, i), rule);
+                }
+            }
+            Rule::Not(rule) => self.check_rule(&format!("{}.not", path), &rule.not),
+        }
+    }
+
+    fn check_time_of_day(&mut self, path: &str, time: &TimeOfDay) {
+        let is_solar = matches!(time, TimeOfDay::Solar { .. });
+
+        if is_solar && self.config.location.is_none() {
+            self.report(
+                path,
+                format!("{} requires a top-level location to be set", time),
+            );
+        }
+    }
+
+    fn check_action(&mut self, path: &str, action: &Action) {
+        match action {
+            Action::ActivateScene(descriptor) => self.check_scene_descriptor(path, descriptor),
+            Action::CycleScenes(descriptor) => {
+                for (i, descriptor) in descriptor.scenes.iter().enumerate() {
+                    self.check_scene_descriptor(&format!("{}.scenes[{}]", path, i), descriptor);
+                }
+            }
+            Action::IntegrationAction(descriptor) => {
+                self.check_integration_exists(path, &descriptor.integration_id)
+            }
+            Action::AdjustBrightness(descriptor) => {
+                self.check_device_targets(path, &descriptor.targets)
+            }
+            Action::SetPower(descriptor) => {
+                if descriptor.power.is_none() && !descriptor.toggle {
+                    self.report(path, "either power or toggle must be given".to_string());
+                }
+
+                self.check_device_targets(path, &descriptor.targets)
+            }
+            Action::AdjustColorTemperature(descriptor) => {
+                self.check_device_targets(path, &descriptor.targets)
+            }
+        }
+    }
+
+    fn check_device_targets(&mut self, path: &str, targets: &DeviceTargets) {
+        if targets.devices.is_none() && targets.groups.is_none() {
+            self.report(path, "either devices or groups must be given".to_string());
+        }
+
+        for (i, link) in targets.devices.iter().flatten().enumerate() {
+            self.check_device_link(
+                &format!("{}.devices[{}]", path, i),
+                &link.integration_id,
+                &link.device_id,
+                &link.name,
+            );
+        }
+
+        for (i, group_id) in targets.groups.iter().flatten().enumerate() {
+            self.check_group_exists(&format!("{}.groups[{}]", path, i), group_id);
+        }
+    }
+}
+
+/// Returns true if `start` can be reached by following group links from
+/// `current`
+fn is_group_in_cycle(
+    groups: &GroupsConfig,
+    start: &GroupId,
+    current: &GroupId,
+    visited: &mut HashSet<GroupId>,
+) -> bool {
+    let links = groups.get(current).and_then(|group| group.groups.as_ref());
+
+    for link in links.into_iter().flatten() {
+        if &link.group_id == start {
+            return true;
+        }
+
+        if visited.insert(link.group_id.clone())
+            && is_group_in_cycle(groups, start, &link.group_id, visited)
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    fn read(toml: &str) -> Result<(Config, OpaqueIntegrationsConfigs)> {
+        let mut settings = ::config::Config::default();
+        settings.merge(::config::File::from_str(toml, ::config::FileFormat::Toml))?;
+
+        let config: Config = serde_path_to_error::deserialize(settings.clone())?;
+        let opaque_integrations_configs = settings.get("integrations").unwrap_or_default();
+
+        Ok((config, opaque_integrations_configs))
+    }
+
+    fn check(toml: &str) -> Vec<String> {
+        let (config, opaque_integrations_configs) = read(toml).unwrap();
+
+        check_config(&config, &opaque_integrations_configs, &Default::default())
+            .iter()
+            .map(|problem| problem.to_string())
+            .collect()
+    }
+
+    const INTEGRATIONS: &str = r#"
+        [integrations.dummy]
+        plugin = "dummy"
+        devices = {}
+    "#;
+
+    #[test]
+    fn accepts_valid_config() {
+        let toml = format!(
+            r#"
+            {}
+            [groups.hallway]
+            name = "Hallway"
+            devices = [ {{ integration_id = "dummy", name = "Lamp" }} ]
+
+            [scenes.bright]
+            name = "Bright"
+            groups = {{ hallway = {{ power = true }} }}
+
+            [routines.motion]
+            name = "Motion"
+            rules = [ {{ integration_id = "dummy", name = "Sensor", state = {{ value = true }} }} ]
+            actions = [ {{ action = "ActivateScene", scene_id = "bright" }} ]
+            "#,
+            INTEGRATIONS
+        );
+
+        assert_eq!(check(&toml), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_all_unknown_references() {
+        let toml = format!(
+            r#"
+            {}
+            [groups.hallway]
+            name = "Hallway"
+            devices = [
+              {{ integration_id = "hue", name = "Lamp" }},
+              {{ integration_id = "dummy" }},
+            ]
+            groups = [ {{ group_id = "upstairs" }} ]
+
+            [routines.motion]
+            name = "Motion"
+            rules = [ {{ person = "alice", home = true }} ]
+            actions = [ {{ action = "ActivateScene", scene_id = "bright" }} ]
+            "#,
+            INTEGRATIONS
+        );
+
+        assert_eq!(
+            check(&toml),
+            vec![
+                "groups.hallway.devices[0]: unknown integration hue",
+                "groups.hallway.devices[1]: either device_id or name must be given",
+                "groups.hallway.groups[0]: unknown group upstairs",
+                "routines.motion.actions[0]: unknown scene bright",
+                "routines.motion.rules[0]: unknown person alice",
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_keys_in_paths() {
+        let toml = r#"
+            [groups."living room"]
+            name = "Living room"
+            devices = [ { integration_id = "hue", name = "Lamp" } ]
+        "#;
+
+        assert_eq!(
+            check(toml),
+            vec![r#"groups."living room".devices[0]: unknown integration hue"#]
+        );
+    }
+
+    #[test]
+    fn reports_group_cycles() {
+        let toml = r#"
+            [groups.a]
+            name = "A"
+            groups = [ { group_id = "b" } ]
+
+            [groups.b]
+            name = "B"
+            groups = [ { group_id = "a" } ]
+        "#;
+
+        assert_eq!(
+            check(toml),
+            vec![
+                "groups.a.groups: group links form a cycle",
+                "groups.b.groups: group links form a cycle",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_integration_configs() {
+        let toml = r#"
+            [integrations.dummy]
+            plugin = "dummy"
+
+            [integrations.other]
+            plugin = "unknown"
+        "#;
+
+        let problems = check(toml);
+
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("integrations.dummy: "));
+        assert!(problems[1].starts_with("integrations.other: Unknown module name"));
+    }
+
+    #[test]
+    fn reports_solar_times_without_location() {
+        let toml = r#"
+            [routines.evening]
+            name = "Evening"
+            rules = [ { time = "sunset - 30min" } ]
+            actions = []
+        "#;
+
+        assert_eq!(
+            check(toml),
+            vec![
+                "routines.evening.rules[0]: Sunset - 30min requires a top-level location to be set"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_scenes_from_db() {
+        let toml = r#"
+            [routines.motion]
+            name = "Motion"
+            rules = []
+            actions = [ { action = "ActivateScene", scene_id = "stored" } ]
+        "#;
+
+        let (config, opaque_integrations_configs) = read(toml).unwrap();
+        let db_scenes: ScenesConfig = serde_json::from_value(serde_json::json!({
+            "stored": { "name": "Stored" }
+        }))
+        .unwrap();
+
+        let problems = check_config(&config, &opaque_integrations_configs, &db_scenes);
+
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn reports_path_of_deserialization_errors() {
+        let toml = r#"
+            [scenes.evening]
+            name = "Evening"
+            transition = { duration_ms = "soon" }
+        "#;
+
+        let error = read(toml).err().unwrap();
+        let problem = read_error_problem(&error);
+
+        assert_eq!(problem.path, "scenes.evening.transition.duration_ms");
+    }
+
+    #[test]
+    fn reports_other_read_errors_for_whole_file() {
+        let error = read("[groups").err().unwrap();
+        let problem = read_error_problem(&error);
+
+        assert_eq!(problem.path, "Settings.toml");
+    }
+}
@@ -1,13 +1,28 @@
 extern crate config;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use homectl_types::{
+    api::{ApiConfig, ServerConfig},
     group::GroupsConfig,
     integration::{IntegrationId, IntegrationsConfig},
+    manual_override::ManualOverrideConfig,
+    presence::PresenceConfig,
     rule::RoutinesConfig,
     scene::ScenesConfig,
+    solar::Location,
 };
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::time;
+
+use super::state::AppState;
+
+/// How often the config file is checked for modifications
+static CONFIG_POLL_RATE: u64 = 2000;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
@@ -15,9 +30,14 @@ pub struct Config {
     pub scenes: Option<ScenesConfig>,
     pub groups: Option<GroupsConfig>,
     pub routines: Option<RoutinesConfig>,
+    pub presence: Option<PresenceConfig>,
+    pub manual_override: Option<ManualOverrideConfig>,
+    pub location: Option<Location>,
+    pub api: Option<ApiConfig>,
+    pub server: Option<ServerConfig>,
 }
 
-type OpaqueIntegrationsConfigs = HashMap<IntegrationId, config::Value>;
+pub type OpaqueIntegrationsConfigs = HashMap<IntegrationId, config::Value>;
 
 pub fn read_config() -> Result<(Config, OpaqueIntegrationsConfigs)> {
     let mut settings = config::Config::default();
@@ -25,7 +45,7 @@ pub fn read_config() -> Result<(Config, OpaqueIntegrationsConfigs)> {
     let root = std::env::current_dir().unwrap();
     let sample_path = root.join("Settings.toml.example");
 
-    let path = root.join("Settings.toml");
+    let path = config_path();
 
     if !path.exists() && std::env::var("SKIP_SAMPLE_CONFIG").is_err() {
         println!("Settings.toml not found, generating sample configuration.");
@@ -41,9 +61,67 @@ pub fn read_config() -> Result<(Config, OpaqueIntegrationsConfigs)> {
         "Failed to deserialize config, compare your config file to Settings.toml.example!",
     )?;
 
+    // Integrations share the top-level location, e.g. for circadian fades
+    // relative to solar events
+    for (integration_id, _) in config.integrations.iter().flatten() {
+        let key = format!("integrations.{}.location", integration_id);
+
+        if settings.get::<config::Value>(&key).is_ok() {
+            return Err(anyhow!(
+                "Location must be set at the top of Settings.toml, not in {}",
+                key
+            ));
+        }
+
+        if let Some(location) = config.location {
+            settings.set(&format!("{}.latitude", key), location.latitude)?;
+            settings.set(&format!("{}.longitude", key), location.longitude)?;
+        }
+    }
+
     let integrations_config = settings
         .get::<OpaqueIntegrationsConfigs>("integrations")
         .context("Expected to find integrations key in config")?;
 
     Ok((config, integrations_config))
 }
+
+fn config_path() -> PathBuf {
+    let root = std::env::current_dir().unwrap();
+    root.join("Settings.toml")
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Watches Settings.toml for modifications and reloads the config whenever
+/// the file has changed.
+pub fn watch_config(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let path = config_path();
+        let poll_rate = Duration::from_millis(CONFIG_POLL_RATE);
+        let mut interval = time::interval(poll_rate);
+        let mut prev_modified = get_modified_time(&path);
+
+        loop {
+            interval.tick().await;
+
+            let modified = get_modified_time(&path);
+
+            if modified == prev_modified {
+                continue;
+            }
+
+            prev_modified = modified;
+
+            println!("Settings.toml was modified, reloading config");
+
+            if let Err(e) = state.reload_config().await {
+                println!("Failed to reload config: {:?}", e);
+            }
+        }
+    });
+}
@@ -1,22 +1,53 @@
-use crate::db::actions::{db_find_device, db_update_device};
+use crate::db::actions::{db_find_device, db_insert_device_history, db_update_device};
 
-use super::scenes::Scenes;
+use super::{
+    groups::Groups,
+    metrics::DEVICE_STATE_CORRECTIONS,
+    scenes::Scenes,
+    transitions::{interpolate_state, TRANSITION_STEP_MS},
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use homectl_types::device::DeviceId;
 use homectl_types::{
-    device::{Device, DeviceColor, DeviceKey, DeviceSceneState, DeviceState, DevicesState},
+    device::{
+        CorrelatedColorTemperature, Device, DeviceAvailability, DeviceColor, DeviceKey,
+        DeviceSceneState, DeviceState, DevicesState, Light, ManualOverride, MultiSourceLight,
+    },
     event::{Message, TxEventChannel},
+    group::GroupDeviceLink,
     integration::IntegrationId,
-    scene::{SceneDescriptor, SceneDevicesConfig, SceneId},
+    manual_override::{ManualOverrideConfig, OverridePolicy},
+    scene::{SceneDescriptor, SceneDevicesConfig, SceneId, SceneTransition},
 };
-use palette::Hsv;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// How often expired manual overrides are checked
+static MANUAL_OVERRIDE_POLL_RATE: u64 = 1000;
+
+/// Devices may still report their previous state for a while after their state
+/// was set, e.g. due to polling or transitions. Mismatches during this period
+/// are never considered manual overrides.
+static MANUAL_OVERRIDE_GRACE_PERIOD_MS: u64 = 5000;
 
 #[derive(Clone)]
 pub struct Devices {
     sender: TxEventChannel,
     state: Arc<Mutex<DevicesState>>,
     scenes: Scenes,
+    groups: Groups,
+    override_config: Arc<RwLock<ManualOverrideConfig>>,
+
+    /// When the state of each device was last sent to its integration
+    state_set_at: Arc<Mutex<HashMap<DeviceKey, Instant>>>,
+
+    /// Devices that are currently being faded by homectl, along with when
+    /// each fade was started
+    fades: Arc<Mutex<HashMap<DeviceKey, Instant>>>,
 }
 
 fn cmp_light_color(
@@ -25,9 +56,9 @@ fn cmp_light_color(
     b: &Option<DeviceColor>,
     b_bri: &Option<f32>,
 ) -> bool {
-    let hue_delta = 1.0;
-    let sat_delta = 0.01;
-    let val_delta = 0.01;
+    // Roughly a couple of just noticeable differences in CIE 1976 u'v'
+    let uv_delta = 0.01;
+    let bri_delta = 0.01;
     let cct_delta = 10.0;
 
     match (a, b) {
@@ -35,16 +66,13 @@ fn cmp_light_color(
         (None, Some(_)) => false,
         (Some(_), None) => false,
         (Some(DeviceColor::Color(a)), Some(DeviceColor::Color(b))) => {
-            let mut a_hsv: Hsv = *a;
-            let mut b_hsv: Hsv = *b;
-            a_hsv.value *= a_bri.unwrap_or(1.0);
-            b_hsv.value *= b_bri.unwrap_or(1.0);
-
-            // Light state is equal if all components differ by less than a given delta
-            (f32::abs(a_hsv.hue.to_positive_degrees() - b_hsv.hue.to_positive_degrees())
-                <= hue_delta)
-                && (f32::abs(a_hsv.saturation - b_hsv.saturation) <= sat_delta)
-                && (f32::abs(a_hsv.value - b_hsv.value) <= val_delta)
+            let a_bri = a.brightness * a_bri.unwrap_or(1.0);
+            let b_bri = b.brightness * b_bri.unwrap_or(1.0);
+
+            // Light state is equal if colors look the same. Expected colors
+            // have been clamped to the device's gamut, so colors reported
+            // back by the device should match closely.
+            a.distance(b) <= uv_delta && f32::abs(a_bri - b_bri) <= bri_delta
         }
         (Some(DeviceColor::Cct(a)), Some(DeviceColor::Cct(b))) => {
             let supported_range = a.get_device_range();
@@ -59,6 +87,11 @@ fn cmp_light_color(
     }
 }
 
+/// Whether both states are of the same kind, e.g. both are lights
+fn is_same_kind(a: &DeviceState, b: &DeviceState) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
 fn cmp_device_states(device: &DeviceState, expected: &DeviceState) -> bool {
     match (device, expected) {
         (DeviceState::OnOffDevice(a), DeviceState::OnOffDevice(b)) => a.power == b.power,
@@ -88,6 +121,16 @@ fn cmp_device_states(device: &DeviceState, expected: &DeviceState) -> bool {
             if a.power != b.power {
                 return false;
             }
+
+            // If both lights are turned off, state matches
+            if !a.power && !b.power {
+                return true;
+            }
+
+            if a.lights.len() != b.lights.len() {
+                return false;
+            }
+
             a.lights
                 .iter()
                 .zip(b.lights.iter())
@@ -106,14 +149,214 @@ fn cmp_device_states(device: &DeviceState, expected: &DeviceState) -> bool {
 }
 
 impl Devices {
-    pub fn new(sender: TxEventChannel, scenes: Scenes) -> Self {
+    pub fn new(
+        sender: TxEventChannel,
+        scenes: Scenes,
+        groups: Groups,
+        override_config: ManualOverrideConfig,
+    ) -> Self {
         Devices {
             sender,
             state: Default::default(),
             scenes,
+            groups,
+            override_config: Arc::new(RwLock::new(override_config)),
+            state_set_at: Default::default(),
+            fades: Default::default(),
+        }
+    }
+
+    /// Replaces manual override config, e.g. after the config file has been
+    /// reloaded
+    pub fn set_override_config(&self, config: ManualOverrideConfig) {
+        *self.override_config.write().unwrap() = config;
+    }
+
+    /// Periodically returns devices with expired manual overrides to the
+    /// scene they were in before being overridden
+    pub fn start_manual_override_timeouts(&self) {
+        let mut devices = self.clone();
+
+        tokio::spawn(async move {
+            let poll_rate = Duration::from_millis(MANUAL_OVERRIDE_POLL_RATE);
+            let mut interval = time::interval(poll_rate);
+
+            loop {
+                interval.tick().await;
+
+                devices.expire_manual_overrides(Utc::now()).await;
+            }
+        });
+    }
+
+    async fn expire_manual_overrides(&mut self, now: DateTime<Utc>) {
+        let expired: Vec<Device> = self
+            .get_devices()
+            .0
+            .into_values()
+            .filter(|device| {
+                let expires = device.manual_override.as_ref().and_then(|o| o.expires);
+
+                match expires {
+                    Some(expires) => expires <= now,
+                    None => false,
+                }
+            })
+            .collect();
+
+        for mut device in expired {
+            let scene_id = device.manual_override.take().and_then(|o| o.scene_id);
+
+            println!(
+                "Manual override of {}/{} expired",
+                device.integration_id, device.id
+            );
+
+            match scene_id {
+                Some(scene_id) => {
+                    device.scene = Some(DeviceSceneState::new(scene_id));
+                    self.set_device_state(&device, true, false, false).await;
+                }
+                None => {
+                    // Nothing to return to, keep the current state
+                    self.set_device_state(&device, true, false, true).await;
+                }
+            }
+        }
+    }
+
+    /// Returns the override policy of given device, or None if the device is
+    /// not allowed to be manually overridden
+    fn find_override_policy(&self, device: &Device) -> Option<OverridePolicy> {
+        let config = self.override_config.read().unwrap();
+
+        let device_policy = config
+            .devices
+            .iter()
+            .flatten()
+            .find(|policy| is_linked_device(&policy.device, device))
+            .map(|policy| policy.policy.clone());
+
+        device_policy.or_else(|| {
+            config
+                .groups
+                .iter()
+                .flatten()
+                .find(|(group_id, _)| {
+                    self.groups
+                        .find_group_device_links(group_id)
+                        .iter()
+                        .any(|link| is_linked_device(link, device))
+                })
+                .map(|(_, policy)| policy.clone())
+        })
+    }
+
+    /// Whether the state of given device was recently sent to its integration
+    fn is_in_grace_period(&self, device: &Device) -> bool {
+        let grace_period = Duration::from_millis(MANUAL_OVERRIDE_GRACE_PERIOD_MS);
+
+        let state_set_at = self.state_set_at.lock().unwrap();
+
+        match state_set_at.get(&device.get_device_key()) {
+            Some(set_at) => set_at.elapsed() < grace_period,
+            None => false,
         }
     }
 
+    fn is_fading(&self, device: &Device) -> bool {
+        self.fades
+            .lock()
+            .unwrap()
+            .contains_key(&device.get_device_key())
+    }
+
+    /// Fades a device from `from` to its current state by sending intermediate
+    /// states to its integration, for devices that don't support transitions
+    /// natively. The fade is cancelled if the device's state is set again.
+    fn start_fade(&self, device: Device, from: DeviceState, transition: SceneTransition) {
+        let device_key = device.get_device_key();
+        let started_at = Instant::now();
+        self.fades
+            .lock()
+            .unwrap()
+            .insert(device_key.clone(), started_at);
+
+        let devices = self.clone();
+
+        tokio::spawn(async move {
+            let step = Duration::from_millis(TRANSITION_STEP_MS);
+            let steps = (transition.duration_ms / TRANSITION_STEP_MS).max(1);
+            let mut interval = time::interval(step);
+
+            for i in 1..=steps {
+                interval.tick().await;
+
+                if devices.fades.lock().unwrap().get(&device_key) != Some(&started_at) {
+                    // Another state has been set in the meantime
+                    return;
+                }
+
+                let t = i as f32 / steps as f32;
+                let state = interpolate_state(&from, &device.state, t, transition.easing);
+
+                let mut device = device.clone();
+                device.state = state.for_capabilities(device.capabilities.as_ref());
+
+                devices.sender.send(Message::SetIntegrationDeviceState {
+                    device,
+                    state_changed: true,
+                });
+            }
+
+            let mut fades = devices.fades.lock().unwrap();
+            if fades.get(&device_key) == Some(&started_at) {
+                fades.remove(&device_key);
+
+                // Give the device some time to report its final state
+                devices
+                    .state_set_at
+                    .lock()
+                    .unwrap()
+                    .insert(device_key, Instant::now());
+            }
+        });
+    }
+
+    /// Accepts a state change made outside of homectl. The device is detached
+    /// from its scene until the override expires or another scene is
+    /// activated.
+    async fn override_device(
+        &mut self,
+        device: &Device,
+        state_device: &Device,
+        policy: OverridePolicy,
+        now: DateTime<Utc>,
+    ) {
+        // Repeated overrides keep the scene the device was originally in
+        let scene_id = match &state_device.manual_override {
+            Some(manual_override) => manual_override.scene_id.clone(),
+            None => state_device.get_scene_id().cloned(),
+        };
+
+        println!(
+            "Manual override detected ({}/{}): {:?}",
+            device.integration_id, device.id, device.state
+        );
+
+        let mut device = device.clone();
+        device.scene = None;
+        device.manual_override = Some(ManualOverride {
+            scene_id,
+            override_time: now,
+            expires: policy
+                .timeout_ms
+                .map(|timeout_ms| now + chrono::Duration::milliseconds(timeout_ms as i64)),
+        });
+
+        self.set_device_state(&device, true, false, true).await;
+    }
+
     pub fn get_devices(&self) -> DevicesState {
         self.state.lock().unwrap().clone()
     }
@@ -123,6 +366,28 @@ impl Devices {
         // println!("handle_integration_device_refresh {:?}", device);
         let state_device = self.get_device(&device.get_device_key());
         println!("{:?}", device);
+
+        let mut device = device.clone();
+        device.availability.last_seen = if device.availability.online {
+            Some(Utc::now())
+        } else {
+            state_device
+                .as_ref()
+                .and_then(|state_device| state_device.availability.last_seen)
+        };
+        // Integrations don't know about manual overrides
+        device.manual_override = state_device
+            .as_ref()
+            .and_then(|state_device| state_device.manual_override.clone());
+        let device = &device;
+
+        let state_device = match state_device {
+            Some(state_device) => Some(
+                self.update_availability(state_device, &device.availability)
+                    .await,
+            ),
+            None => None,
+        };
         // recompute expected_state here as it may have changed since we last
         // computed it
         let expected_state = state_device
@@ -136,9 +401,14 @@ impl Devices {
         }
         let kind = device.state.clone();
 
-        match (kind, state_device, expected_state) {
+        let override_policy = state_device
+            .as_ref()
+            .filter(|state_device| !self.is_in_grace_period(state_device))
+            .and_then(|state_device| self.find_override_policy(state_device));
+
+        match (kind, state_device, expected_state, override_policy) {
             // Device was seen for the first time
-            (_, None, _) => {
+            (_, None, _, _) => {
                 let db_device = db_find_device(&device.get_device_key()).await.ok();
 
                 match db_device {
@@ -147,14 +417,29 @@ impl Devices {
                         // discovered by the integration. This way we don't end up with a lot
                         // of possibly old/stale devices.
                         println!("Restored device from DB: {:?}", device);
+
+                        // Stored state no longer applies if the device now
+                        // reports a different kind of state. Sensors should
+                        // always use the most recent sensor reading.
+                        let is_sensor = matches!(device.state, DeviceState::Sensor(_));
+                        let state = if is_same_kind(&device.state, &db_device.state) && !is_sensor {
+                            db_device.state.clone()
+                        } else {
+                            device.state.clone()
+                        };
+
                         let device = Device {
                             // Don't restore name from DB as this prevents us from changing it
                             name: device.name.clone(),
                             capabilities: device.capabilities.clone(),
+                            availability: device.availability.clone(),
+                            state,
                             ..db_device
                         };
 
-                        self.set_device_state(&device, true, true, false).await;
+                        // Sensors can't be set, so there's no need to
+                        // involve the integration
+                        self.set_device_state(&device, true, true, is_sensor).await;
                     }
                     None => {
                         println!("Discovered device: {:?}", device);
@@ -166,15 +451,45 @@ impl Devices {
 
             // Sensor state has changed, defer handling of this update
             // to other subsystems
-            (DeviceState::Sensor(_), Some(_), _) => {
+            (DeviceState::Sensor(_), Some(_), _, _) => {
+                self.set_device_state(device, false, false, true).await;
+            }
+
+            // Device now reports a different kind of state, e.g. a light
+            // turned out to have multiple light sources. Stored state no
+            // longer applies.
+            (_, Some(state_device), _, _) if !is_same_kind(&device.state, &state_device.state) => {
                 self.set_device_state(device, false, false, true).await;
             }
 
+            // Device is being faded to its expected state by homectl, so
+            // intermediate states are expected. Keep everything else the
+            // integration reported.
+            (_, Some(state_device), Some(_), _) if self.is_fading(&state_device) => {
+                let device = Device {
+                    scene: state_device.scene,
+                    state: state_device.state,
+                    ..device.clone()
+                };
+
+                let mut states = self.state.lock().unwrap();
+                states.0.insert(device.get_device_key(), device);
+            }
+
+            // Device state does not match expected state, and the device is
+            // allowed to be changed outside of homectl. Keep the new state.
+            (_, Some(state_device), Some(expected_state), Some(override_policy))
+                if !cmp_device_states(&device.state, &expected_state) =>
+            {
+                self.override_device(device, &state_device, override_policy, Utc::now())
+                    .await;
+            }
+
             // Device state does not match expected state, maybe the
             // device missed a state update or forgot its state? Try
             // fixing this by emitting a SetIntegrationDeviceState
             // message back to integration
-            (_, _, Some(expected_state)) => {
+            (_, _, Some(expected_state), _) => {
                 if cmp_device_states(&device.state, &expected_state) {
                     return;
                 }
@@ -183,6 +498,10 @@ impl Devices {
                     "Device state mismatch detected ({}/{}): (was: {:?}, expected: {:?})",
                     device.integration_id, device.id, device.state, expected_state
                 );
+                DEVICE_STATE_CORRECTIONS
+                    .with_label_values(&[&device.integration_id.to_string()])
+                    .inc();
+
                 let mut device = device.clone();
                 device.state = expected_state;
 
@@ -193,17 +512,53 @@ impl Devices {
             }
 
             // Expected device state was not found
-            (_, _, None) => {
+            (_, _, None, _) => {
                 self.set_device_state(device, false, false, true).await;
             }
         }
     }
 
+    /// Stores availability reported by an integration. Devices going online or
+    /// offline are dispatched as regular device updates so that routines can
+    /// react to them.
+    async fn update_availability(
+        &mut self,
+        mut device: Device,
+        availability: &DeviceAvailability,
+    ) -> Device {
+        let changed = &device.availability != availability;
+        device.availability = availability.clone();
+
+        if changed {
+            println!(
+                "Device {}/{} is now {}",
+                device.integration_id,
+                device.id,
+                if availability.online {
+                    "online"
+                } else {
+                    "offline"
+                }
+            );
+
+            self.set_device_state(&device, false, true, true).await
+        } else {
+            // Only last_seen has changed, no need to notify anyone about it
+            let mut states = self.state.lock().unwrap();
+            states.0.insert(device.get_device_key(), device.clone());
+
+            device
+        }
+    }
+
     /// Returns expected state for given device based on possible active scene.
     /// If no scene active and use_passed_state is false, previous device state is returned.
     /// If no scene active and use_passed_state is true, passed device state is returned.
+    ///
+    /// The returned state is adapted to the device's capabilities, so that it
+    /// can be compared against states reported by the integration.
     fn get_expected_state(&self, device: &Device, use_passed_state: bool) -> DeviceState {
-        match device.state {
+        let expected_state = match device.state {
             // Sensors should always use the most recent sensor reading
             DeviceState::Sensor(_) => device.state.clone(),
 
@@ -231,7 +586,9 @@ impl Devices {
                     }
                 })
             }
-        }
+        };
+
+        expected_state.for_capabilities(device.capabilities.as_ref())
     }
 
     /// Sets stored state for given device and dispatches DeviceUpdate
@@ -274,10 +631,19 @@ impl Devices {
             let device = device.clone();
             tokio::spawn(async move {
                 db_update_device(&device).await.ok();
+                db_insert_device_history(&device).await.ok();
             });
         }
 
         if !skip_integration {
+            self.state_set_at
+                .lock()
+                .unwrap()
+                .insert(device.get_device_key(), Instant::now());
+
+            // Any fade in progress no longer applies
+            self.fades.lock().unwrap().remove(&device.get_device_key());
+
             self.sender.send(Message::SetIntegrationDeviceState {
                 device: device.clone(),
                 state_changed,
@@ -306,6 +672,10 @@ impl Devices {
         let scene_devices_config = self.find_scene_devices_config(scene_id)?;
 
         let device_scene_state = Some(DeviceSceneState::new(scene_id.to_owned()));
+        let transition = self
+            .scenes
+            .find_scene(scene_id)
+            .and_then(|scene| scene.transition);
 
         for (integration_id, devices) in scene_devices_config {
             for (device_id, _) in devices {
@@ -321,9 +691,24 @@ impl Devices {
                 let device = self.get_device(device_key);
 
                 if let Some(device) = device {
+                    let from = device.state.clone();
+
+                    // Devices with native transitions are given the scene's
+                    // transition time, other devices are faded by us
+                    let fade = transition
+                        .clone()
+                        .filter(|_| !device.supports_transitions());
+
                     let mut device = device.clone();
                     device.scene = device_scene_state.clone();
-                    self.set_device_state(&device, true, false, false).await;
+                    device.manual_override = None;
+                    let device = self
+                        .set_device_state(&device, true, false, fade.is_some())
+                        .await;
+
+                    if let Some(transition) = fade {
+                        self.start_fade(device, from, transition);
+                    }
                 }
             }
         }
@@ -420,6 +805,130 @@ impl Devices {
 
         Some(true)
     }
+
+    /// Applies a change to the current state of each given device. The
+    /// devices are detached from their active scene, as their state no longer
+    /// matches it, and any manual override is cleared as homectl is in
+    /// control of them again.
+    async fn adjust_device_states<F>(&mut self, devices: &[Device], f: F)
+    where
+        F: Fn(&mut DeviceState),
+    {
+        for device in devices {
+            let mut device = device.clone();
+            f(&mut device.state);
+            device.scene = None;
+            device.manual_override = None;
+
+            self.set_device_state(&device, true, false, false).await;
+        }
+    }
+
+    /// Changes brightness of given devices by `delta`. Devices that are
+    /// powered off are left alone.
+    pub async fn adjust_brightness(&mut self, devices: &[Device], delta: f32) {
+        let devices: Vec<Device> = devices
+            .iter()
+            .filter(|device| device.state.is_powered_on() == Some(true))
+            .cloned()
+            .collect();
+
+        self.adjust_device_states(&devices, |state| adjust_state_brightness(state, delta))
+            .await;
+    }
+
+    /// Turns given devices on or off. When toggling, all devices are turned
+    /// off if any of them are on, so that e.g. groups end up in a consistent
+    /// state.
+    pub async fn set_power(
+        &mut self,
+        devices: &[Device],
+        power: Option<bool>,
+        toggle: bool,
+    ) -> Result<()> {
+        let power = if toggle {
+            !devices
+                .iter()
+                .any(|device| device.state.is_powered_on() == Some(true))
+        } else {
+            power.ok_or_else(|| anyhow!("Either power or toggle must be given"))?
+        };
+
+        self.adjust_device_states(devices, |state| state.set_power(power))
+            .await;
+
+        Ok(())
+    }
+
+    /// Changes color temperature of given devices by `delta` kelvin. Devices
+    /// that are powered off or don't have a color are left alone.
+    pub async fn adjust_color_temperature(&mut self, devices: &[Device], delta: f32) {
+        let devices: Vec<Device> = devices
+            .iter()
+            .filter(|device| device.state.is_powered_on() == Some(true))
+            .filter(|device| device.state.get_approximate_cct().is_some())
+            .cloned()
+            .collect();
+
+        let default_cct = CorrelatedColorTemperature::default();
+        let range = default_cct.get_device_range();
+
+        self.adjust_device_states(&devices, |state| {
+            if let Some(cct) = state.get_approximate_cct() {
+                state.set_cct((cct + delta).clamp(range.start, range.end));
+            }
+        })
+        .await;
+    }
+}
+
+/// Changes brightness of given state by `delta`, clamped between 0 and 1.
+/// Lights that don't report a separate brightness, such as LIFX lights, keep
+/// their level in the value of their color, which is adjusted instead.
+fn adjust_state_brightness(state: &mut DeviceState, delta: f32) {
+    let adjust = |level: f32| (level + delta).clamp(0.0, 1.0);
+
+    match state {
+        DeviceState::Light(Light {
+            brightness: None,
+            color: Some(DeviceColor::Color(color)),
+            ..
+        }) => {
+            color.brightness = adjust(color.brightness);
+        }
+        DeviceState::MultiSourceLight(MultiSourceLight {
+            brightness: None,
+            lights,
+            ..
+        }) if lights
+            .iter()
+            .any(|light| matches!(light, DeviceColor::Color(_))) =>
+        {
+            for light in lights {
+                if let DeviceColor::Color(color) = light {
+                    color.brightness = adjust(color.brightness);
+                }
+            }
+        }
+        state => {
+            let brightness = state.get_brightness().unwrap_or(1.0);
+            state.set_brightness(adjust(brightness));
+        }
+    }
+}
+
+/// Whether given link points to given device
+fn is_linked_device(link: &GroupDeviceLink, device: &Device) -> bool {
+    let id_matches = match &link.device_id {
+        Some(id) => id == &device.id,
+        None => true,
+    };
+    let name_matches = match &link.name {
+        Some(name) => name == &device.name,
+        None => true,
+    };
+
+    link.integration_id == device.integration_id && id_matches && name_matches
 }
 
 pub fn find_device(
@@ -458,3 +967,257 @@ pub fn find_device(
 
     Some(device.clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use homectl_types::{
+        color::ColorXy,
+        event::{mk_channel, RxEventChannel},
+    };
+
+    fn light(brightness: Option<f32>, color: Option<DeviceColor>) -> DeviceState {
+        DeviceState::Light(Light::new(true, brightness, color, None))
+    }
+
+    fn color(value: f32) -> DeviceColor {
+        DeviceColor::Color(ColorXy::new(0.3, 0.3, value))
+    }
+
+    fn color_value(state: &DeviceState) -> Option<f32> {
+        state.get_color().map(|color| color.value)
+    }
+
+    #[test]
+    fn adjusts_brightness() {
+        let mut state = light(Some(0.5), Some(color(0.8)));
+        adjust_state_brightness(&mut state, 0.25);
+
+        assert_eq!(state.get_brightness(), Some(0.75));
+        assert_eq!(color_value(&state), Some(0.8));
+    }
+
+    #[test]
+    fn clamps_brightness() {
+        let mut state = light(Some(0.9), None);
+        adjust_state_brightness(&mut state, 0.25);
+        assert_eq!(state.get_brightness(), Some(1.0));
+
+        let mut state = light(Some(0.1), None);
+        adjust_state_brightness(&mut state, -0.25);
+        assert_eq!(state.get_brightness(), Some(0.0));
+    }
+
+    #[test]
+    fn adjusts_color_value_without_brightness() {
+        let mut state = light(None, Some(color(0.5)));
+        adjust_state_brightness(&mut state, -0.25);
+
+        assert_eq!(state.get_brightness(), None);
+        assert_eq!(color_value(&state), Some(0.25));
+    }
+
+    #[test]
+    fn clamps_color_value() {
+        let mut state = light(None, Some(color(0.9)));
+        adjust_state_brightness(&mut state, 0.25);
+        assert_eq!(color_value(&state), Some(1.0));
+
+        let mut state = light(None, Some(color(0.1)));
+        adjust_state_brightness(&mut state, -0.25);
+        assert_eq!(color_value(&state), Some(0.0));
+    }
+
+    #[test]
+    fn adjusts_each_light_source_without_brightness() {
+        let mut state = DeviceState::MultiSourceLight(MultiSourceLight {
+            power: true,
+            brightness: None,
+            lights: vec![color(0.2), color(0.9)],
+            transition_ms: None,
+        });
+        adjust_state_brightness(&mut state, 0.25);
+
+        let values: Vec<f32> = match &state {
+            DeviceState::MultiSourceLight(state) => state
+                .lights
+                .iter()
+                .map(|light| light.to_xy().brightness)
+                .collect(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(state.get_brightness(), None);
+        assert_eq!(values, vec![0.45, 1.0]);
+    }
+
+    #[test]
+    fn assumes_full_brightness_without_brightness_or_color() {
+        let mut state = light(None, None);
+        adjust_state_brightness(&mut state, -0.25);
+
+        assert_eq!(state.get_brightness(), Some(0.75));
+    }
+
+    const OVERRIDE_CONFIG: &str = r#"
+        [groups.hallway]
+        name = "Hallway"
+        devices = [
+            { integration_id = "hue1", name = "Lamp" },
+            { integration_id = "hue1", name = "Ceiling" },
+        ]
+
+        [scenes.evening]
+        name = "Evening"
+        devices = { hue1 = { "Lamp" = { power = true, brightness = 0.5 } } }
+
+        [manual_override]
+        groups = { hallway = { timeout_ms = 60000 } }
+        devices = [{ integration_id = "hue1", name = "Lamp", timeout_ms = 1000 }]
+    "#;
+
+    fn mk_devices(toml: &str) -> (Devices, RxEventChannel) {
+        let mut config = config::Config::default();
+        config
+            .merge(config::File::from_str(toml, config::FileFormat::Toml))
+            .unwrap();
+
+        let groups = Groups::new(config.get("groups").unwrap());
+        let scenes = Scenes::new(config.get("scenes").unwrap(), groups.clone());
+        let override_config = config.get("manual_override").unwrap_or_default();
+
+        let (sender, receiver) = mk_channel();
+
+        (
+            Devices::new(sender, scenes, groups, override_config),
+            receiver,
+        )
+    }
+
+    fn lamp(name: &str, brightness: f32, scene: Option<&str>) -> Device {
+        let mut device = Device::new(
+            IntegrationId::from("hue1".to_string()),
+            DeviceId::new(&name.to_lowercase()),
+            name.to_string(),
+            DeviceState::Light(Light::new(true, Some(brightness), None, None)),
+            None,
+        );
+        device.scene = scene.map(|scene_id| DeviceSceneState::new(SceneId::new(scene_id.into())));
+
+        device
+    }
+
+    fn policy(timeout_ms: Option<u64>) -> OverridePolicy {
+        OverridePolicy { timeout_ms }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp(1_600_000_000 + secs, 0)
+    }
+
+    /// Lamp in the evening scene, overridden at `at(0)`
+    async fn mk_overridden_lamp(devices: &mut Devices, timeout_ms: Option<u64>) -> Device {
+        let state_device = lamp("Lamp", 0.5, Some("evening"));
+        devices
+            .state
+            .lock()
+            .unwrap()
+            .0
+            .insert(state_device.get_device_key(), state_device.clone());
+
+        devices
+            .override_device(
+                &lamp("Lamp", 0.2, None),
+                &state_device,
+                policy(timeout_ms),
+                at(0),
+            )
+            .await;
+
+        devices.get_device(&state_device.get_device_key()).unwrap()
+    }
+
+    #[test]
+    fn device_override_policy_takes_precedence() {
+        let (devices, _receiver) = mk_devices(OVERRIDE_CONFIG);
+
+        let find_timeout = |name| {
+            devices
+                .find_override_policy(&lamp(name, 1.0, None))
+                .map(|policy| policy.timeout_ms)
+        };
+
+        assert_eq!(find_timeout("Lamp"), Some(Some(1000)));
+        assert_eq!(find_timeout("Ceiling"), Some(Some(60000)));
+        assert_eq!(find_timeout("Desk lamp"), None);
+    }
+
+    #[tokio::test]
+    async fn override_detaches_device_from_scene() {
+        let (mut devices, _receiver) = mk_devices(OVERRIDE_CONFIG);
+        let device = mk_overridden_lamp(&mut devices, Some(60000)).await;
+        let manual_override = device.manual_override.clone().unwrap();
+
+        assert!(device.scene.is_none());
+        assert_eq!(device.state.get_brightness(), Some(0.2));
+        assert_eq!(
+            manual_override.scene_id,
+            Some(SceneId::new("evening".into()))
+        );
+        assert_eq!(manual_override.override_time, at(0));
+        assert_eq!(manual_override.expires, Some(at(60)));
+
+        // Repeated overrides keep the original scene and restart the timeout
+        devices
+            .override_device(
+                &lamp("Lamp", 0.1, None),
+                &device,
+                policy(Some(60000)),
+                at(30),
+            )
+            .await;
+        let device = devices.get_device(&device.get_device_key()).unwrap();
+        let manual_override = device.manual_override.unwrap();
+
+        assert_eq!(
+            manual_override.scene_id,
+            Some(SceneId::new("evening".into()))
+        );
+        assert_eq!(manual_override.expires, Some(at(90)));
+    }
+
+    #[tokio::test]
+    async fn expired_override_returns_device_to_scene() {
+        let (mut devices, _receiver) = mk_devices(OVERRIDE_CONFIG);
+        let device_key = mk_overridden_lamp(&mut devices, Some(60000))
+            .await
+            .get_device_key();
+
+        devices.expire_manual_overrides(at(59)).await;
+        let device = devices.get_device(&device_key).unwrap();
+        assert!(device.manual_override.is_some());
+        assert_eq!(device.state.get_brightness(), Some(0.2));
+
+        devices.expire_manual_overrides(at(60)).await;
+        let device = devices.get_device(&device_key).unwrap();
+        assert!(device.manual_override.is_none());
+        assert_eq!(device.get_scene_id(), Some(&SceneId::new("evening".into())));
+        assert_eq!(device.state.get_brightness(), Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn override_without_timeout_never_expires() {
+        let (mut devices, _receiver) = mk_devices(OVERRIDE_CONFIG);
+        let device_key = mk_overridden_lamp(&mut devices, None)
+            .await
+            .get_device_key();
+
+        devices.expire_manual_overrides(at(365 * 24 * 3600)).await;
+        let device = devices.get_device(&device_key).unwrap();
+
+        assert!(device.manual_override.is_some());
+        assert!(device.scene.is_none());
+        assert_eq!(device.state.get_brightness(), Some(0.2));
+    }
+}
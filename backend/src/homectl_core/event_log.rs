@@ -0,0 +1,44 @@
+use chrono::Utc;
+use homectl_types::{
+    event_log::{Event, EventLogEntry, EventOrigin},
+    websockets::WebSocketResponse,
+};
+
+use crate::db::actions::db_insert_event_log;
+
+use super::websockets::WebSockets;
+
+/// Records what happened and why, e.g. which routine fired or who activated a
+/// scene. Entries are persisted to the DB and broadcast to WebSocket peers.
+#[derive(Clone)]
+pub struct EventLog {
+    ws: WebSockets,
+}
+
+impl EventLog {
+    pub fn new(ws: WebSockets) -> Self {
+        EventLog { ws }
+    }
+
+    pub fn log(&self, origin: EventOrigin, event: Event) {
+        let entry = EventLogEntry {
+            origin,
+            event,
+            created_at: Utc::now(),
+        };
+
+        if let Event::Error { error, .. } = &entry.event {
+            println!("Error from {:?}: {}", entry.origin, error);
+        }
+
+        let ws = self.ws.clone();
+        tokio::spawn(async move {
+            db_insert_event_log(&entry).await.ok();
+
+            if ws.num_users().await > 0 {
+                ws.send(None, &WebSocketResponse::Event(Box::new(entry)))
+                    .await;
+            }
+        });
+    }
+}
@@ -4,19 +4,29 @@ use homectl_types::{
 };
 
 use super::devices::find_device;
+use std::sync::{Arc, RwLock};
 
 #[derive(Clone)]
 pub struct Groups {
-    config: GroupsConfig,
+    config: Arc<RwLock<GroupsConfig>>,
 }
 
 impl Groups {
     pub fn new(config: GroupsConfig) -> Self {
-        Groups { config }
+        Groups {
+            config: Arc::new(RwLock::new(config)),
+        }
+    }
+
+    /// Replaces groups config, e.g. after the config file has been reloaded
+    pub fn set_config(&self, config: GroupsConfig) {
+        *self.config.write().unwrap() = config;
     }
 
     pub fn get_flattened_groups(&self, devices: &DevicesState) -> FlattenedGroupsConfig {
-        self.config
+        let config = self.config.read().unwrap().clone();
+
+        config
             .iter()
             .map(|(group_id, group)| {
                 (
@@ -36,24 +46,8 @@ impl Groups {
 
     /// Returns all GroupDeviceLinks that belong to given group
     pub fn find_group_device_links(&self, group_id: &GroupId) -> Vec<GroupDeviceLink> {
-        let group = self.config.get(group_id);
-
-        let results = group.map(|group| {
-            let mut results = vec![];
-
-            for device_link in group.devices.clone().unwrap_or_default() {
-                results.push(device_link);
-            }
-
-            for group_link in group.groups.clone().unwrap_or_default() {
-                let mut device_links = self.find_group_device_links(&group_link.group_id);
-                results.append(device_links.as_mut());
-            }
-
-            results
-        });
-
-        results.unwrap_or_default()
+        let config = self.config.read().unwrap();
+        find_group_device_links(&config, group_id)
     }
 
     pub fn find_group_devices(&self, devices: &DevicesState, group_id: &GroupId) -> Vec<Device> {
@@ -71,3 +65,24 @@ impl Groups {
             .collect()
     }
 }
+
+fn find_group_device_links(config: &GroupsConfig, group_id: &GroupId) -> Vec<GroupDeviceLink> {
+    let group = config.get(group_id);
+
+    let results = group.map(|group| {
+        let mut results = vec![];
+
+        for device_link in group.devices.clone().unwrap_or_default() {
+            results.push(device_link);
+        }
+
+        for group_link in group.groups.clone().unwrap_or_default() {
+            let mut device_links = find_group_device_links(config, &group_link.group_id);
+            results.append(device_links.as_mut());
+        }
+
+        results
+    });
+
+    results.unwrap_or_default()
+}
@@ -1,31 +1,63 @@
 use crate::integrations::{
-    boolean::Boolean, circadian::Circadian, dummy::Dummy, hue::Hue, lifx::Lifx, neato::Neato,
-    random::Random, timer::Timer, tuya::Tuya, wake_on_lan::WakeOnLan,
+    boolean::Boolean, circadian::Circadian, dummy::Dummy, hue::Hue, lifx::Lifx, mqtt::Mqtt,
+    neato::Neato, ping::Ping, random::Random, timer::Timer, tuya::Tuya, wake_on_lan::WakeOnLan,
 };
 use anyhow::{anyhow, Context, Result};
 use homectl_types::{
     device::Device,
-    event::TxEventChannel,
-    integration::{Integration, IntegrationActionPayload, IntegrationId},
+    event::{mk_channel, TxEventChannel},
+    integration::{
+        Integration, IntegrationActionPayload, IntegrationHealth, IntegrationId,
+        IntegrationStatus, IntegrationsConfig, IntegrationsStatus,
+    },
+    websockets::WebSocketResponse,
 };
-use tokio::sync::Mutex;
-use std::{collections::HashMap, sync::Arc};
+use tokio::{sync::Mutex, time};
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::Duration};
 
-pub type IntegrationsTree = HashMap<IntegrationId, Arc<Mutex<Box<dyn Integration + Send>>>>;
+use super::{
+    config::OpaqueIntegrationsConfigs,
+    metrics::{INTEGRATION_SET_STATE_DURATION, INTEGRATION_SET_STATE_ERRORS},
+    websockets::WebSockets,
+};
+
+/// How often running integrations are checked for failures
+static HEALTH_CHECK_POLL_RATE: u64 = 10000;
+
+/// Delay before the first attempt to restart a failed integration, doubled
+/// after each failed attempt up to RESTART_BACKOFF_MAX
+static RESTART_BACKOFF_INITIAL: u64 = 1000;
+static RESTART_BACKOFF_MAX: u64 = 300000;
+
+type IntegrationHandle = Arc<Mutex<Box<dyn Integration + Send>>>;
+
+pub type IntegrationsTree = HashMap<IntegrationId, LoadedIntegration>;
+
+#[derive(Clone)]
+pub struct LoadedIntegration {
+    module_name: String,
+    config: config::Value,
+    integration: IntegrationHandle,
+}
 
 #[derive(Clone)]
 pub struct Integrations {
-    integrations: IntegrationsTree,
+    integrations: Arc<RwLock<IntegrationsTree>>,
+    status: Arc<RwLock<IntegrationsStatus>>,
     sender: TxEventChannel,
+    ws: WebSockets,
 }
 
 impl Integrations {
-    pub fn new(sender: TxEventChannel) -> Self {
+    pub fn new(sender: TxEventChannel, ws: WebSockets) -> Self {
         let integrations = Default::default();
+        let status = Default::default();
 
         Integrations {
             integrations,
+            status,
             sender,
+            ws,
         }
     }
 
@@ -41,45 +73,398 @@ impl Integrations {
             load_integration(module_name, integration_id, config, self.sender.clone())?;
         let integration = Arc::new(Mutex::new(integration));
 
+        let loaded_integration = LoadedIntegration {
+            module_name: module_name.to_string(),
+            config: config.clone(),
+            integration,
+        };
+
         self.integrations
-            .insert(integration_id.clone(), integration);
+            .write()
+            .unwrap()
+            .insert(integration_id.clone(), loaded_integration);
 
         Ok(())
     }
 
-    pub async fn run_register_pass(&mut self) -> Result<()> {
-        for (_integration_id, integration) in self.integrations.iter_mut() {
-            let mut integration = integration.lock().await;
+    fn get_integration(&self, integration_id: &IntegrationId) -> Result<IntegrationHandle> {
+        let integrations = self.integrations.read().unwrap();
+        let loaded_integration = integrations.get(integration_id).context(format!(
+            "Expected to find integration by id {}",
+            integration_id
+        ))?;
+
+        Ok(loaded_integration.integration.clone())
+    }
+
+    fn get_all_integrations(&self) -> Vec<(IntegrationId, IntegrationHandle)> {
+        let integrations = self.integrations.read().unwrap();
+
+        integrations
+            .iter()
+            .map(|(integration_id, loaded_integration)| {
+                (integration_id.clone(), loaded_integration.integration.clone())
+            })
+            .collect()
+    }
+
+    /// Returns true if given integration is still loaded under given id, i.e.
+    /// it hasn't been unloaded or replaced by a config reload
+    fn is_loaded(&self, integration_id: &IntegrationId, integration: &IntegrationHandle) -> bool {
+        let integrations = self.integrations.read().unwrap();
+
+        integrations
+            .get(integration_id)
+            .map(|loaded_integration| Arc::ptr_eq(&loaded_integration.integration, integration))
+            .unwrap_or(false)
+    }
+
+    pub fn get_status(&self) -> IntegrationsStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    /// Sends status of all integrations over WebSockets. If user_id is
+    /// omitted, the message is broadcast to all connected peers.
+    pub async fn send_status_ws(&self, user_id: Option<usize>) {
+        let message = WebSocketResponse::IntegrationsStatus(self.get_status());
+        self.ws.send(user_id, &message).await;
+    }
+
+    /// Updates health of given integration. If error is omitted, the previous
+    /// error is kept around for troubleshooting.
+    async fn set_health(
+        &self,
+        integration_id: &IntegrationId,
+        health: IntegrationHealth,
+        error: Option<String>,
+    ) {
+        {
+            let mut status = self.status.write().unwrap();
+
+            let last_error = error.or_else(|| {
+                status
+                    .get(integration_id)
+                    .and_then(|status| status.last_error.clone())
+            });
 
-            integration.register().await.unwrap();
+            status.insert(
+                integration_id.clone(),
+                IntegrationStatus { health, last_error },
+            );
         }
 
-        Ok(())
+        self.send_status_ws(None).await;
     }
 
-    pub async fn run_start_pass(&mut self) -> Result<()> {
-        for (_integration_id, integration) in self.integrations.iter_mut() {
-            let mut integration = integration.lock().await;
+    /// Marks running integrations as degraded when they fail to handle a
+    /// request, and as healthy again once they succeed
+    async fn report_result(&self, integration_id: &IntegrationId, result: &Result<()>) {
+        let health = self
+            .status
+            .read()
+            .unwrap()
+            .get(integration_id)
+            .map(|status| status.health);
 
-            integration.start().await.unwrap();
+        match (health, result) {
+            (Some(IntegrationHealth::Healthy), Err(e))
+            | (Some(IntegrationHealth::Degraded), Err(e)) => {
+                self.set_health(
+                    integration_id,
+                    IntegrationHealth::Degraded,
+                    Some(format!("{:#}", e)),
+                )
+                .await
+            }
+            (Some(IntegrationHealth::Degraded), Ok(())) => {
+                self.set_health(integration_id, IntegrationHealth::Healthy, None)
+                    .await
+            }
+            _ => {}
         }
+    }
+
+    /// Registers and starts all loaded integrations in the background
+    pub fn run_start_pass(&self) {
+        for (integration_id, integration) in self.get_all_integrations() {
+            self.spawn_start(integration_id, integration, false);
+        }
+    }
+
+    /// Registers and starts given integration in a background task. Failed
+    /// attempts are retried with exponential backoff until the integration
+    /// starts or is unloaded. If restart is true, the integration is first
+    /// stopped and replaced with a new instance.
+    fn spawn_start(
+        &self,
+        integration_id: IntegrationId,
+        integration: IntegrationHandle,
+        mut restart: bool,
+    ) {
+        let integrations = self.clone();
+
+        tokio::spawn(async move {
+            let mut backoff = RESTART_BACKOFF_INITIAL;
+
+            loop {
+                if !integrations.is_loaded(&integration_id, &integration) {
+                    return;
+                }
+
+                integrations
+                    .set_health(&integration_id, IntegrationHealth::Starting, None)
+                    .await;
+
+                let result = integrations
+                    .try_start(&integration_id, &integration, restart)
+                    .await;
+
+                if !integrations.is_loaded(&integration_id, &integration) {
+                    return;
+                }
+
+                match result {
+                    Ok(()) => {
+                        integrations
+                            .set_health(&integration_id, IntegrationHealth::Healthy, None)
+                            .await;
+
+                        return;
+                    }
+                    Err(e) => {
+                        println!(
+                            "Error while starting integration {} (retrying in {}ms): {:?}",
+                            integration_id, backoff, e
+                        );
+
+                        integrations
+                            .set_health(
+                                &integration_id,
+                                IntegrationHealth::Failed,
+                                Some(format!("{:#}", e)),
+                            )
+                            .await;
+                    }
+                }
+
+                time::sleep(Duration::from_millis(backoff)).await;
+                backoff = next_backoff(backoff);
+                restart = true;
+            }
+        });
+    }
+
+    async fn try_start(
+        &self,
+        integration_id: &IntegrationId,
+        integration: &IntegrationHandle,
+        restart: bool,
+    ) -> Result<()> {
+        let mut integration = integration.lock().await;
+
+        if restart {
+            println!("restarting integration {}", integration_id);
+
+            // Stop any tasks left over from the previous attempt
+            if let Err(e) = integration.stop().await {
+                println!("Error while stopping integration {}: {:?}", integration_id, e);
+            }
+
+            let (module_name, config) = {
+                let integrations = self.integrations.read().unwrap();
+                let loaded_integration = integrations.get(integration_id).context(format!(
+                    "Expected to find integration by id {}",
+                    integration_id
+                ))?;
+
+                (loaded_integration.module_name.clone(), loaded_integration.config.clone())
+            };
+
+            *integration =
+                load_integration(&module_name, integration_id, &config, self.sender.clone())?;
+        }
+
+        integration.register().await?;
+        integration.start().await
+    }
+
+    /// Periodically runs health checks of running integrations, restarting
+    /// any integrations that have stopped working
+    pub fn start_health_checks(&self) {
+        let integrations = self.clone();
+
+        tokio::spawn(async move {
+            let poll_rate = Duration::from_millis(HEALTH_CHECK_POLL_RATE);
+            let mut interval = time::interval(poll_rate);
+
+            loop {
+                interval.tick().await;
+                integrations.run_health_checks().await;
+            }
+        });
+    }
+
+    async fn run_health_checks(&self) {
+        for (integration_id, integration) in self.get_all_integrations() {
+            let health = self
+                .status
+                .read()
+                .unwrap()
+                .get(&integration_id)
+                .map(|status| status.health);
+
+            // Integrations that are (re)starting are already taken care of
+            if !matches!(
+                health,
+                Some(IntegrationHealth::Healthy) | Some(IntegrationHealth::Degraded)
+            ) {
+                continue;
+            }
+
+            let result = integration.lock().await.health_check().await;
+
+            if let Err(e) = result {
+                println!("Integration {} failed health check: {:?}", integration_id, e);
+
+                self.set_health(
+                    &integration_id,
+                    IntegrationHealth::Failed,
+                    Some(format!("{:#}", e)),
+                )
+                .await;
+
+                self.spawn_start(integration_id, integration, true);
+            }
+        }
+    }
+
+    /// Compares given configs against the currently loaded integrations.
+    /// Integrations that were removed from the config or whose config has
+    /// changed are stopped, and new or changed integrations are (re)started.
+    /// Integrations with unchanged config are left running as-is.
+    ///
+    /// All new integrations are constructed before anything is stopped, so if
+    /// any of them fails to load the currently running integrations are left
+    /// untouched.
+    pub async fn reload_integrations(
+        &mut self,
+        integrations_config: &IntegrationsConfig,
+        opaque_integrations_configs: &OpaqueIntegrationsConfigs,
+    ) -> Result<()> {
+        let loaded_integrations = self.integrations.read().unwrap().clone();
+
+        let is_unchanged = |integration_id: &IntegrationId| {
+            let loaded_integration = match loaded_integrations.get(integration_id) {
+                Some(loaded_integration) => loaded_integration,
+                None => return false,
+            };
+
+            match (
+                integrations_config.get(integration_id),
+                opaque_integrations_configs.get(integration_id),
+            ) {
+                (Some(integration_config), Some(config)) => {
+                    integration_config.plugin == loaded_integration.module_name
+                        && config_eq(config, &loaded_integration.config)
+                }
+                _ => false,
+            }
+        };
+
+        let mut new_integrations = vec![];
+
+        for (integration_id, integration_config) in integrations_config {
+            if is_unchanged(integration_id) {
+                continue;
+            }
+
+            let config = opaque_integrations_configs.get(integration_id).with_context(|| {
+                format!("Expected to find config for integration with id {}", integration_id)
+            })?;
+
+            let integration = load_integration(
+                &integration_config.plugin,
+                integration_id,
+                config,
+                self.sender.clone(),
+            )
+            .with_context(|| format!("Failed to load integration {}", integration_id))?;
+
+            new_integrations.push((
+                integration_id.clone(),
+                LoadedIntegration {
+                    module_name: integration_config.plugin.clone(),
+                    config: config.clone(),
+                    integration: Arc::new(Mutex::new(integration)),
+                },
+            ));
+        }
+
+        for (integration_id, loaded_integration) in &loaded_integrations {
+            if is_unchanged(integration_id) {
+                continue;
+            }
+
+            println!("stopping integration {}", integration_id);
+
+            self.integrations.write().unwrap().remove(integration_id);
+            self.status.write().unwrap().remove(integration_id);
+
+            let mut integration = loaded_integration.integration.lock().await;
+            if let Err(e) = integration.stop().await {
+                println!("Error while stopping integration {}: {:?}", integration_id, e);
+            }
+        }
+
+        for (integration_id, loaded_integration) in new_integrations {
+            println!(
+                "loading integration with module_name {}",
+                loaded_integration.module_name
+            );
+
+            let integration = loaded_integration.integration.clone();
+
+            self.integrations
+                .write()
+                .unwrap()
+                .insert(integration_id.clone(), loaded_integration);
+
+            self.spawn_start(integration_id, integration, false);
+        }
+
+        self.send_status_ws(None).await;
 
         Ok(())
     }
 
+    /// Adapts the requested device state to what the device can actually do,
+    /// then passes it on to the device's integration. Integrations can thus
+    /// rely on only receiving colors and fields that the device supports.
     pub async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
-        let integration = self
-            .integrations
-            .get(&device.integration_id)
-            .context(format!(
-                "Expected to find integration by id {}",
-                device.integration_id
-            ))?;
+        let integration = self.get_integration(&device.integration_id)?;
         let mut integration = integration.lock().await;
 
-        integration
-            .set_integration_device_state(&device.clone())
-            .await
+        let mut device = device.clone();
+        device.state = device.state.for_capabilities(device.capabilities.as_ref());
+
+        let integration_id = device.integration_id.to_string();
+        let timer = INTEGRATION_SET_STATE_DURATION
+            .with_label_values(&[&integration_id])
+            .start_timer();
+
+        let result = integration.set_integration_device_state(&device).await;
+
+        timer.observe_duration();
+
+        if result.is_err() {
+            INTEGRATION_SET_STATE_ERRORS
+                .with_label_values(&[&integration_id])
+                .inc();
+        }
+
+        self.report_result(&device.integration_id, &result).await;
+
+        result
     }
 
     pub async fn run_integration_action(
@@ -87,16 +472,44 @@ impl Integrations {
         integration_id: &IntegrationId,
         payload: &IntegrationActionPayload,
     ) -> Result<()> {
-        let integration = self.integrations.get(integration_id).context(format!(
-            "Expected to find integration by id {}",
-            integration_id
-        ))?;
+        let integration = self.get_integration(integration_id)?;
         let mut integration = integration.lock().await;
 
-        integration.run_integration_action(payload).await
+        let result = integration.run_integration_action(payload).await;
+        self.report_result(integration_id, &result).await;
+
+        result
     }
 }
 
+/// Returns how long to wait before the next restart attempt after waiting
+/// `backoff` milliseconds before the previous one
+fn next_backoff(backoff: u64) -> u64 {
+    u64::min(backoff.saturating_mul(2), RESTART_BACKOFF_MAX)
+}
+
+/// Compares integration configs by their contents, ignoring e.g. the order of
+/// keys in tables
+fn config_eq(a: &config::Value, b: &config::Value) -> bool {
+    let a: Option<serde_json::Value> = a.clone().try_into().ok();
+    let b: Option<serde_json::Value> = b.clone().try_into().ok();
+
+    a.is_some() && a == b
+}
+
+/// Checks that given integration accepts its config, without registering or
+/// starting the integration
+pub fn validate_integration_config(
+    module_name: &str,
+    id: &IntegrationId,
+    config: &config::Value,
+) -> Result<()> {
+    let (sender, _receiver) = mk_channel();
+    load_integration(module_name, id, config, sender)?;
+
+    Ok(())
+}
+
 // TODO: Load integrations dynamically as plugins:
 // https://michael-f-bryan.github.io/rust-ffi-guide/dynamic_loading.html
 fn load_integration(
@@ -113,9 +526,256 @@ fn load_integration(
         "dummy" => Ok(Box::new(Dummy::new(id, config, event_tx)?)),
         "lifx" => Ok(Box::new(Lifx::new(id, config, event_tx)?)),
         "hue" => Ok(Box::new(Hue::new(id, config, event_tx)?)),
+        "mqtt" => Ok(Box::new(Mqtt::new(id, config, event_tx)?)),
         "neato" => Ok(Box::new(Neato::new(id, config, event_tx)?)),
+        "ping" => Ok(Box::new(Ping::new(id, config, event_tx)?)),
         "tuya" => Ok(Box::new(Tuya::new(id, config, event_tx)?)),
         "wake_on_lan" => Ok(Box::new(WakeOnLan::new(id, config, event_tx)?)),
         _ => Err(anyhow!("Unknown module name {}!", module_name)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_configs(toml: &str) -> (IntegrationsConfig, OpaqueIntegrationsConfigs) {
+        let mut settings = config::Config::default();
+        settings
+            .merge(config::File::from_str(toml, config::FileFormat::Toml))
+            .unwrap();
+
+        (
+            settings.get("integrations").unwrap(),
+            settings.get("integrations").unwrap(),
+        )
+    }
+
+    const CONFIG: &str = r#"
+        [integrations.dummy1]
+        plugin = "dummy"
+        devices = {}
+
+        [integrations.dummy2]
+        plugin = "dummy"
+        devices = {}
+    "#;
+
+    fn id(id: &str) -> IntegrationId {
+        IntegrationId::from(id.to_string())
+    }
+
+    async fn reload(integrations: &mut Integrations, toml: &str) -> Result<()> {
+        let (integrations_config, opaque_integrations_configs) = mk_configs(toml);
+
+        integrations
+            .reload_integrations(&integrations_config, &opaque_integrations_configs)
+            .await
+    }
+
+    fn loaded_ids(integrations: &Integrations) -> Vec<String> {
+        let mut ids: Vec<String> = integrations
+            .integrations
+            .read()
+            .unwrap()
+            .keys()
+            .map(|id| id.to_string())
+            .collect();
+
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn compares_configs_by_contents() {
+        let (_, a) = mk_configs(
+            r#"
+            [integrations.dummy]
+            plugin = "dummy"
+            devices = {}
+            "#,
+        );
+        let (_, b) = mk_configs(
+            r#"
+            [integrations.dummy]
+            devices = {}
+            plugin = "dummy"
+            "#,
+        );
+        let (_, c) = mk_configs(
+            r#"
+            [integrations.dummy]
+            plugin = "dummy"
+            devices = {}
+            extra = 1
+            "#,
+        );
+
+        assert!(config_eq(&a[&id("dummy")], &b[&id("dummy")]));
+        assert!(!config_eq(&a[&id("dummy")], &c[&id("dummy")]));
+    }
+
+    #[tokio::test]
+    async fn loads_new_integrations() {
+        let (sender, _receiver) = mk_channel();
+        let mut integrations = Integrations::new(sender, Default::default());
+
+        reload(&mut integrations, CONFIG).await.unwrap();
+
+        assert_eq!(loaded_ids(&integrations), vec!["dummy1", "dummy2"]);
+    }
+
+    #[tokio::test]
+    async fn restarts_only_changed_integrations() {
+        let (sender, _receiver) = mk_channel();
+        let mut integrations = Integrations::new(sender, Default::default());
+
+        reload(&mut integrations, CONFIG).await.unwrap();
+        let dummy1 = integrations.get_integration(&id("dummy1")).unwrap();
+        let dummy2 = integrations.get_integration(&id("dummy2")).unwrap();
+
+        let changed = r#"
+            [integrations.dummy1]
+            plugin = "dummy"
+            devices = {}
+            extra = 1
+
+            [integrations.dummy2]
+            devices = {}
+            plugin = "dummy"
+        "#;
+        reload(&mut integrations, changed).await.unwrap();
+
+        assert!(!integrations.is_loaded(&id("dummy1"), &dummy1));
+        assert!(integrations.is_loaded(&id("dummy2"), &dummy2));
+        assert_eq!(loaded_ids(&integrations), vec!["dummy1", "dummy2"]);
+    }
+
+    #[tokio::test]
+    async fn unloads_removed_integrations() {
+        let (sender, _receiver) = mk_channel();
+        let mut integrations = Integrations::new(sender, Default::default());
+
+        reload(&mut integrations, CONFIG).await.unwrap();
+
+        let removed = r#"
+            [integrations.dummy2]
+            plugin = "dummy"
+            devices = {}
+        "#;
+        reload(&mut integrations, removed).await.unwrap();
+
+        assert_eq!(loaded_ids(&integrations), vec!["dummy2"]);
+        assert!(!integrations.get_status().contains_key(&id("dummy1")));
+    }
+
+    #[tokio::test]
+    async fn keeps_running_integrations_if_reload_fails() {
+        let (sender, _receiver) = mk_channel();
+        let mut integrations = Integrations::new(sender, Default::default());
+
+        reload(&mut integrations, CONFIG).await.unwrap();
+        let dummy1 = integrations.get_integration(&id("dummy1")).unwrap();
+
+        let invalid = r#"
+            [integrations.dummy1]
+            plugin = "dummy"
+            devices = {}
+            extra = 1
+
+            [integrations.unknown]
+            plugin = "unknown"
+        "#;
+        assert!(reload(&mut integrations, invalid).await.is_err());
+
+        assert!(integrations.is_loaded(&id("dummy1"), &dummy1));
+        assert_eq!(loaded_ids(&integrations), vec!["dummy1", "dummy2"]);
+    }
+
+    #[test]
+    fn doubles_backoff_up_to_max() {
+        let mut backoff = RESTART_BACKOFF_INITIAL;
+        let mut backoffs = vec![];
+
+        for _ in 0..12 {
+            backoffs.push(backoff);
+            backoff = next_backoff(backoff);
+        }
+
+        assert_eq!(
+            backoffs,
+            vec![
+                1000, 2000, 4000, 8000, 16000, 32000, 64000, 128000, 256000, 300000, 300000, 300000
+            ]
+        );
+        assert_eq!(next_backoff(u64::MAX), RESTART_BACKOFF_MAX);
+    }
+
+    fn health(integrations: &Integrations, integration_id: &str) -> Option<IntegrationHealth> {
+        integrations
+            .get_status()
+            .get(&id(integration_id))
+            .map(|status| status.health)
+    }
+
+    fn last_error(integrations: &Integrations, integration_id: &str) -> Option<String> {
+        integrations
+            .get_status()
+            .get(&id(integration_id))
+            .and_then(|status| status.last_error.clone())
+    }
+
+    #[tokio::test]
+    async fn degrades_and_recovers_running_integrations() {
+        let (sender, _receiver) = mk_channel();
+        let integrations = Integrations::new(sender, Default::default());
+
+        integrations
+            .set_health(&id("dummy"), IntegrationHealth::Healthy, None)
+            .await;
+
+        integrations
+            .report_result(&id("dummy"), &Err(anyhow!("Bridge unreachable")))
+            .await;
+        assert_eq!(
+            health(&integrations, "dummy"),
+            Some(IntegrationHealth::Degraded)
+        );
+        assert_eq!(
+            last_error(&integrations, "dummy"),
+            Some("Bridge unreachable".to_string())
+        );
+
+        integrations.report_result(&id("dummy"), &Ok(())).await;
+        assert_eq!(
+            health(&integrations, "dummy"),
+            Some(IntegrationHealth::Healthy)
+        );
+
+        // The last error is kept around for troubleshooting
+        assert_eq!(
+            last_error(&integrations, "dummy"),
+            Some("Bridge unreachable".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_results_of_integrations_that_are_not_running() {
+        let (sender, _receiver) = mk_channel();
+        let integrations = Integrations::new(sender, Default::default());
+
+        integrations
+            .report_result(&id("dummy"), &Err(anyhow!("Not loaded")))
+            .await;
+        assert_eq!(health(&integrations, "dummy"), None);
+
+        integrations
+            .set_health(&id("dummy"), IntegrationHealth::Failed, None)
+            .await;
+        integrations.report_result(&id("dummy"), &Ok(())).await;
+        assert_eq!(
+            health(&integrations, "dummy"),
+            Some(IntegrationHealth::Failed)
+        );
+    }
+}
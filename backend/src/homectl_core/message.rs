@@ -2,17 +2,58 @@ use anyhow::Result;
 use std::sync::Arc;
 
 use homectl_types::{
-    action::Action,
+    action::{
+        Action, AdjustBrightnessDescriptor, AdjustColorTemperatureDescriptor, DeviceTargets,
+        SetPowerDescriptor,
+    },
+    device::Device,
     event::*,
+    event_log::{Event, EventOrigin},
     integration::IntegrationActionDescriptor,
     scene::{CycleScenesDescriptor, SceneDescriptor},
 };
 
 use crate::db::actions::{db_delete_scene, db_store_scene};
 
-use super::state::AppState;
+use super::{devices::find_device, metrics::record_message, state::AppState};
+
+/// Finds devices targeted by an action, skipping devices that haven't been
+/// discovered yet
+fn find_target_devices(state: &AppState, targets: &DeviceTargets) -> Vec<Device> {
+    let devices_state = state.devices.get_devices();
+    let mut devices: Vec<Device> = vec![];
+
+    let linked_devices = targets.devices.iter().flatten().filter_map(|link| {
+        find_device(
+            &devices_state,
+            &link.integration_id,
+            link.device_id.as_ref(),
+            link.name.as_ref(),
+        )
+    });
+
+    let group_devices = targets
+        .groups
+        .iter()
+        .flatten()
+        .flat_map(|group_id| state.groups.find_group_devices(&devices_state, group_id));
+
+    for device in linked_devices.chain(group_devices) {
+        // Devices may be targeted both directly and via groups
+        if !devices
+            .iter()
+            .any(|d| d.get_device_key() == device.get_device_key())
+        {
+            devices.push(device);
+        }
+    }
+
+    devices
+}
 
 pub async fn handle_message(state: Arc<AppState>, msg: Message) {
+    record_message(&msg);
+
     let result: Result<()> = match &msg {
         Message::IntegrationDeviceRefresh { device } => {
             let mut devices = state.devices.clone();
@@ -26,6 +67,7 @@ pub async fn handle_message(state: Arc<AppState>, msg: Message) {
             old,
             new,
         } => {
+            state.presence.handle_device_update(new_state);
             state
                 .rules
                 .handle_device_update(old_state, new_state, old, new)
@@ -34,9 +76,13 @@ pub async fn handle_message(state: Arc<AppState>, msg: Message) {
             Ok(())
         }
         Message::SetDeviceState { device, set_scene } => {
+            // Devices that are explicitly set are no longer manually overridden
+            let mut device = device.clone();
+            device.manual_override = None;
+
             let mut devices = state.devices.clone();
             devices
-                .set_device_state(device, *set_scene, false, false)
+                .set_device_state(&device, *set_scene, false, false)
                 .await;
 
             Ok(())
@@ -93,11 +139,124 @@ pub async fn handle_message(state: Arc<AppState>, msg: Message) {
                 .run_integration_action(integration_id, payload)
                 .await
         }
+        Message::Action(Action::AdjustBrightness(AdjustBrightnessDescriptor {
+            delta,
+            targets,
+        })) => {
+            let target_devices = find_target_devices(&state, targets);
+            let mut devices = state.devices.clone();
+            devices.adjust_brightness(&target_devices, *delta).await;
+
+            Ok(())
+        }
+        Message::Action(Action::SetPower(SetPowerDescriptor {
+            power,
+            toggle,
+            targets,
+        })) => {
+            let target_devices = find_target_devices(&state, targets);
+            let mut devices = state.devices.clone();
+            devices.set_power(&target_devices, *power, *toggle).await
+        }
+        Message::Action(Action::AdjustColorTemperature(AdjustColorTemperatureDescriptor {
+            delta,
+            targets,
+        })) => {
+            let target_devices = find_target_devices(&state, targets);
+            let mut devices = state.devices.clone();
+            devices
+                .adjust_color_temperature(&target_devices, *delta)
+                .await;
+
+            Ok(())
+        }
     };
 
     if let Err(err) = result {
-        println!("Error while handling message:");
-        println!("Msg: {:#?}", msg);
-        println!("Error: {:#?}", err);
+        state.event_log.log(
+            error_origin(&msg),
+            Event::Error {
+                message: msg,
+                error: format!("{:#}", err),
+            },
+        );
+    }
+}
+
+/// Attributes errors to the integration that failed to handle the message, if
+/// any
+fn error_origin(msg: &Message) -> EventOrigin {
+    match msg {
+        Message::SetIntegrationDeviceState { device, .. } => EventOrigin::Integration {
+            integration_id: device.integration_id.clone(),
+        },
+        Message::Action(Action::IntegrationAction(IntegrationActionDescriptor {
+            integration_id,
+            ..
+        })) => EventOrigin::Integration {
+            integration_id: integration_id.clone(),
+        },
+        _ => EventOrigin::Core,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use homectl_types::{
+        device::{DeviceId, DeviceState, OnOffDevice},
+        integration::IntegrationId,
+    };
+
+    fn action(json: serde_json::Value) -> Message {
+        Message::Action(serde_json::from_value(json).unwrap())
+    }
+
+    fn integration_id(origin: EventOrigin) -> Option<String> {
+        match origin {
+            EventOrigin::Integration { integration_id } => Some(integration_id.to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn attributes_device_state_errors_to_integration() {
+        let device = Device::new(
+            IntegrationId::from("hue1".to_string()),
+            DeviceId::new("lamp"),
+            "Lamp".to_string(),
+            DeviceState::OnOffDevice(OnOffDevice { power: true }),
+            None,
+        );
+        let msg = Message::SetIntegrationDeviceState {
+            device,
+            state_changed: true,
+        };
+
+        assert_eq!(integration_id(error_origin(&msg)), Some("hue1".to_string()));
+    }
+
+    #[test]
+    fn attributes_integration_action_errors_to_integration() {
+        let msg = action(serde_json::json!({
+            "action": "IntegrationAction",
+            "integration_id": "neato",
+            "payload": "clean_house",
+        }));
+
+        assert_eq!(
+            integration_id(error_origin(&msg)),
+            Some("neato".to_string())
+        );
+    }
+
+    #[test]
+    fn attributes_other_errors_to_core() {
+        let msg = action(serde_json::json!({
+            "action": "ActivateScene",
+            "scene_id": "evening",
+        }));
+
+        assert!(matches!(error_origin(&msg), EventOrigin::Core));
     }
 }
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use homectl_types::event::Message;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+use super::state::AppState;
+
+static MESSAGES_PROCESSED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "homectl_messages_processed_total",
+        "Number of messages processed, by message type",
+        &["message"]
+    )
+    .unwrap()
+});
+
+static CHANNEL_BACKLOG: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "homectl_channel_backlog",
+        "Number of messages waiting in the message channel"
+    )
+    .unwrap()
+});
+
+pub static MESSAGES_IN_FLIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "homectl_messages_in_flight",
+        "Number of messages whose handlers are still running"
+    )
+    .unwrap()
+});
+
+pub static INTEGRATION_SET_STATE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "homectl_integration_set_state_duration_seconds",
+        "Time taken by integrations to set device state",
+        &["integration_id"]
+    )
+    .unwrap()
+});
+
+pub static INTEGRATION_SET_STATE_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "homectl_integration_set_state_errors_total",
+        "Number of times integrations failed to set device state",
+        &["integration_id"]
+    )
+    .unwrap()
+});
+
+pub static DEVICE_STATE_CORRECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "homectl_device_state_corrections_total",
+        "Number of times a device was found in an unexpected state and corrected",
+        &["integration_id"]
+    )
+    .unwrap()
+});
+
+static WEBSOCKET_USERS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "homectl_websocket_users",
+        "Number of connected WebSocket peers"
+    )
+    .unwrap()
+});
+
+fn message_kind(msg: &Message) -> &'static str {
+    match msg {
+        Message::IntegrationDeviceRefresh { .. } => "IntegrationDeviceRefresh",
+        Message::DeviceUpdate { .. } => "DeviceUpdate",
+        Message::SetDeviceState { .. } => "SetDeviceState",
+        Message::SetIntegrationDeviceState { .. } => "SetIntegrationDeviceState",
+        Message::StoreScene { .. } => "StoreScene",
+        Message::DeleteScene { .. } => "DeleteScene",
+        Message::Action(_) => "Action",
+    }
+}
+
+pub fn record_message(msg: &Message) {
+    MESSAGES_PROCESSED
+        .with_label_values(&[message_kind(msg)])
+        .inc();
+}
+
+/// Samples gauges from current app state and renders all metrics in the
+/// Prometheus text format
+pub async fn render_metrics(app_state: &AppState) -> Result<String> {
+    CHANNEL_BACKLOG.set(app_state.sender.backlog() as i64);
+    WEBSOCKET_USERS.set(app_state.ws.num_users().await as i64);
+
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(String::from_utf8(buffer)?)
+}
@@ -1,9 +1,14 @@
+pub mod check_config;
 pub mod config;
 pub mod devices;
+pub mod event_log;
 pub mod groups;
 pub mod integrations;
 pub mod message;
+pub mod metrics;
+pub mod presence;
 pub mod rules;
 pub mod scenes;
 pub mod state;
+pub mod transitions;
 pub mod websockets;
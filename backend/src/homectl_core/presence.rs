@@ -0,0 +1,343 @@
+use homectl_types::{
+    device::{Device, DeviceId, DeviceState, DevicesState, SensorKind},
+    event::{Message, TxEventChannel},
+    group::GroupDeviceLink,
+    presence::{
+        person_device_id, presence_integration_id, room_device_id, someone_home_device_id,
+        PresenceConfig,
+    },
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+use super::devices::{find_device, Devices};
+
+/// How often occupancy timeouts are checked
+static PRESENCE_POLL_RATE: u64 = 1000;
+
+/// Phones often drop off the network for a few minutes while sleeping, which
+/// shouldn't be mistaken for their owner leaving
+static DEFAULT_PERSON_TIMEOUT_MS: u64 = 300_000;
+
+/// Motion sensors usually only stay active for a short while after detecting
+/// motion, while people may sit still for much longer
+static DEFAULT_ROOM_TIMEOUT_MS: u64 = 600_000;
+
+#[derive(Default)]
+struct PresenceState {
+    /// When devices of each person or room were last active, keyed by virtual
+    /// device id
+    last_active: HashMap<DeviceId, Instant>,
+
+    /// Most recently reported value of each virtual device
+    reported: HashMap<DeviceId, bool>,
+}
+
+impl PresenceState {
+    fn is_occupied(
+        &mut self,
+        device_id: &DeviceId,
+        devices: &DevicesState,
+        links: &[GroupDeviceLink],
+        timeout: Duration,
+        now: Instant,
+    ) -> bool {
+        let active = links
+            .iter()
+            .filter_map(|link| {
+                find_device(
+                    devices,
+                    &link.integration_id,
+                    link.device_id.as_ref(),
+                    link.name.as_ref(),
+                )
+            })
+            .any(|device| is_device_active(&device));
+
+        if active {
+            self.last_active.insert(device_id.clone(), now);
+        }
+
+        match self.last_active.get(device_id) {
+            Some(last_active) => now.duration_since(*last_active) <= timeout,
+            None => false,
+        }
+    }
+
+    /// Evaluates occupancy of each person and room, and whether anyone is
+    /// home. Returns the id, name and value of each virtual device.
+    fn evaluate(
+        &mut self,
+        config: &PresenceConfig,
+        devices: &DevicesState,
+        now: Instant,
+    ) -> Vec<(DeviceId, String, bool)> {
+        let mut occupancy: Vec<(DeviceId, String, bool)> = vec![];
+        let mut someone_home = false;
+
+        for (person_id, person) in config.people.iter().flatten() {
+            let device_id = person_device_id(person_id);
+            let timeout = person.timeout_ms.unwrap_or(DEFAULT_PERSON_TIMEOUT_MS);
+            let home = self.is_occupied(
+                &device_id,
+                devices,
+                &person.devices,
+                Duration::from_millis(timeout),
+                now,
+            );
+
+            someone_home |= home;
+            occupancy.push((device_id, person.name.clone(), home));
+        }
+
+        occupancy.push((
+            someone_home_device_id(),
+            "Someone home".to_string(),
+            someone_home,
+        ));
+
+        for (room_id, room) in config.rooms.iter().flatten() {
+            let device_id = room_device_id(room_id);
+            let timeout = room.timeout_ms.unwrap_or(DEFAULT_ROOM_TIMEOUT_MS);
+            let occupied = self.is_occupied(
+                &device_id,
+                devices,
+                &room.devices,
+                Duration::from_millis(timeout),
+                now,
+            );
+
+            occupancy.push((device_id, room.name.clone(), occupied));
+        }
+
+        occupancy
+    }
+}
+
+/// Whether a device currently signals that someone is around, e.g. a motion
+/// sensor detecting motion, a reachable phone or a manually toggled switch
+fn is_device_active(device: &Device) -> bool {
+    match device.state {
+        DeviceState::Sensor(SensorKind::OnOffSensor { value }) => value,
+        DeviceState::Sensor(SensorKind::DimmerSwitch { on, up, down, off }) => {
+            on || up || down || off
+        }
+        DeviceState::OnOffDevice(device) => device.power,
+        _ => false,
+    }
+}
+
+/// Maps device level signals into per-person and per-room occupancy, which
+/// are exposed as virtual sensor devices under the "presence" integration id
+#[derive(Clone)]
+pub struct Presence {
+    config: Arc<RwLock<PresenceConfig>>,
+    state: Arc<Mutex<PresenceState>>,
+    sender: TxEventChannel,
+}
+
+impl Presence {
+    pub fn new(config: PresenceConfig, sender: TxEventChannel) -> Self {
+        Presence {
+            config: Arc::new(RwLock::new(config)),
+            state: Default::default(),
+            sender,
+        }
+    }
+
+    /// Replaces presence config, e.g. after the config file has been reloaded
+    pub fn set_config(&self, config: PresenceConfig) {
+        *self.config.write().unwrap() = config;
+
+        // Make sure all virtual devices are reported again
+        self.state.lock().unwrap().reported.clear();
+    }
+
+    /// Periodically re-evaluates occupancy, so that people and rooms time out
+    /// even without any device updates
+    pub fn start(&self, devices: Devices) {
+        let presence = self.clone();
+
+        tokio::spawn(async move {
+            let poll_rate = Duration::from_millis(PRESENCE_POLL_RATE);
+            let mut interval = time::interval(poll_rate);
+
+            loop {
+                interval.tick().await;
+
+                presence.update(&devices.get_devices());
+            }
+        });
+    }
+
+    pub fn handle_device_update(&self, devices: &DevicesState) {
+        self.update(devices);
+    }
+
+    /// Evaluates occupancy and reports virtual devices whose value has changed
+    fn update(&self, devices: &DevicesState) {
+        let config = self.config.read().unwrap().clone();
+        let mut state = self.state.lock().unwrap();
+        let occupancy = state.evaluate(&config, devices, Instant::now());
+
+        for (device_id, name, value) in occupancy {
+            if state.reported.insert(device_id.clone(), value) == Some(value) {
+                continue;
+            }
+
+            let device = Device {
+                id: device_id,
+                name,
+                integration_id: presence_integration_id(),
+                scene: None,
+                state: DeviceState::Sensor(SensorKind::OnOffSensor { value }),
+                capabilities: None,
+                availability: Default::default(),
+                manual_override: None,
+            };
+
+            self.sender
+                .send(Message::IntegrationDeviceRefresh { device });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use homectl_types::{device::OnOffDevice, integration::IntegrationId};
+
+    fn mk_devices(active: &[(&str, bool)]) -> DevicesState {
+        DevicesState(
+            active
+                .iter()
+                .map(|(name, power)| {
+                    let device = Device::new(
+                        IntegrationId::from("ping".to_string()),
+                        DeviceId::new(name),
+                        name.to_string(),
+                        DeviceState::OnOffDevice(OnOffDevice { power: *power }),
+                        None,
+                    );
+
+                    (device.get_device_key(), device)
+                })
+                .collect(),
+        )
+    }
+
+    fn mk_config() -> PresenceConfig {
+        serde_json::from_value(serde_json::json!({
+            "people": {
+                "alice": {
+                    "name": "Alice",
+                    "devices": [{ "integration_id": "ping", "name": "alice_phone" }],
+                },
+                "bob": {
+                    "name": "Bob",
+                    "devices": [{ "integration_id": "ping", "name": "bob_phone" }],
+                    "timeout_ms": 0,
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn links(name: &str) -> Vec<GroupDeviceLink> {
+        serde_json::from_value(serde_json::json!([{ "integration_id": "ping", "name": name }]))
+            .unwrap()
+    }
+
+    fn value(occupancy: &[(DeviceId, String, bool)], name: &str) -> bool {
+        occupancy
+            .iter()
+            .find(|(_, candidate, _)| candidate == name)
+            .map(|(_, _, value)| *value)
+            .unwrap()
+    }
+
+    #[test]
+    fn is_occupied_while_devices_are_active() {
+        let mut state = PresenceState::default();
+        let device_id = DeviceId::new("room");
+        let other_id = DeviceId::new("other_room");
+        let timeout = Duration::from_secs(60);
+        let now = Instant::now();
+
+        let inactive = mk_devices(&[("phone", false)]);
+        let active = mk_devices(&[("phone", true)]);
+
+        assert!(!state.is_occupied(&device_id, &inactive, &links("phone"), timeout, now));
+        assert!(state.is_occupied(&device_id, &active, &links("phone"), timeout, now));
+        assert!(!state.is_occupied(&other_id, &active, &links("missing"), timeout, now));
+    }
+
+    #[test]
+    fn is_occupied_until_timeout_expires() {
+        let mut state = PresenceState::default();
+        let device_id = DeviceId::new("room");
+        let timeout = Duration::from_secs(60);
+        let start = Instant::now();
+
+        let active = mk_devices(&[("phone", true)]);
+        let inactive = mk_devices(&[("phone", false)]);
+
+        assert!(state.is_occupied(&device_id, &active, &links("phone"), timeout, start));
+
+        let later = start + Duration::from_secs(60);
+        assert!(state.is_occupied(&device_id, &inactive, &links("phone"), timeout, later));
+
+        let expired = start + Duration::from_secs(61);
+        assert!(!state.is_occupied(&device_id, &inactive, &links("phone"), timeout, expired));
+    }
+
+    #[test]
+    fn someone_home_while_anyone_is_home() {
+        let mut state = PresenceState::default();
+        let config = mk_config();
+        let start = Instant::now();
+
+        let occupancy = state.evaluate(&config, &mk_devices(&[]), start);
+        assert!(!value(&occupancy, "Alice"));
+        assert!(!value(&occupancy, "Bob"));
+        assert!(!value(&occupancy, "Someone home"));
+
+        let devices = mk_devices(&[("alice_phone", false), ("bob_phone", true)]);
+        let occupancy = state.evaluate(&config, &devices, start);
+        assert!(!value(&occupancy, "Alice"));
+        assert!(value(&occupancy, "Bob"));
+        assert!(value(&occupancy, "Someone home"));
+
+        // Bob has no timeout, so he's away as soon as his phone is
+        let devices = mk_devices(&[("alice_phone", true), ("bob_phone", false)]);
+        let occupancy = state.evaluate(&config, &devices, start + Duration::from_secs(1));
+        assert!(value(&occupancy, "Alice"));
+        assert!(!value(&occupancy, "Bob"));
+        assert!(value(&occupancy, "Someone home"));
+    }
+
+    #[test]
+    fn people_use_default_timeout() {
+        let mut state = PresenceState::default();
+        let config = mk_config();
+        let start = Instant::now();
+
+        let devices = mk_devices(&[("alice_phone", true)]);
+        state.evaluate(&config, &devices, start);
+
+        let devices = mk_devices(&[("alice_phone", false)]);
+        let timeout = Duration::from_millis(DEFAULT_PERSON_TIMEOUT_MS);
+
+        let occupancy = state.evaluate(&config, &devices, start + timeout);
+        assert!(value(&occupancy, "Alice"));
+        assert!(value(&occupancy, "Someone home"));
+
+        let occupancy = state.evaluate(&config, &devices, start + timeout * 2);
+        assert!(!value(&occupancy, "Alice"));
+        assert!(!value(&occupancy, "Someone home"));
+    }
+}
@@ -1,31 +1,86 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Timelike};
 use homectl_types::{
-    action::{Action, Actions},
-    device::{Device, DeviceState, DevicesState, SensorKind},
+    action::Action,
+    device::{Device, DeviceId, DeviceState, DevicesState, SensorKind},
     event::{Message, TxEventChannel},
-    rule::{DeviceRule, GroupRule, Routine, RoutineId, RoutinesConfig, Rule, SensorRuleState},
+    event_log::{Event, EventOrigin},
+    group::GroupDeviceLink,
+    presence::{person_device_id, presence_integration_id, room_device_id, someone_home_device_id},
+    rule::{
+        AllRule, AnyRule, DeviceRule, GroupRule, NotRule, NumericSensorRuleState, PersonRule,
+        RoomRule, Routine, RoutineId, RoutinesConfig, Rule, ScheduleRule, SensorRuleState,
+        SomeoneHomeRule, TimeWindowRule,
+    },
+    scene::{SceneDescriptor, SceneId},
+    solar::{Location, TimeOfDay},
 };
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::time;
 
 use crate::homectl_core::devices::find_device;
 
-use super::groups::Groups;
+use super::{devices::Devices, event_log::EventLog, groups::Groups};
+
+/// How often routines are evaluated against the current time
+static SCHEDULER_POLL_RATE: u64 = 1000;
+
+/// How far back the scheduler catches up on minutes it didn't get to
+/// evaluate, e.g. after the system has been suspended
+static SCHEDULER_MAX_CATCH_UP_MINUTES: i64 = 24 * 60;
+
+#[derive(Default)]
+struct MotionLightingState {
+    /// Whether lights were turned on by the routine and are still in its
+    /// `on_scene`
+    lights_on: bool,
+
+    /// When to turn lights off, set once motion is no longer detected
+    off_at: Option<Instant>,
+}
 
 #[derive(Clone)]
 pub struct Rules {
-    config: RoutinesConfig,
+    config: Arc<RwLock<RoutinesConfig>>,
+    location: Arc<RwLock<Option<Location>>>,
     sender: TxEventChannel,
     groups: Groups,
+    event_log: EventLog,
+    motion_lighting_states: Arc<Mutex<HashMap<RoutineId, MotionLightingState>>>,
 }
 
 impl Rules {
-    pub fn new(config: RoutinesConfig, groups: Groups, sender: TxEventChannel) -> Self {
+    pub fn new(
+        config: RoutinesConfig,
+        location: Option<Location>,
+        groups: Groups,
+        sender: TxEventChannel,
+        event_log: EventLog,
+    ) -> Self {
         Rules {
-            config,
+            config: Arc::new(RwLock::new(config)),
+            location: Arc::new(RwLock::new(location)),
             sender,
             groups,
+            event_log,
+            motion_lighting_states: Default::default(),
         }
     }
 
+    pub fn get_routines(&self) -> RoutinesConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replaces routines and location, e.g. after the config file has been
+    /// reloaded
+    pub fn set_config(&self, config: RoutinesConfig, location: Option<Location>) {
+        *self.config.write().unwrap() = config;
+        *self.location.write().unwrap() = location;
+    }
+
     pub async fn handle_device_update(
         &self,
         old_state: &DevicesState,
@@ -33,58 +88,337 @@ impl Rules {
         old: &Option<Device>,
         _new: &Device,
     ) {
+        self.handle_motion_lighting(old_state, new_state, Instant::now())
+            .await;
+
         match old {
             Some(_) => {
                 // println!("device_updated {:?} (was: {:?})", new, old);
 
-                let matching_actions = self.find_matching_actions(old_state, new_state);
+                let matching_routines = self.find_matching_routines(old_state, new_state);
+                let time = chrono::Local::now().naive_local();
 
-                for action in matching_actions {
-                    self.run_action(&action).await;
+                for (routine_id, routine) in matching_routines {
+                    self.run_routine(routine_id, routine, new_state, &time)
+                        .await;
                 }
             }
             None => {}
         }
     }
 
-    async fn run_action(&self, action: &Action) {
-        self.sender.send(Message::Action(action.clone()));
+    /// Periodically evaluates routines against the current time, so that
+    /// routines with schedule rules can trigger without any device updates.
+    pub fn start_scheduler(&self, devices: Devices) {
+        let rules = self.clone();
+
+        tokio::spawn(async move {
+            let poll_rate = Duration::from_millis(SCHEDULER_POLL_RATE);
+            let mut interval = time::interval(poll_rate);
+            let mut prev_time = chrono::Local::now().naive_local();
+
+            loop {
+                interval.tick().await;
+
+                let time = chrono::Local::now().naive_local();
+                let state = devices.get_devices();
+
+                for (minute, routine_id, routine) in
+                    rules.find_scheduled_routines(&state, &prev_time, &time)
+                {
+                    rules
+                        .run_routine(routine_id, routine, &state, &minute)
+                        .await;
+                }
+
+                rules
+                    .handle_motion_lighting_timeouts(&state, Instant::now())
+                    .await;
+
+                prev_time = time;
+            }
+        });
+    }
+
+    /// Turns on lights of motion lighting routines when motion is detected,
+    /// and starts their timeouts once motion is no longer detected. Lights
+    /// that someone else has turned on are left alone.
+    async fn handle_motion_lighting(
+        &self,
+        old_state: &DevicesState,
+        new_state: &DevicesState,
+        now: Instant,
+    ) {
+        let routines: Vec<(RoutineId, Routine)> = self
+            .config
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, routine)| routine.motion_lighting.is_some())
+            .map(|(routine_id, routine)| (routine_id.clone(), routine.clone()))
+            .collect();
+
+        let location = *self.location.read().unwrap();
+        let time = chrono::Local::now().naive_local();
+        let mut triggered = vec![];
+
+        {
+            let mut states = self.motion_lighting_states.lock().unwrap();
+
+            for (routine_id, routine) in routines {
+                let motion_lighting = match &routine.motion_lighting {
+                    Some(motion_lighting) => motion_lighting,
+                    None => continue,
+                };
+
+                let motion = is_motion_detected(new_state, &motion_lighting.sensors);
+                if motion == is_motion_detected(old_state, &motion_lighting.sensors) {
+                    continue;
+                }
+
+                let state = states.entry(routine_id.clone()).or_default();
+
+                if !motion {
+                    if state.lights_on {
+                        let timeout = Duration::from_millis(motion_lighting.timeout_ms);
+                        state.off_at = Some(now + timeout);
+                    }
+
+                    continue;
+                }
+
+                // New motion restarts the timeout
+                state.off_at = None;
+
+                let devices = self
+                    .groups
+                    .find_group_devices(new_state, &motion_lighting.group_id);
+
+                if is_in_scene(&devices, &motion_lighting.on_scene) {
+                    state.lights_on = true;
+                    continue;
+                }
+
+                // Someone else has turned on the lights
+                let in_use = devices
+                    .iter()
+                    .any(|device| device.state.is_powered_on() == Some(true));
+
+                // Rules act as additional conditions for turning lights on
+                let conditions_met = is_routine_triggered(
+                    new_state,
+                    &self.groups,
+                    location.as_ref(),
+                    &routine,
+                    &time,
+                ) == Ok(true);
+
+                state.lights_on = !in_use && conditions_met;
+
+                if state.lights_on {
+                    let action = activate_group_scene(&devices, &motion_lighting.on_scene);
+                    triggered.push((routine_id, routine.clone(), action));
+                }
+            }
+        }
+
+        for (routine_id, routine, action) in triggered {
+            let routine = Routine {
+                actions: vec![action],
+                ..routine
+            };
+
+            self.run_routine(routine_id, routine, new_state, &time)
+                .await;
+        }
+    }
+
+    /// Turns off lights of motion lighting routines whose timeout has
+    /// elapsed, unless someone has changed the scene of the lights since they
+    /// were turned on
+    async fn handle_motion_lighting_timeouts(&self, state: &DevicesState, now: Instant) {
+        let mut triggered = vec![];
+
+        {
+            let config = self.config.read().unwrap();
+            let mut states = self.motion_lighting_states.lock().unwrap();
+
+            for (routine_id, motion_lighting_state) in states.iter_mut() {
+                match motion_lighting_state.off_at {
+                    Some(off_at) if off_at <= now => {}
+                    _ => continue,
+                }
+
+                motion_lighting_state.off_at = None;
+                motion_lighting_state.lights_on = false;
+
+                let routine = config.get(routine_id);
+                let motion_lighting = routine.and_then(|routine| routine.motion_lighting.as_ref());
+
+                if let (Some(routine), Some(motion_lighting)) = (routine, motion_lighting) {
+                    let devices = self
+                        .groups
+                        .find_group_devices(state, &motion_lighting.group_id);
+
+                    if is_in_scene(&devices, &motion_lighting.on_scene) {
+                        let action = activate_group_scene(&devices, &motion_lighting.off_scene);
+                        triggered.push((routine_id.clone(), routine.clone(), action));
+                    }
+                }
+            }
+        }
+
+        let time = chrono::Local::now().naive_local();
+
+        for (routine_id, routine, action) in triggered {
+            let routine = Routine {
+                actions: vec![action],
+                ..routine
+            };
+
+            self.run_routine(routine_id, routine, state, &time).await;
+        }
+    }
+
+    /// Runs the actions of a routine and logs which of its rules were
+    /// triggered in given state
+    async fn run_routine(
+        &self,
+        routine_id: RoutineId,
+        routine: Routine,
+        state: &DevicesState,
+        time: &NaiveDateTime,
+    ) {
+        for action in &routine.actions {
+            self.sender.send(Message::Action(action.clone()));
+        }
+
+        let location = *self.location.read().unwrap();
+        let triggered_rules = routine
+            .rules
+            .into_iter()
+            .filter(|rule| {
+                is_rule_triggered(state, &self.groups, location.as_ref(), rule, time) == Ok(true)
+            })
+            .collect();
+
+        self.event_log.log(
+            EventOrigin::Routine {
+                routine_id: routine_id.clone(),
+            },
+            Event::RoutineTriggered {
+                routine_id,
+                name: routine.name,
+                rules: triggered_rules,
+                actions: routine.actions,
+            },
+        );
     }
 
-    fn find_matching_actions(&self, old_state: &DevicesState, new_state: &DevicesState) -> Actions {
+    fn find_matching_routines(
+        &self,
+        old_state: &DevicesState,
+        new_state: &DevicesState,
+    ) -> Vec<(RoutineId, Routine)> {
         // if states are equal we can bail out early
         if old_state == new_state {
             return vec![];
         }
 
-        let prev_triggered_routine_ids =
-            get_triggered_routine_ids(&self.config, &self.groups, old_state);
-        let new_triggered_routine_ids =
-            get_triggered_routine_ids(&self.config, &self.groups, new_state);
+        let time = chrono::Local::now().naive_local();
+
+        self.find_triggered_routines(old_state, &time, new_state, &time)
+    }
+
+    /// Finds routines triggered by the passing of time since prev_time, along
+    /// with the minute at which they were triggered. Every minute since
+    /// prev_time is evaluated, so that schedules aren't skipped if the
+    /// scheduler falls behind.
+    fn find_scheduled_routines(
+        &self,
+        state: &DevicesState,
+        prev_time: &NaiveDateTime,
+        time: &NaiveDateTime,
+    ) -> Vec<(NaiveDateTime, RoutineId, Routine)> {
+        let one_minute = chrono::Duration::minutes(1);
+        let catch_up_start = truncate_to_minute(time)
+            - chrono::Duration::minutes(SCHEDULER_MAX_CATCH_UP_MINUTES - 1);
+
+        // schedule rules have minute precision, nothing can change within the
+        // same minute
+        let mut minute = std::cmp::max(truncate_to_minute(prev_time) + one_minute, catch_up_start);
+        let mut triggered = vec![];
+
+        while minute <= *time {
+            let prev_minute = minute - one_minute;
+
+            for (routine_id, routine) in
+                self.find_triggered_routines(state, &prev_minute, state, &minute)
+            {
+                triggered.push((minute, routine_id, routine));
+            }
+
+            minute += one_minute;
+        }
+
+        triggered
+    }
+
+    fn find_triggered_routines(
+        &self,
+        old_state: &DevicesState,
+        old_time: &NaiveDateTime,
+        new_state: &DevicesState,
+        new_time: &NaiveDateTime,
+    ) -> Vec<(RoutineId, Routine)> {
+        let config = self.config.read().unwrap();
+        let location = *self.location.read().unwrap();
+
+        let prev_triggered_routine_ids = get_triggered_routine_ids(
+            &config,
+            &self.groups,
+            location.as_ref(),
+            old_state,
+            old_time,
+        );
+        let new_triggered_routine_ids = get_triggered_routine_ids(
+            &config,
+            &self.groups,
+            location.as_ref(),
+            new_state,
+            new_time,
+        );
 
         let triggered_routine_ids =
             new_triggered_routine_ids.difference(&prev_triggered_routine_ids);
 
-        triggered_routine_ids.flat_map(|id| {
-                let routine = self
-                    .config
+        triggered_routine_ids.map(|id| {
+                let routine = config
                     .get(id)
                     .expect("Expected triggered_routine_ids to only contain ids of routines existing in the RoutinesConfig");
-                routine.actions.clone()
+                (id.clone(), routine.clone())
             })
             .collect()
     }
 }
 
+fn truncate_to_minute(time: &NaiveDateTime) -> NaiveDateTime {
+    time.date().and_hms(time.hour(), time.minute(), 0)
+}
+
 fn get_triggered_routine_ids(
     routines: &RoutinesConfig,
     groups: &Groups,
+    location: Option<&Location>,
     state: &DevicesState,
+    time: &NaiveDateTime,
 ) -> HashSet<RoutineId> {
     let triggered_routine_ids: HashSet<RoutineId> = routines
         .iter()
+        // Motion lighting routines are handled separately
+        .filter(|(_, routine)| routine.motion_lighting.is_none())
         .filter(
-            |(_, routine)| match is_routine_triggered(state, groups, routine) {
+            |(_, routine)| match is_routine_triggered(state, groups, location, routine, time) {
                 Ok(triggered) => triggered,
                 Err(e) => {
                     println!("Error while checking routine {:?} rules: {}", routine, e);
@@ -102,17 +436,54 @@ fn is_routine_triggered(
     state: &DevicesState,
 
     groups: &Groups,
+    location: Option<&Location>,
     routine: &Routine,
+    time: &NaiveDateTime,
 ) -> Result<bool, String> {
     let result = routine
         .rules
         .iter()
-        .map(|rule| is_rule_triggered(state, groups, rule))
+        .map(|rule| is_rule_triggered(state, groups, location, rule, time))
         .all(|result| result == Ok(true));
 
     Ok(result)
 }
 
+fn is_motion_detected(state: &DevicesState, sensors: &[GroupDeviceLink]) -> bool {
+    sensors
+        .iter()
+        .filter_map(|link| {
+            find_device(
+                state,
+                &link.integration_id,
+                link.device_id.as_ref(),
+                link.name.as_ref(),
+            )
+        })
+        .any(|device| {
+            matches!(
+                device.state,
+                DeviceState::Sensor(SensorKind::OnOffSensor { value: true })
+            )
+        })
+}
+
+/// Whether all given devices have given scene active
+fn is_in_scene(devices: &[Device], scene_id: &SceneId) -> bool {
+    !devices.is_empty()
+        && devices
+            .iter()
+            .all(|device| device.get_scene_id() == Some(scene_id))
+}
+
+/// Activates a scene for given devices only
+fn activate_group_scene(devices: &[Device], scene_id: &SceneId) -> Action {
+    Action::ActivateScene(SceneDescriptor {
+        scene_id: scene_id.clone(),
+        device_keys: Some(devices.iter().map(Device::get_device_key).collect()),
+    })
+}
+
 fn get_device_sensor_kind(device: &Device) -> Option<SensorKind> {
     match device.state {
         DeviceState::Sensor(sensor_kind) => Some(sensor_kind),
@@ -120,6 +491,28 @@ fn get_device_sensor_kind(device: &Device) -> Option<SensorKind> {
     }
 }
 
+fn compare_numeric_sensor_value(rule_state: &NumericSensorRuleState, value: f32) -> bool {
+    if let Some(above) = rule_state.above {
+        if value <= above {
+            return false;
+        }
+    }
+
+    if let Some(below) = rule_state.below {
+        if value >= below {
+            return false;
+        }
+    }
+
+    if let Some((min, max)) = rule_state.between {
+        if value < min || value > max {
+            return false;
+        }
+    }
+
+    true
+}
+
 fn compare_rule_device_state(rule: &Rule, device: &Device) -> Result<bool, String> {
     let sensor_kind = get_device_sensor_kind(device);
 
@@ -189,29 +582,195 @@ fn compare_rule_device_state(rule: &Rule, device: &Device) -> Result<bool, Strin
                         off: sensor_off,
                     }),
                 ) => Ok(rule_off == sensor_off),
+                (SensorRuleState::NumericSensor(rule_state), Some(sensor_kind)) => {
+                    let value = sensor_kind.get_numeric_value().ok_or(format!(
+                        "Numeric sensor rule {:?} used on non-numeric sensor {:?}",
+                        rule_state, sensor_kind
+                    ))?;
+
+                    Ok(compare_numeric_sensor_value(&rule_state, value))
+                }
                 (rule, sensor) => Err(format!(
                     "Unknown sensor states encountered when processing rule {:?}. (sensor: {:?})",
                     rule, sensor,
                 )),
             }
         }
-        Rule::Group(GroupRule { scene, power, .. })
-        | Rule::Device(DeviceRule { scene, power, .. }) => {
+        Rule::Group(GroupRule {
+            scene,
+            power,
+            online,
+            manually_overridden,
+            ..
+        })
+        | Rule::Device(DeviceRule {
+            scene,
+            power,
+            online,
+            manually_overridden,
+            ..
+        }) =>
+        {
             #[allow(clippy::if_same_then_else)]
             if scene.is_some() && scene.as_ref() != device.scene.as_ref().map(|s| &s.scene_id) {
                 Ok(false)
             } else if power.is_some() && power != &device.state.is_powered_on() {
                 Ok(false)
+            } else if online.is_some() && online != &Some(device.availability.online) {
+                Ok(false)
+            } else if manually_overridden.is_some()
+                && manually_overridden != &Some(device.manual_override.is_some())
+            {
+                Ok(false)
             } else {
                 Ok(true)
             }
         }
+        Rule::SomeoneHome(_)
+        | Rule::Person(_)
+        | Rule::Room(_)
+        | Rule::Schedule(_)
+        | Rule::TimeWindow(_)
+        | Rule::Any(_)
+        | Rule::All(_)
+        | Rule::Not(_) => Err(format!(
+            "Rule can't be compared against the state of a single device. (rule: {:?})",
+            rule
+        )),
     }
 }
 
-fn is_rule_triggered(state: &DevicesState, groups: &Groups, rule: &Rule) -> Result<bool, String> {
+fn is_schedule_triggered(
+    rule: &ScheduleRule,
+    location: Option<&Location>,
+    time: &NaiveDateTime,
+) -> Result<bool, String> {
+    if let Some(weekdays) = &rule.weekdays {
+        if !weekdays.contains(&time.weekday()) {
+            return Ok(false);
+        }
+    }
+
+    let scheduled_time = resolve_time_of_day(&rule.time, location, time)?;
+
+    Ok(time.hour() == scheduled_time.hour() && time.minute() == scheduled_time.minute())
+}
+
+fn is_time_window_triggered(
+    rule: &TimeWindowRule,
+    location: Option<&Location>,
+    time: &NaiveDateTime,
+) -> Result<bool, String> {
+    if let Some(weekdays) = &rule.weekdays {
+        if !weekdays.contains(&time.weekday()) {
+            return Ok(false);
+        }
+    }
+
+    let resolve = |time_of_day: &Option<TimeOfDay>| {
+        time_of_day
+            .as_ref()
+            .map(|time_of_day| resolve_time_of_day(time_of_day, location, time))
+            .transpose()
+    };
+    let after = resolve(&rule.after)?;
+    let before = resolve(&rule.before)?;
+    let now = time.time();
+
+    let is_after = match after {
+        Some(after) => now >= after,
+        None => true,
+    };
+    let is_before = match before {
+        Some(before) => now < before,
+        None => true,
+    };
+
+    match (after, before) {
+        // Window spans midnight
+        (Some(after), Some(before)) if after > before => Ok(is_after || is_before),
+        _ => Ok(is_after && is_before),
+    }
+}
+
+/// Resolves given TimeOfDay on the date of given time
+fn resolve_time_of_day(
+    time_of_day: &TimeOfDay,
+    location: Option<&Location>,
+    time: &NaiveDateTime,
+) -> Result<NaiveTime, String> {
+    match (time_of_day, location) {
+        (TimeOfDay::Fixed(fixed_time), _) => Ok(*fixed_time),
+        (time_of_day, Some(location)) => time_of_day.resolve(time.date(), location).ok_or(format!(
+            "{} does not occur on {}",
+            time_of_day,
+            time.date()
+        )),
+        (time_of_day, None) => Err(format!(
+            "Cannot resolve {} without a configured location",
+            time_of_day
+        )),
+    }
+}
+
+/// Presence rules are evaluated against the virtual sensor devices exposed by
+/// the presence subsystem
+fn get_presence_value(state: &DevicesState, device_id: &DeviceId) -> Result<bool, String> {
+    let device = find_device(state, &presence_integration_id(), Some(device_id), None)
+        .ok_or(format!("Could not find presence sensor {}", device_id))?;
+
+    match device.state {
+        DeviceState::Sensor(SensorKind::OnOffSensor { value }) => Ok(value),
+        state => Err(format!(
+            "Unexpected state for presence sensor {}: {:?}",
+            device_id, state
+        )),
+    }
+}
+
+fn is_rule_triggered(
+    state: &DevicesState,
+    groups: &Groups,
+    location: Option<&Location>,
+    rule: &Rule,
+    time: &NaiveDateTime,
+) -> Result<bool, String> {
     // Try finding matching device
     let devices = match rule {
+        // Schedule rules don't depend on any devices
+        Rule::Schedule(rule) => return is_schedule_triggered(rule, location, time),
+        Rule::TimeWindow(rule) => return is_time_window_triggered(rule, location, time),
+
+        Rule::SomeoneHome(SomeoneHomeRule { someone_home }) => {
+            return Ok(get_presence_value(state, &someone_home_device_id())? == *someone_home)
+        }
+        Rule::Person(PersonRule { person, home }) => {
+            return Ok(get_presence_value(state, &person_device_id(person))? == *home)
+        }
+        Rule::Room(RoomRule {
+            room,
+            room_occupied,
+        }) => return Ok(get_presence_value(state, &room_device_id(room))? == *room_occupied),
+
+        // Combinators evaluate their contained rules recursively
+        Rule::Any(AnyRule { any }) => {
+            return Ok(any
+                .iter()
+                .any(|rule| is_rule_triggered(state, groups, location, rule, time) == Ok(true)))
+        }
+        Rule::All(AllRule { all }) => {
+            return Ok(all
+                .iter()
+                .all(|rule| is_rule_triggered(state, groups, location, rule, time) == Ok(true)))
+        }
+        // Unlike within any and all rules, errors can't be treated as the
+        // contained rule not being triggered, as that would trigger the not
+        // rule. Propagate them instead so that e.g. a misspelled device name
+        // doesn't trigger the routine.
+        Rule::Not(NotRule { not }) => {
+            return Ok(!is_rule_triggered(state, groups, location, not, time)?)
+        }
+
         Rule::Sensor(rule) => {
             vec![find_device(
                 state,
@@ -268,3 +827,503 @@ fn is_rule_triggered(state: &DevicesState, groups: &Groups, rule: &Rule) -> Resu
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::homectl_core::websockets::WebSockets;
+    use chrono::NaiveDate;
+    use homectl_types::{
+        device::{DeviceSceneState, OnOffDevice},
+        event::{mk_channel, RxEventChannel},
+        integration::IntegrationId,
+    };
+
+    fn mk_rules(routines: Vec<(&str, Vec<Rule>)>) -> Rules {
+        let config = routines
+            .into_iter()
+            .map(|(routine_id, rules)| {
+                let routine = Routine {
+                    name: routine_id.to_string(),
+                    rules,
+                    actions: vec![],
+                    motion_lighting: None,
+                };
+
+                (serde_json::from_value(routine_id.into()).unwrap(), routine)
+            })
+            .collect();
+
+        let (sender, _) = mk_channel();
+
+        Rules::new(
+            config,
+            None,
+            Groups::new(Default::default()),
+            sender,
+            EventLog::new(WebSockets::default()),
+        )
+    }
+
+    fn schedule(time: &str) -> Rule {
+        Rule::Schedule(ScheduleRule {
+            time: time.parse().unwrap(),
+            weekdays: None,
+        })
+    }
+
+    fn rule(json: &str) -> Rule {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// A motion sensor that has detected motion and a lamp that is turned off
+    fn mk_state() -> DevicesState {
+        let integration_id = IntegrationId::from("hue1".to_string());
+        let devices = [
+            Device::new(
+                integration_id.clone(),
+                DeviceId::new("motion"),
+                "Motion sensor".to_string(),
+                DeviceState::Sensor(SensorKind::OnOffSensor { value: true }),
+                None,
+            ),
+            Device::new(
+                integration_id,
+                DeviceId::new("lamp"),
+                "Lamp".to_string(),
+                DeviceState::OnOffDevice(OnOffDevice { power: false }),
+                None,
+            ),
+        ];
+
+        DevicesState(
+            devices
+                .iter()
+                .map(|device| (device.get_device_key(), device.clone()))
+                .collect(),
+        )
+    }
+
+    const TRIGGERED: &str =
+        r#"{ "integration_id": "hue1", "name": "Motion sensor", "state": { "value": true } }"#;
+    const UNTRIGGERED: &str = r#"{ "integration_id": "hue1", "name": "Lamp", "power": true }"#;
+    const ERROR: &str = r#"{ "integration_id": "hue1", "name": "Misspelled lamp", "power": true }"#;
+
+    fn evaluate(json: &str) -> Result<bool, String> {
+        let groups = Groups::new(Default::default());
+
+        is_rule_triggered(&mk_state(), &groups, None, &rule(json), &at(1, 12, 0, 0))
+    }
+
+    #[test]
+    fn evaluates_leaf_rules() {
+        assert_eq!(evaluate(TRIGGERED), Ok(true));
+        assert_eq!(evaluate(UNTRIGGERED), Ok(false));
+        assert!(evaluate(ERROR).is_err());
+    }
+
+    #[test]
+    fn any_rule() {
+        let any = |rules: &[&str]| evaluate(&format!(r#"{{ "any": [{}] }}"#, rules.join(",")));
+
+        assert_eq!(any(&[TRIGGERED, UNTRIGGERED]), Ok(true));
+        assert_eq!(any(&[UNTRIGGERED, UNTRIGGERED]), Ok(false));
+        assert_eq!(any(&[]), Ok(false));
+
+        // Errors count as not triggered
+        assert_eq!(any(&[ERROR, TRIGGERED]), Ok(true));
+        assert_eq!(any(&[ERROR]), Ok(false));
+    }
+
+    #[test]
+    fn all_rule() {
+        let all = |rules: &[&str]| evaluate(&format!(r#"{{ "all": [{}] }}"#, rules.join(",")));
+
+        assert_eq!(all(&[TRIGGERED, TRIGGERED]), Ok(true));
+        assert_eq!(all(&[TRIGGERED, UNTRIGGERED]), Ok(false));
+        assert_eq!(all(&[]), Ok(true));
+
+        // Errors count as not triggered
+        assert_eq!(all(&[TRIGGERED, ERROR]), Ok(false));
+    }
+
+    #[test]
+    fn not_rule() {
+        let not = |rule: &str| evaluate(&format!(r#"{{ "not": {} }}"#, rule));
+
+        assert_eq!(not(TRIGGERED), Ok(false));
+        assert_eq!(not(UNTRIGGERED), Ok(true));
+
+        // Errors must not trigger the not rule
+        assert!(not(ERROR).is_err());
+    }
+
+    #[test]
+    fn nested_rules() {
+        let not_error = format!(r#"{{ "not": {} }}"#, ERROR);
+        let not_untriggered = format!(r#"{{ "not": {} }}"#, UNTRIGGERED);
+
+        assert_eq!(
+            evaluate(&format!(
+                r#"{{ "any": [{}, {}] }}"#,
+                not_error, not_untriggered
+            )),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate(&format!(r#"{{ "all": [{}, {}] }}"#, TRIGGERED, not_error)),
+            Ok(false)
+        );
+        assert_eq!(
+            evaluate(&format!(
+                r#"{{ "not": {{ "any": [{}, {}] }} }}"#,
+                UNTRIGGERED, not_untriggered
+            )),
+            Ok(false)
+        );
+    }
+
+    fn sensor_rule_state(json: &str) -> serde_json::Result<SensorRuleState> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn parses_sensor_rule_states() {
+        assert!(matches!(
+            sensor_rule_state(r#"{ "value": true }"#),
+            Ok(SensorRuleState::OnOffSensor { value: true })
+        ));
+        assert!(matches!(
+            sensor_rule_state(r#"{ "below": 40 }"#),
+            Ok(SensorRuleState::NumericSensor(_))
+        ));
+        assert!(matches!(
+            sensor_rule_state(r#"{ "on": true }"#),
+            Ok(SensorRuleState::DimmerSwitch { on: Some(true), .. })
+        ));
+
+        // Numeric rules without any conditions would match every reading
+        assert!(matches!(
+            sensor_rule_state("{}"),
+            Ok(SensorRuleState::DimmerSwitch {
+                on: None,
+                up: None,
+                down: None,
+                off: None
+            })
+        ));
+    }
+
+    #[test]
+    fn compares_numeric_sensor_values() {
+        let numeric = |json: &str| match sensor_rule_state(json) {
+            Ok(SensorRuleState::NumericSensor(rule_state)) => rule_state,
+            state => panic!("Expected a numeric sensor rule state, got {:?}", state),
+        };
+
+        let above = numeric(r#"{ "above": 20 }"#);
+        assert!(!compare_numeric_sensor_value(&above, 20.0));
+        assert!(compare_numeric_sensor_value(&above, 20.5));
+
+        let below = numeric(r#"{ "below": 20 }"#);
+        assert!(compare_numeric_sensor_value(&below, 19.5));
+        assert!(!compare_numeric_sensor_value(&below, 20.0));
+
+        let between = numeric(r#"{ "between": [18, 22] }"#);
+        assert!(!compare_numeric_sensor_value(&between, 17.9));
+        assert!(compare_numeric_sensor_value(&between, 18.0));
+        assert!(compare_numeric_sensor_value(&between, 22.0));
+        assert!(!compare_numeric_sensor_value(&between, 22.1));
+
+        let combined = numeric(r#"{ "above": 10, "below": 20 }"#);
+        assert!(compare_numeric_sensor_value(&combined, 15.0));
+        assert!(!compare_numeric_sensor_value(&combined, 25.0));
+    }
+
+    fn is_in_window(json: &str, time: NaiveDateTime) -> Result<bool, String> {
+        let groups = Groups::new(Default::default());
+
+        is_rule_triggered(&mk_state(), &groups, None, &rule(json), &time)
+    }
+
+    #[test]
+    fn time_window_rule() {
+        let after = r#"{ "after": "23:00" }"#;
+        assert_eq!(is_in_window(after, at(1, 22, 59, 59)), Ok(false));
+        assert_eq!(is_in_window(after, at(1, 23, 0, 0)), Ok(true));
+        assert_eq!(is_in_window(after, at(1, 23, 59, 0)), Ok(true));
+
+        let before = r#"{ "before": "06:00" }"#;
+        assert_eq!(is_in_window(before, at(1, 5, 59, 59)), Ok(true));
+        assert_eq!(is_in_window(before, at(1, 6, 0, 0)), Ok(false));
+
+        let daytime = r#"{ "after": "08:00", "before": "18:00" }"#;
+        assert_eq!(is_in_window(daytime, at(1, 7, 0, 0)), Ok(false));
+        assert_eq!(is_in_window(daytime, at(1, 12, 0, 0)), Ok(true));
+        assert_eq!(is_in_window(daytime, at(1, 18, 0, 0)), Ok(false));
+
+        let night = r#"{ "after": "23:00", "before": "06:00" }"#;
+        assert_eq!(is_in_window(night, at(1, 23, 30, 0)), Ok(true));
+        assert_eq!(is_in_window(night, at(1, 1, 0, 0)), Ok(true));
+        assert_eq!(is_in_window(night, at(1, 12, 0, 0)), Ok(false));
+
+        // 2021-03-01 is a Monday
+        let weekend = r#"{ "after": "10:00", "weekdays": ["Sat", "Sun"] }"#;
+        assert_eq!(is_in_window(weekend, at(1, 12, 0, 0)), Ok(false));
+        assert_eq!(is_in_window(weekend, at(6, 12, 0, 0)), Ok(true));
+
+        // Solar events can't be resolved without a location
+        assert!(is_in_window(r#"{ "after": "sunset" }"#, at(1, 12, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn time_window_rule_requires_after_or_before() {
+        assert!(matches!(
+            rule(r#"{ "after": "23:00" }"#),
+            Rule::TimeWindow(_)
+        ));
+        assert!(serde_json::from_str::<Rule>(r#"{ "weekdays": ["Mon"] }"#).is_err());
+        assert!(serde_json::from_str::<Rule>(r#"{ "after": "23:00", "foo": 1 }"#).is_err());
+    }
+
+    #[test]
+    fn time_window_combined_with_sensor() {
+        let motion_at_night = format!(r#"{{ "all": [{}, {{ "after": "23:00" }}] }}"#, TRIGGERED);
+
+        assert_eq!(is_in_window(&motion_at_night, at(1, 22, 0, 0)), Ok(false));
+        assert_eq!(is_in_window(&motion_at_night, at(1, 23, 30, 0)), Ok(true));
+
+        // Routines are triggered by the scheduler as the window starts
+        let rules = mk_rules(vec![("night", vec![rule(r#"{ "after": "23:00" }"#)])]);
+        assert_eq!(
+            scheduled_minutes(&rules, at(1, 22, 59, 30), at(1, 23, 0, 30)),
+            vec![at(1, 23, 0, 0)]
+        );
+        assert!(scheduled_minutes(&rules, at(1, 23, 0, 30), at(1, 23, 5, 0)).is_empty());
+    }
+
+    fn at(day: u32, h: u32, m: u32, s: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2021, 3, day).and_hms(h, m, s)
+    }
+
+    fn scheduled_minutes(
+        rules: &Rules,
+        prev_time: NaiveDateTime,
+        time: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        rules
+            .find_scheduled_routines(&Default::default(), &prev_time, &time)
+            .into_iter()
+            .map(|(minute, _, _)| minute)
+            .collect()
+    }
+
+    #[test]
+    fn schedule_fires_once_at_scheduled_minute() {
+        let rules = mk_rules(vec![("wake_up", vec![schedule("07:00")])]);
+
+        assert_eq!(
+            scheduled_minutes(&rules, at(1, 6, 59, 59), at(1, 7, 0, 1)),
+            vec![at(1, 7, 0, 0)]
+        );
+        assert!(scheduled_minutes(&rules, at(1, 7, 0, 1), at(1, 7, 0, 2)).is_empty());
+        assert!(scheduled_minutes(&rules, at(1, 7, 0, 59), at(1, 7, 1, 0)).is_empty());
+    }
+
+    #[test]
+    fn schedule_fires_when_scheduler_falls_behind() {
+        let rules = mk_rules(vec![("wake_up", vec![schedule("07:00")])]);
+
+        // Tick delayed past the scheduled minute
+        assert_eq!(
+            scheduled_minutes(&rules, at(1, 6, 59, 58), at(1, 7, 1, 2)),
+            vec![at(1, 7, 0, 0)]
+        );
+
+        // Resumed from suspend hours later
+        assert_eq!(
+            scheduled_minutes(&rules, at(1, 23, 0, 0), at(2, 9, 30, 0)),
+            vec![at(2, 7, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn schedule_catches_up_at_most_one_day() {
+        let rules = mk_rules(vec![("wake_up", vec![schedule("07:00")])]);
+
+        assert_eq!(
+            scheduled_minutes(&rules, at(1, 6, 0, 0), at(4, 6, 30, 0)),
+            vec![at(3, 7, 0, 0)]
+        );
+    }
+
+    fn mk_motion_rules() -> (Rules, RxEventChannel) {
+        let routine = serde_json::json!({
+            "name": "Hallway motion",
+            "motion_lighting": {
+                "sensors": [{ "integration_id": "hue1", "name": "Motion sensor" }],
+                "group_id": "hallway",
+                "on_scene": "bright",
+                "off_scene": "off",
+                "timeout_ms": 60000
+            }
+        });
+        let groups = serde_json::json!({
+            "hallway": {
+                "name": "Hallway",
+                "devices": [{ "integration_id": "hue1", "name": "Lamp" }]
+            }
+        });
+
+        let config = vec![(
+            serde_json::from_value("hallway_motion".into()).unwrap(),
+            serde_json::from_value(routine).unwrap(),
+        )]
+        .into_iter()
+        .collect();
+
+        let (sender, receiver) = mk_channel();
+
+        let rules = Rules::new(
+            config,
+            None,
+            Groups::new(serde_json::from_value(groups).unwrap()),
+            sender,
+            EventLog::new(WebSockets::default()),
+        );
+
+        (rules, receiver)
+    }
+
+    /// Motion sensor and lamp, with the lamp optionally in given scene
+    fn motion_state(motion: bool, power: bool, scene: Option<&str>) -> DevicesState {
+        let mut state = mk_state();
+
+        for device in state.0.values_mut() {
+            match device.name.as_str() {
+                "Motion sensor" => {
+                    device.state = DeviceState::Sensor(SensorKind::OnOffSensor { value: motion })
+                }
+                _ => {
+                    device.state = DeviceState::OnOffDevice(OnOffDevice { power });
+                    device.scene = scene
+                        .map(|scene_id| DeviceSceneState::new(SceneId::new(scene_id.to_string())));
+                }
+            }
+        }
+
+        state
+    }
+
+    async fn activated_scenes(receiver: &mut RxEventChannel) -> Vec<SceneId> {
+        let mut scene_ids = vec![];
+
+        while let Ok(Some(msg)) = time::timeout(Duration::from_millis(10), receiver.recv()).await {
+            if let Message::Action(Action::ActivateScene(SceneDescriptor { scene_id, .. })) = msg {
+                scene_ids.push(scene_id);
+            }
+        }
+
+        scene_ids
+    }
+
+    fn scene(scene_id: &str) -> SceneId {
+        SceneId::new(scene_id.to_string())
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(60000);
+
+    #[tokio::test]
+    async fn motion_lighting_turns_lights_off_after_timeout() {
+        let (rules, mut receiver) = mk_motion_rules();
+        let start = Instant::now();
+
+        let idle = motion_state(false, false, None);
+        let motion = motion_state(true, false, None);
+        rules.handle_motion_lighting(&idle, &motion, start).await;
+        assert_eq!(activated_scenes(&mut receiver).await, vec![scene("bright")]);
+
+        let lit = motion_state(true, true, Some("bright"));
+        let no_motion = motion_state(false, true, Some("bright"));
+        rules.handle_motion_lighting(&lit, &no_motion, start).await;
+
+        let almost = start + TIMEOUT - Duration::from_millis(1);
+        rules
+            .handle_motion_lighting_timeouts(&no_motion, almost)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+
+        rules
+            .handle_motion_lighting_timeouts(&no_motion, start + TIMEOUT)
+            .await;
+        assert_eq!(activated_scenes(&mut receiver).await, vec![scene("off")]);
+
+        // Lights are turned off only once
+        let later = start + TIMEOUT * 2;
+        rules
+            .handle_motion_lighting_timeouts(&no_motion, later)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn motion_lighting_new_motion_restarts_timeout() {
+        let (rules, mut receiver) = mk_motion_rules();
+        let start = Instant::now();
+
+        let lit = motion_state(true, true, Some("bright"));
+        let no_motion = motion_state(false, true, Some("bright"));
+        rules.handle_motion_lighting(&no_motion, &lit, start).await;
+        rules.handle_motion_lighting(&lit, &no_motion, start).await;
+
+        let half = start + TIMEOUT / 2;
+        rules.handle_motion_lighting(&no_motion, &lit, half).await;
+        rules
+            .handle_motion_lighting_timeouts(&lit, start + TIMEOUT)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+
+        rules.handle_motion_lighting(&lit, &no_motion, half).await;
+        rules
+            .handle_motion_lighting_timeouts(&no_motion, start + TIMEOUT)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+
+        rules
+            .handle_motion_lighting_timeouts(&no_motion, half + TIMEOUT)
+            .await;
+        assert_eq!(activated_scenes(&mut receiver).await, vec![scene("off")]);
+    }
+
+    #[tokio::test]
+    async fn motion_lighting_keeps_lights_if_scene_changed() {
+        let (rules, mut receiver) = mk_motion_rules();
+        let start = Instant::now();
+
+        let lit = motion_state(true, true, Some("bright"));
+        let no_motion = motion_state(false, true, Some("bright"));
+        rules.handle_motion_lighting(&no_motion, &lit, start).await;
+        rules.handle_motion_lighting(&lit, &no_motion, start).await;
+
+        let reading = motion_state(false, true, Some("reading"));
+        rules
+            .handle_motion_lighting_timeouts(&reading, start + TIMEOUT)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn motion_lighting_ignores_lights_turned_on_by_others() {
+        let (rules, mut receiver) = mk_motion_rules();
+        let start = Instant::now();
+
+        let on = motion_state(false, true, None);
+        let motion = motion_state(true, true, None);
+        rules.handle_motion_lighting(&on, &motion, start).await;
+        rules.handle_motion_lighting(&motion, &on, start).await;
+        rules
+            .handle_motion_lighting_timeouts(&on, start + TIMEOUT)
+            .await;
+        assert!(activated_scenes(&mut receiver).await.is_empty());
+    }
+}
@@ -1,10 +1,15 @@
 use chrono::Utc;
 use homectl_types::{
-    device::{Device, DeviceId, DeviceKey, DeviceSceneState, DeviceState, DevicesState, Light},
+    color::ColorXy,
+    device::{
+        Capability, Device, DeviceColor, DeviceId, DeviceKey, DeviceSceneState, DeviceState,
+        DevicesState, Light,
+    },
     group::GroupDeviceLink,
     scene::{
         color_config_as_device_color, FlattenedSceneConfig, FlattenedScenesConfig, SceneConfig,
-        SceneDeviceConfig, SceneDeviceStates, SceneDevicesConfig, SceneId, ScenesConfig,
+        SceneDeviceConfig, SceneDeviceState, SceneDeviceStates, SceneDevicesConfig, SceneId,
+        ScenesConfig,
     },
 };
 
@@ -18,7 +23,7 @@ use std::{
 
 #[derive(Clone)]
 pub struct Scenes {
-    config: ScenesConfig,
+    config: Arc<RwLock<ScenesConfig>>,
     groups: Groups,
     db_scenes: Arc<RwLock<ScenesConfig>>,
 }
@@ -26,12 +31,18 @@ pub struct Scenes {
 impl Scenes {
     pub fn new(config: ScenesConfig, groups: Groups) -> Self {
         Scenes {
-            config,
+            config: Arc::new(RwLock::new(config)),
             groups,
             db_scenes: Default::default(),
         }
     }
 
+    /// Replaces scenes config, e.g. after the config file has been reloaded
+    pub fn set_config(&self, config: ScenesConfig) {
+        let mut rw_lock = self.config.write().unwrap();
+        *rw_lock = config;
+    }
+
     pub async fn refresh_db_scenes(&self) {
         let db_scenes = db_get_scenes().await.unwrap_or_default();
         let mut rw_lock = self.db_scenes.write().unwrap();
@@ -40,7 +51,7 @@ impl Scenes {
 
     pub fn get_scenes(&self) -> ScenesConfig {
         let mut db_scenes = self.db_scenes.read().unwrap().clone();
-        db_scenes.extend(self.config.clone().into_iter());
+        db_scenes.extend(self.config.read().unwrap().clone());
         db_scenes
     }
 
@@ -48,6 +59,12 @@ impl Scenes {
         Some(self.get_scenes().get(scene_id)?.clone())
     }
 
+    /// Whether given scene is defined in the config file. These take
+    /// precedence over scenes stored in the database.
+    pub fn is_config_scene(&self, scene_id: &SceneId) -> bool {
+        self.config.read().unwrap().contains_key(scene_id)
+    }
+
     pub fn find_scene_devices_config(
         &self,
         devices: &DevicesState,
@@ -135,6 +152,11 @@ impl Scenes {
                 // Use state from another device
 
                 // Try finding device by integration_id, device_id, name
+                let target_capabilities = device.capabilities.as_ref();
+                let target_light_count = match &device.state {
+                    DeviceState::MultiSourceLight(state) => Some(state.lights.len()),
+                    _ => None,
+                };
                 let device = find_device(
                     devices,
                     &link.integration_id,
@@ -144,18 +166,31 @@ impl Scenes {
 
                 let state = device.state;
 
-                // Brightness override
-                let state = match state {
-                    DeviceState::Light(mut state) => {
+                // Brightness override, and convert color to what the device
+                // supports in case the linked device uses a different kind.
+                // Lights with multiple light sources need one color for each.
+                let state = match (state, target_light_count) {
+                    (DeviceState::Light(mut state), None) => {
                         state.brightness = link.brightness.or(state.brightness);
-                        DeviceState::Light(state)
+                        DeviceState::Light(state.for_capabilities(target_capabilities))
                     }
-                    DeviceState::MultiSourceLight(mut state) => {
+                    (DeviceState::Light(mut state), Some(count)) => {
                         state.brightness = link.brightness.or(state.brightness);
-                        DeviceState::MultiSourceLight(state)
+                        let state = state.to_multi_source(count);
+                        DeviceState::MultiSourceLight(state.for_capabilities(target_capabilities))
+                    }
+                    (DeviceState::MultiSourceLight(mut state), None) => {
+                        state.brightness = link.brightness.or(state.brightness);
+                        let state = state.to_light();
+                        DeviceState::Light(state.for_capabilities(target_capabilities))
                     }
-                    DeviceState::OnOffDevice(state) => DeviceState::OnOffDevice(state),
-                    DeviceState::Sensor(state) => DeviceState::Sensor(state),
+                    (DeviceState::MultiSourceLight(mut state), Some(count)) => {
+                        state.brightness = link.brightness.or(state.brightness);
+                        state.lights = state.lights.into_iter().cycle().take(count).collect();
+                        DeviceState::MultiSourceLight(state.for_capabilities(target_capabilities))
+                    }
+                    (DeviceState::OnOffDevice(state), _) => DeviceState::OnOffDevice(state),
+                    (DeviceState::Sensor(state), _) => DeviceState::Sensor(state),
                 };
 
                 // Ignore device's transition_ms value
@@ -164,6 +199,10 @@ impl Scenes {
                         state.transition_ms = None;
                         DeviceState::Light(state)
                     }
+                    (true, DeviceState::MultiSourceLight(mut state)) => {
+                        state.transition_ms = None;
+                        DeviceState::MultiSourceLight(state)
+                    }
                     _ => state,
                 };
 
@@ -183,13 +222,78 @@ impl Scenes {
                 self.find_scene_device_state(&device, devices, ignore_transition)
             }
 
-            SceneDeviceConfig::SceneDeviceState(scene_device) => Some(DeviceState::Light(Light {
+            SceneDeviceConfig::SceneDeviceState(scene_device) => {
+                // Lights with a single light source use the first segment
+                // color if no color is given
+                let first_segment = scene_device
+                    .segments
+                    .as_ref()
+                    .or(scene_device.gradient.as_ref())
+                    .and_then(|colors| colors.first());
+                let color = scene_device
+                    .color
+                    .as_ref()
+                    .or(first_segment)
+                    .cloned()
+                    .map(color_config_as_device_color);
+                let cct = scene_device.cct.clone().map(DeviceColor::Cct);
+
+                // If both are given, prefer color temperature for devices
+                // that only support it
+                let cct_only = matches!(
+                    device.capabilities,
+                    Some(Capability {
+                        hsv: false,
+                        cct: true,
+                        ..
+                    })
+                );
+                let color = if cct_only {
+                    cct.or(color)
+                } else {
+                    color.or(cct)
+                };
+
+                // Fall back to the transition of the scene. Devices without
+                // native transitions are faded by homectl core instead, so
+                // they shouldn't additionally transition to each step.
+                let transition_ms = scene_device.transition_ms.or_else(|| {
+                    if !device.supports_transitions() {
+                        return None;
+                    }
+
+                    let scene = self.find_scene(scene_id)?;
+                    scene.transition.map(|transition| transition.duration_ms)
+                });
+
                 // Use state from scene_device
-                brightness: scene_device.brightness,
-                color: scene_device.color.clone().map(color_config_as_device_color),
-                power: scene_device.power,
-                transition_ms: scene_device.transition_ms,
-            })),
+                let light = Light {
+                    brightness: scene_device.brightness,
+                    color,
+                    power: scene_device.power,
+                    transition_ms,
+                };
+
+                if let DeviceState::MultiSourceLight(current) = &device.state {
+                    let count = current.lights.len();
+                    let mut state = light.to_multi_source(count);
+
+                    if let Some(lights) = mk_segment_colors(scene_device, count) {
+                        state.lights = lights;
+                    } else if state.lights.is_empty() {
+                        // Scene doesn't specify colors, keep current ones
+                        state.lights = current.lights.clone();
+                    }
+
+                    return Some(DeviceState::MultiSourceLight(
+                        state.for_capabilities(device.capabilities.as_ref()),
+                    ));
+                }
+
+                Some(DeviceState::Light(
+                    light.for_capabilities(device.capabilities.as_ref()),
+                ))
+            }
         }
     }
 
@@ -247,3 +351,50 @@ impl Scenes {
             .collect()
     }
 }
+
+/// Computes colors for each of the `count` light sources of a device from the
+/// `segments` or `gradient` of a scene device config, if given
+fn mk_segment_colors(scene_device: &SceneDeviceState, count: usize) -> Option<Vec<DeviceColor>> {
+    if let Some(segments) = scene_device.segments.as_ref().filter(|s| !s.is_empty()) {
+        let colors = segments
+            .iter()
+            .cloned()
+            .map(color_config_as_device_color)
+            .cycle()
+            .take(count)
+            .collect();
+
+        return Some(colors);
+    }
+
+    let stops: Vec<ColorXy> = scene_device
+        .gradient
+        .as_ref()?
+        .iter()
+        .cloned()
+        .map(|color| color_config_as_device_color(color).to_xy())
+        .collect();
+
+    if stops.len() < 2 {
+        let stop = stops.first()?;
+        return Some(vec![DeviceColor::Color(*stop); count]);
+    }
+
+    let colors = (0..count)
+        .map(|i| {
+            // Position of this light source along the gradient, from 0 to
+            // the index of the last stop
+            let t = if count > 1 {
+                i as f32 / (count - 1) as f32
+            } else {
+                0.0
+            };
+            let pos = t * (stops.len() - 1) as f32;
+            let index = (pos.floor() as usize).min(stops.len() - 2);
+
+            DeviceColor::Color(stops[index].mix(&stops[index + 1], pos - index as f32))
+        })
+        .collect();
+
+    Some(colors)
+}
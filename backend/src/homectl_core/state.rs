@@ -1,10 +1,13 @@
+use anyhow::Result;
 use homectl_types::{
+    api::ApiConfig,
     event::TxEventChannel,
     websockets::{StateUpdate, WebSocketResponse},
 };
 
 use super::{
-    devices::Devices, groups::Groups, integrations::Integrations, rules::Rules, scenes::Scenes,
+    config::read_config, devices::Devices, event_log::EventLog, groups::Groups,
+    integrations::Integrations, presence::Presence, rules::Rules, scenes::Scenes,
     websockets::WebSockets,
 };
 
@@ -15,11 +18,45 @@ pub struct AppState {
     pub scenes: Scenes,
     pub devices: Devices,
     pub rules: Rules,
+    pub presence: Presence,
     pub sender: TxEventChannel,
     pub ws: WebSockets,
+    pub event_log: EventLog,
+    pub api_config: ApiConfig,
 }
 
 impl AppState {
+    /// Re-reads the config file and replaces groups, scenes and routines in
+    /// place. Integrations are only restarted if their config has changed.
+    pub async fn reload_config(&self) -> Result<()> {
+        let (config, opaque_integrations_configs) = read_config()?;
+
+        // Integrations are the only part of the config that can fail to
+        // load, so they go first to avoid applying a partially reloaded config
+        let mut integrations = self.integrations.clone();
+        integrations
+            .reload_integrations(
+                &config.integrations.unwrap_or_default(),
+                &opaque_integrations_configs,
+            )
+            .await?;
+
+        self.groups.set_config(config.groups.unwrap_or_default());
+        self.scenes.set_config(config.scenes.unwrap_or_default());
+        self.rules
+            .set_config(config.routines.unwrap_or_default(), config.location);
+        self.presence
+            .set_config(config.presence.unwrap_or_default());
+        self.devices
+            .set_override_config(config.manual_override.unwrap_or_default());
+
+        self.send_state_ws(None).await;
+
+        println!("reloaded config");
+
+        Ok(())
+    }
+
     /// Sends current state over WebSockets. If user_id is omitted, the message
     /// is broadcast to all connected peers.
     pub async fn send_state_ws(&self, user_id: Option<usize>) {
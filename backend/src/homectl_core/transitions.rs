@@ -0,0 +1,124 @@
+use homectl_types::{
+    color::ColorXy,
+    device::{DeviceColor, DeviceState, Light, MultiSourceLight},
+    scene::TransitionEasing,
+};
+
+/// How often intermediate states are sent to devices that are faded by
+/// homectl. Most integrations without native transitions are fairly slow to
+/// apply state changes, so there's little point in going much faster.
+pub static TRANSITION_STEP_MS: u64 = 200;
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+fn interpolate_color(
+    from: &Option<DeviceColor>,
+    to: &Option<DeviceColor>,
+    t: f32,
+) -> Option<DeviceColor> {
+    match (from, to) {
+        (Some(DeviceColor::Cct(from)), Some(DeviceColor::Cct(to))) => Some(DeviceColor::Cct(
+            to.set_cct(lerp(from.get_cct(), to.get_cct(), t)),
+        )),
+        (Some(from), Some(to)) => {
+            let from = from.to_xy();
+            let to = to.to_xy();
+
+            Some(DeviceColor::Color(ColorXy::new(
+                lerp(from.x, to.x, t),
+                lerp(from.y, to.y, t),
+                lerp(from.brightness, to.brightness, t),
+            )))
+        }
+        // Nothing to fade from, switch to target color immediately
+        (None, to) => to.clone(),
+        (_, None) => None,
+    }
+}
+
+/// Brightness of a light, lights that are off have zero brightness
+fn effective_brightness(power: bool, brightness: Option<f32>) -> f32 {
+    if power {
+        brightness.unwrap_or(1.0)
+    } else {
+        0.0
+    }
+}
+
+fn interpolate_light(from: &Light, to: &Light, t: f32, easing: TransitionEasing) -> Light {
+    let brightness = easing.interpolate_brightness(
+        effective_brightness(from.power, from.brightness),
+        effective_brightness(to.power, to.brightness),
+        t,
+    );
+
+    // Lights that are turning on fade in from their target color
+    let from_color = if from.power { &from.color } else { &to.color };
+
+    Light {
+        // Keep lights on until the transition has finished
+        power: from.power || to.power,
+        brightness: Some(brightness),
+        color: interpolate_color(from_color, &to.color, easing.ease(t)),
+        transition_ms: None,
+    }
+}
+
+fn interpolate_multi_source_light(
+    from: &MultiSourceLight,
+    to: &MultiSourceLight,
+    t: f32,
+    easing: TransitionEasing,
+) -> MultiSourceLight {
+    let brightness = easing.interpolate_brightness(
+        effective_brightness(from.power, from.brightness),
+        effective_brightness(to.power, to.brightness),
+        t,
+    );
+
+    let lights = if from.power && from.lights.len() == to.lights.len() {
+        from.lights
+            .iter()
+            .zip(to.lights.iter())
+            .filter_map(|(from, to)| {
+                interpolate_color(&Some(from.clone()), &Some(to.clone()), easing.ease(t))
+            })
+            .collect()
+    } else {
+        to.lights.clone()
+    };
+
+    MultiSourceLight {
+        power: from.power || to.power,
+        brightness: Some(brightness),
+        lights,
+        transition_ms: None,
+    }
+}
+
+/// Returns the state of a device at progress `t` (0..1) of a transition from
+/// `from` to `to`. The final state is always exactly `to`.
+pub fn interpolate_state(
+    from: &DeviceState,
+    to: &DeviceState,
+    t: f32,
+    easing: TransitionEasing,
+) -> DeviceState {
+    if t >= 1.0 {
+        return to.clone();
+    }
+
+    match (from, to) {
+        (DeviceState::Light(from), DeviceState::Light(to)) => {
+            DeviceState::Light(interpolate_light(from, to, t, easing))
+        }
+        (DeviceState::MultiSourceLight(from), DeviceState::MultiSourceLight(to)) => {
+            DeviceState::MultiSourceLight(interpolate_multi_source_light(from, to, t, easing))
+        }
+        // Other kinds of devices can't be faded, switch them to their target
+        // state immediately
+        _ => to.clone(),
+    }
+}
@@ -51,6 +51,16 @@ impl Integration for Boolean {
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        // do nothing
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        // nothing to check
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, _device: &Device) -> Result<()> {
         Ok(())
     }
@@ -88,5 +98,7 @@ fn mk_boolean_device(id: &IntegrationId, config: &BooleanConfig, value: Option<b
         scene: None,
         state,
         capabilities: None,
+        availability: Default::default(),
+        manual_override: None,
     }
 }
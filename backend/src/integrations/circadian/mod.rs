@@ -1,11 +1,14 @@
-use crate::utils::from_hh_mm;
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use futures::future::{abortable, AbortHandle};
 use homectl_types::{
-    device::{Device, DeviceColor, DeviceId, DeviceState, Light},
+    device::{CorrelatedColorTemperature, Device, DeviceColor, DeviceId, DeviceState, Light},
     event::{Message, TxEventChannel},
     integration::{Integration, IntegrationActionPayload, IntegrationId},
     scene::{color_config_as_device_color, ColorConfig},
+    solar::{Location, SolarEvent, TimeOfDay},
+    utils::interpolate_cct,
 };
 use palette::Gradient;
 use serde::Deserialize;
@@ -16,17 +19,43 @@ use tokio::time;
 pub struct CircadianConfig {
     device_name: String,
 
-    #[serde(deserialize_with = "from_hh_mm")]
-    day_fade_start: chrono::NaiveTime,
+    /// Top-level location from Settings.toml, required if any fade start
+    /// times are relative to solar events
+    location: Option<Location>,
+
+    day_fade_start: TimeOfDay,
     day_fade_duration_hours: i64,
-    day_color: ColorConfig,
     day_brightness: Option<f32>,
 
-    #[serde(deserialize_with = "from_hh_mm")]
-    night_fade_start: chrono::NaiveTime,
+    /// Either day_color or day_cct (in kelvin) must be given
+    day_color: Option<ColorConfig>,
+    day_cct: Option<CorrelatedColorTemperature>,
+
+    night_fade_start: TimeOfDay,
     night_fade_duration_hours: i64,
-    night_color: ColorConfig,
     night_brightness: Option<f32>,
+
+    /// Either night_color or night_cct (in kelvin) must be given
+    night_color: Option<ColorConfig>,
+    night_cct: Option<CorrelatedColorTemperature>,
+}
+
+fn mk_circadian_color(
+    id: &IntegrationId,
+    prefix: &str,
+    color: Option<ColorConfig>,
+    cct: Option<CorrelatedColorTemperature>,
+) -> Result<DeviceColor> {
+    match (color, cct) {
+        (Some(color), None) => Ok(color_config_as_device_color(color)),
+        (None, Some(cct)) => Ok(DeviceColor::Cct(cct)),
+        _ => Err(anyhow!(
+            "Circadian integration {} must have exactly one of {}_color or {}_cct configured",
+            id,
+            prefix,
+            prefix
+        )),
+    }
 }
 
 #[derive(Clone)]
@@ -36,6 +65,7 @@ pub struct Circadian {
     sender: TxEventChannel,
     converted_day_color: DeviceColor,
     converted_night_color: DeviceColor,
+    poll_handle: Option<AbortHandle>,
 }
 
 #[async_trait]
@@ -46,12 +76,33 @@ impl Integration for Circadian {
             .try_into()
             .context("Failed to deserialize config of Circadian integration")?;
 
+        let uses_solar_events = [&config.day_fade_start, &config.night_fade_start]
+            .iter()
+            .any(|time| matches!(time, TimeOfDay::Solar { .. }));
+
+        if uses_solar_events && config.location.is_none() {
+            return Err(anyhow!(
+                "Circadian integration {} uses solar events, which requires a top-level location",
+                id
+            ));
+        }
+
+        let converted_day_color =
+            mk_circadian_color(id, "day", config.day_color.clone(), config.day_cct.clone())?;
+        let converted_night_color = mk_circadian_color(
+            id,
+            "night",
+            config.night_color.clone(),
+            config.night_cct.clone(),
+        )?;
+
         Ok(Circadian {
             id: id.clone(),
-            config: config.clone(),
+            config,
             sender,
-            converted_day_color: color_config_as_device_color(config.day_color),
-            converted_night_color: color_config_as_device_color(config.night_color),
+            converted_day_color,
+            converted_night_color,
+            poll_handle: None,
         })
     }
 
@@ -73,11 +124,26 @@ impl Integration for Circadian {
 
         // FIXME: can we restructure the integrations / devices systems such
         // that polling is not needed here?
-        tokio::spawn(async { poll_sensor(circadian).await });
+        let (poll_task, poll_handle) = abortable(poll_sensor(circadian));
+        tokio::spawn(poll_task);
+        self.poll_handle = Some(poll_handle);
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(poll_handle) = self.poll_handle.take() {
+            poll_handle.abort();
+        }
 
         Ok(())
     }
 
+    async fn health_check(&mut self) -> Result<()> {
+        // poll task runs forever and is only stopped by stop()
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, _device: &Device) -> Result<()> {
         // do nothing
         Ok(())
@@ -89,14 +155,38 @@ impl Integration for Circadian {
     }
 }
 
-fn get_night_fade(circadian: &Circadian) -> f32 {
-    let local = chrono::Local::now().naive_local().time();
+/// Resolves given TimeOfDay on given date. Falls back to solar noon if the
+/// solar event does not occur on that date (e.g. during polar day or night).
+fn resolve_time_of_day(circadian: &Circadian, time: &TimeOfDay, date: NaiveDate) -> NaiveTime {
+    let noon = NaiveTime::from_hms(12, 0, 0);
+
+    match (time, &circadian.config.location) {
+        (TimeOfDay::Fixed(time), _) => *time,
+        (time, Some(location)) => time.resolve(date, location).unwrap_or_else(|| {
+            let solar_noon = TimeOfDay::Solar {
+                event: SolarEvent::SolarNoon,
+                offset: chrono::Duration::zero(),
+            };
+
+            solar_noon.resolve(date, location).unwrap_or(noon)
+        }),
+        // Checked in Circadian::new()
+        (_, None) => noon,
+    }
+}
 
-    let day_fade_start = circadian.config.day_fade_start;
+/// Returns how far into the night it is at given local time, from 0.0 (day)
+/// to 1.0 (night)
+fn get_night_fade(circadian: &Circadian, now: NaiveDateTime) -> f32 {
+    let local = now.time();
+
+    let day_fade_start =
+        resolve_time_of_day(circadian, &circadian.config.day_fade_start, now.date());
     let day_fade_duration = chrono::Duration::hours(circadian.config.day_fade_duration_hours);
     let day_fade_end = day_fade_start + day_fade_duration;
 
-    let night_fade_start = circadian.config.night_fade_start;
+    let night_fade_start =
+        resolve_time_of_day(circadian, &circadian.config.night_fade_start, now.date());
     let night_fade_duration = chrono::Duration::hours(circadian.config.night_fade_duration_hours);
     let night_fade_end = night_fade_start + night_fade_duration;
 
@@ -122,30 +212,34 @@ fn get_night_fade(circadian: &Circadian) -> f32 {
     }
 }
 
-fn get_circadian_color(circadian: &Circadian) -> DeviceColor {
+fn get_circadian_color(circadian: &Circadian, now: NaiveDateTime) -> DeviceColor {
+    let i = get_night_fade(circadian, now);
+
     match (
-        circadian.converted_day_color.clone(),
-        circadian.converted_night_color.clone(),
+        &circadian.converted_day_color,
+        &circadian.converted_night_color,
     ) {
-        (DeviceColor::Color(day), DeviceColor::Color(night)) => {
-            let gradient = Gradient::new(vec![day, night]);
+        (DeviceColor::Cct(day), DeviceColor::Cct(night)) => {
+            let cct = interpolate_cct(day.get_cct(), night.get_cct(), i);
 
-            let i = get_night_fade(circadian);
+            DeviceColor::Cct(day.set_cct(cct))
+        }
+        // Fade in HSV if either color isn't a color temperature
+        (day, night) => {
+            let gradient = Gradient::new(vec![day.to_hsv(), night.to_hsv()]);
 
-            DeviceColor::Color(gradient.get(i))
+            DeviceColor::Color(gradient.get(i).into())
         }
-        (DeviceColor::Cct(_), DeviceColor::Cct(_)) => todo!(),
-        _ => panic!("Mixed color types not supported"),
     }
 }
 
-fn get_circadian_brightness(circadian: &Circadian) -> Option<f32> {
+fn get_circadian_brightness(circadian: &Circadian, now: NaiveDateTime) -> Option<f32> {
     match (
         circadian.config.day_brightness,
         circadian.config.night_brightness,
     ) {
         (Some(day), Some(night)) => {
-            let i = get_night_fade(circadian);
+            let i = get_night_fade(circadian, now);
 
             let brightness = (1.0 - i) * day + i * night;
 
@@ -175,10 +269,12 @@ async fn poll_sensor(circadian: Circadian) {
 }
 
 fn mk_circadian_device(circadian: &Circadian) -> Device {
+    let now = chrono::Local::now().naive_local();
+
     let state = DeviceState::Light(Light::new(
         true,
-        get_circadian_brightness(circadian),
-        Some(get_circadian_color(circadian)),
+        get_circadian_brightness(circadian, now),
+        Some(get_circadian_color(circadian, now)),
         Some(POLL_RATE),
     ));
 
@@ -189,5 +285,148 @@ fn mk_circadian_device(circadian: &Circadian) -> Device {
         scene: None,
         state,
         capabilities: None,
+        availability: Default::default(),
+        manual_override: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use homectl_types::event::mk_channel;
+
+    const CONFIG: &str = r#"
+        device_name = "Circadian"
+        day_fade_start = "07:00"
+        day_fade_duration_hours = 2
+        day_brightness = 1.0
+        day_cct = 5000
+        night_fade_start = "19:00"
+        night_fade_duration_hours = 2
+        night_brightness = 0.5
+        night_cct = 2000
+    "#;
+
+    fn mk_circadian(toml: &str) -> Result<Circadian> {
+        let mut settings = config::Config::default();
+        settings
+            .merge(config::File::from_str(toml, config::FileFormat::Toml))
+            .unwrap();
+
+        let config: config::Value = settings.try_into().unwrap();
+        let (sender, _receiver) = mk_channel();
+
+        Circadian::new(
+            &IntegrationId::from("circadian".to_string()),
+            &config,
+            sender,
+        )
+    }
+
+    fn at(h: u32, m: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2021, 6, 1).and_hms(h, m, 0)
+    }
+
+    fn cct(color: DeviceColor) -> f32 {
+        match color {
+            DeviceColor::Cct(cct) => cct.get_cct(),
+            color => panic!("Expected color temperature, got {:?}", color),
+        }
+    }
+
+    fn approx_eq(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn fades_between_day_and_night() {
+        let circadian = mk_circadian(CONFIG).unwrap();
+
+        assert_eq!(get_night_fade(&circadian, at(3, 0)), 1.0);
+        assert_eq!(get_night_fade(&circadian, at(7, 0)), 1.0);
+        assert!(approx_eq(get_night_fade(&circadian, at(8, 0)), 0.5));
+        assert_eq!(get_night_fade(&circadian, at(12, 0)), 0.0);
+        assert_eq!(get_night_fade(&circadian, at(19, 0)), 0.0);
+        assert!(approx_eq(
+            get_night_fade(&circadian, at(20, 0)),
+            f32::sin(std::f32::consts::PI / 4.0)
+        ));
+        assert_eq!(get_night_fade(&circadian, at(23, 0)), 1.0);
+    }
+
+    #[test]
+    fn interpolates_color_temperatures() {
+        let circadian = mk_circadian(CONFIG).unwrap();
+
+        assert!(approx_eq(
+            cct(get_circadian_color(&circadian, at(12, 0))),
+            5000.0
+        ));
+        assert!(approx_eq(
+            cct(get_circadian_color(&circadian, at(23, 0))),
+            2000.0
+        ));
+
+        // Halfway in mireds between 200 and 500
+        let halfway = cct(get_circadian_color(&circadian, at(8, 0)));
+        assert!(
+            (halfway - 1_000_000.0 / 350.0).abs() < 1.0,
+            "was {}K",
+            halfway
+        );
+    }
+
+    #[test]
+    fn interpolates_brightness() {
+        let circadian = mk_circadian(CONFIG).unwrap();
+
+        assert_eq!(get_circadian_brightness(&circadian, at(12, 0)), Some(1.0));
+        assert_eq!(get_circadian_brightness(&circadian, at(23, 0)), Some(0.5));
+        assert!(approx_eq(
+            get_circadian_brightness(&circadian, at(8, 0)).unwrap(),
+            0.75
+        ));
+    }
+
+    #[test]
+    fn fades_mixed_colors_in_hsv() {
+        let toml = CONFIG.replace(
+            "night_cct = 2000",
+            "night_color = { hue = 30, saturation = 1.0, value = 1.0 }",
+        );
+        let circadian = mk_circadian(&toml).unwrap();
+
+        assert!(matches!(
+            get_circadian_color(&circadian, at(8, 0)),
+            DeviceColor::Color(_)
+        ));
+    }
+
+    #[test]
+    fn requires_exactly_one_color() {
+        let both = format!(
+            "{}\nday_color = {{ hue = 30, saturation = 1.0, value = 1.0 }}",
+            CONFIG
+        );
+        let neither = CONFIG.replace("night_cct = 2000", "");
+
+        assert!(mk_circadian(&both).is_err());
+        assert!(mk_circadian(&neither).is_err());
+    }
+
+    #[test]
+    fn requires_location_for_solar_events() {
+        let toml = CONFIG.replace(
+            r#"night_fade_start = "19:00""#,
+            r#"night_fade_start = "sunset""#,
+        );
+
+        assert!(mk_circadian(&toml).is_err());
+
+        let toml = format!(
+            "{}\nlocation = {{ latitude = 60.17, longitude = 24.94 }}",
+            toml
+        );
+        assert!(mk_circadian(&toml).is_ok());
     }
 }
@@ -48,7 +48,7 @@ impl Integration for Dummy {
             let state = DeviceState::Light(Light::new(
                 true,
                 Some(1.0),
-                Some(DeviceColor::Color(device.init_state)),
+                Some(DeviceColor::Color(device.init_state.into())),
                 None,
             ));
             let device = Device::new(
@@ -72,6 +72,16 @@ impl Integration for Dummy {
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        // do nothing
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        // nothing to check
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
         self.devices.insert(device.id.clone(), device.clone());
         Ok(())
@@ -27,10 +27,39 @@ pub struct BridgeLightState {
     pub reachable: Option<bool>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub enum BridgeColorGamutType {
+    A,
+    B,
+    C,
+
+    #[serde(other)]
+    Other,
+}
+
+/// Supported color temperature range in mireds
+#[derive(Clone, Debug, Deserialize)]
+pub struct BridgeCtRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BridgeLightControl {
+    pub colorgamuttype: Option<BridgeColorGamutType>,
+    pub ct: Option<BridgeCtRange>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BridgeLightCapabilities {
+    pub control: Option<BridgeLightControl>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct BridgeLight {
     pub state: BridgeLightState,
     pub name: String,
+    pub capabilities: Option<BridgeLightCapabilities>,
 }
 
 pub type BridgeLightId = DeviceId;
@@ -42,6 +71,20 @@ pub struct ZLLPresenceState {
     pub lastupdated: String,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ZLLLightLevelState {
+    /// 10000 * log10(lux) + 1
+    pub lightlevel: Option<u32>,
+    pub lastupdated: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ZLLTemperatureState {
+    /// Hundredths of degrees Celsius
+    pub temperature: Option<i32>,
+    pub lastupdated: String,
+}
+
 pub type BridgeButtonEvent = u32;
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -51,6 +94,14 @@ pub struct ZLLSwitchState {
     pub lastupdated: String,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct BridgeSensorConfig {
+    pub reachable: Option<bool>,
+
+    /// Battery level in percent, only reported by battery powered sensors
+    pub battery: Option<u8>,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(tag = "type")]
 pub enum BridgeSensor {
@@ -59,17 +110,27 @@ pub enum BridgeSensor {
     },
     ZLLLightLevel {
         name: String,
+        state: ZLLLightLevelState,
+        #[serde(default)]
+        config: BridgeSensorConfig,
     },
     ZLLPresence {
         name: String,
         state: ZLLPresenceState,
+        #[serde(default)]
+        config: BridgeSensorConfig,
     },
     ZLLSwitch {
         name: String,
         state: ZLLSwitchState,
+        #[serde(default)]
+        config: BridgeSensorConfig,
     },
     ZLLTemperature {
         name: String,
+        state: ZLLTemperatureState,
+        #[serde(default)]
+        config: BridgeSensorConfig,
     },
     CLIPPresence {
         name: String,
@@ -1,18 +1,63 @@
-use super::bridge::{BridgeLight, ColorMode};
+use super::bridge::{BridgeColorGamutType, BridgeLight, ColorMode};
 
 use homectl_types::{
+    color::{ColorGamut, ColorXy},
     device::{
-        Capability, CorrelatedColorTemperature, Device, DeviceColor, DeviceId, DeviceState, Light,
+        Capability, CorrelatedColorTemperature, Device, DeviceAvailability, DeviceColor, DeviceId,
+        DeviceState, Light,
     },
     integration::IntegrationId,
 };
-use palette::{Hsv, Yxy};
+use palette::Hsv;
+
+/// Finds out what a light can do based on the capabilities and state fields
+/// reported by the bridge
+pub fn to_capability(bridge_light: &BridgeLight) -> Capability {
+    let control = bridge_light
+        .capabilities
+        .as_ref()
+        .and_then(|capabilities| capabilities.control.as_ref());
+
+    let color_gamut = control
+        .and_then(|control| control.colorgamuttype.as_ref())
+        .and_then(|gamut_type| match gamut_type {
+            BridgeColorGamutType::A => Some(ColorGamut::HueA),
+            BridgeColorGamutType::B => Some(ColorGamut::HueB),
+            BridgeColorGamutType::C => Some(ColorGamut::HueC),
+            BridgeColorGamutType::Other => None,
+        });
+
+    // Hue reports color temperatures in mireds, so min and max swap places
+    let cct_range = control
+        .and_then(|control| control.ct.as_ref())
+        .map(|ct| (1_000_000.0 / ct.max as f32)..(1_000_000.0 / ct.min as f32));
+
+    let dimmable = bridge_light.state.bri.is_some();
+
+    Capability {
+        hsv: bridge_light.state.hue.is_some() || bridge_light.state.xy.is_some(),
+        cct: bridge_light.state.ct.is_some(),
+        color_gamut,
+        cct_range,
+        dimmable,
+        transitions: true,
+        // Lowest brightness level accepted by the bridge is 1
+        min_brightness: if dimmable { Some(1.0 / 254.0) } else { None },
+    }
+}
 
 pub fn to_light(bridge_light: BridgeLight) -> Light {
+    let cct_range = to_capability(&bridge_light)
+        .cct_range
+        .unwrap_or(2000.0..6500.0);
     let power = bridge_light.state.on;
     let xy = bridge_light.state.xy;
     let ct = bridge_light.state.ct.map(|ct| 1_000_000.0 / ct as f32);
-    let hue = bridge_light.state.hue.map(|hue| hue as f32 / 65535.0);
+    // Hue uses [0, 65535] for hue, palette uses degrees
+    let hue = bridge_light
+        .state
+        .hue
+        .map(|hue| hue as f32 / 65535.0 * 360.0);
     let sat = bridge_light.state.sat.map(|sat| sat as f32 / 254.0);
     let brightness = bridge_light.state.bri.map(|bri| bri as f32 / 254.0);
     let transition_ms = bridge_light
@@ -23,22 +68,19 @@ pub fn to_light(bridge_light: BridgeLight) -> Light {
     let color = match bridge_light.state.colormode {
         Some(ColorMode::Ct) => (move || {
             let ct = ct?;
-            let cct = CorrelatedColorTemperature::new(ct, 2000.0..6500.0);
+            let cct = CorrelatedColorTemperature::new(ct, cct_range);
             Some(DeviceColor::Cct(cct))
         })(),
         Some(ColorMode::Xy) => (move || {
             let (x, y) = xy?;
-            let hsv = Yxy::new(x, y, 1.0);
-            let mut device_color: Hsv = hsv.into();
-            device_color.value = 1.0;
-            Some(DeviceColor::Color(device_color))
+            Some(DeviceColor::Color(ColorXy::new(x, y, 1.0)))
         })(),
         Some(ColorMode::Hs) => (move || {
             let hue = hue?;
             let sat = sat?;
 
             let device_color = Hsv::new(hue, sat, 1.0);
-            Some(DeviceColor::Color(device_color))
+            Some(DeviceColor::Color(device_color.into()))
         })(),
         None => None,
     };
@@ -58,11 +100,9 @@ pub fn bridge_light_to_device(
     bridge_light: BridgeLight,
 ) -> Device {
     let name = bridge_light.name.clone();
+    let availability = DeviceAvailability::new(bridge_light.state.reachable.unwrap_or(true));
     let state = DeviceState::Light(to_light(bridge_light.clone()));
-    let capabilities = Capability {
-        Hsv: bridge_light.state.hue.is_some(),
-        Cct: bridge_light.state.ct.is_some(),
-    };
+    let capabilities = to_capability(&bridge_light);
     Device {
         id: DeviceId::new(&format!("lights/{}", id)),
         name,
@@ -70,5 +110,7 @@ pub fn bridge_light_to_device(
         scene: None,
         state,
         capabilities: Some(capabilities),
+        availability,
+        manual_override: None,
     }
 }
@@ -7,7 +7,6 @@ use homectl_types::{
 use super::bridge::BridgeLights;
 use super::{light_utils::bridge_light_to_device, HueConfig};
 use anyhow::anyhow;
-use palette::Yxy;
 use serde::{Deserialize, Serialize};
 use std::{error::Error, time::Duration};
 use tokio::time;
@@ -95,26 +94,13 @@ pub async fn set_device_state(config: HueConfig, device: &Device) -> Result<(),
 
             Ok(match state.color {
                 Some(DeviceColor::Color(color)) => {
-                    let hsv = color;
-                    let color: Yxy = color.into();
+                    // Colors are already clamped to the light's gamut by
+                    // homectl core
+                    let xy = Some(vec![color.x, color.y]);
 
-                    // palette hue value is [0, 360[, Hue uses [0, 65536[
-                    // let hue = ((color.hue.to_positive_degrees() / 360.0) * 65536.0).floor() as u16;
-
-                    // palette sat value is [0, 1], Hue uses [0, 254]
-                    // let sat = (f32::min(color.saturation * 254.0, 1.0)).floor() as u16;
-
-                    // palette bri value is [0, 1], Hue uses [0, 254]
-                    // let bri = (f32::min(color.value, 1.0) * 254.0).floor() as u16;
-
-                    let x = color.x;
-                    let y = color.y;
-
-                    let xy = Some(vec![x, y]);
-                    // let bri = (color.luma * 254.0 * state.brightness.unwrap_or(1.0) as f32).floor()
-                    //     as u32;
-                    let bri = (hsv.value * 254.0 * (state.brightness.unwrap_or(1.0) as f32)).floor()
-                        as u8;
+                    // brightness is [0, 1], Hue uses [0, 254]
+                    let bri =
+                        (color.brightness * 254.0 * state.brightness.unwrap_or(1.0)).floor() as u8;
 
                     HueMsg::LightMsg(LightMsg {
                         on: state.power,
@@ -8,15 +8,18 @@ use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use bridge::BridgeState;
 use homectl_types::{
-    device::{Capability, Device},
+    device::Device,
     event::{Message, TxEventChannel},
     integration::{Integration, IntegrationActionPayload, IntegrationId},
 };
 use serde::Deserialize;
+use tokio::task::JoinHandle;
+
+use crate::utils::is_task_finished;
 
 use light_utils::bridge_light_to_device;
 use lights::{poll_lights, set_device_state};
-use sensor_utils::bridge_sensor_to_device;
+use sensor_utils::{bridge_sensor_to_battery_device, bridge_sensor_to_device};
 use sensors::poll_sensors;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -32,6 +35,7 @@ pub struct Hue {
     event_tx: TxEventChannel,
     config: HueConfig,
     bridge_state: Option<BridgeState>,
+    poll_handles: Vec<JoinHandle<()>>,
 }
 
 #[async_trait]
@@ -47,6 +51,7 @@ impl Integration for Hue {
             config,
             event_tx,
             bridge_state: None,
+            poll_handles: Vec::new(),
         })
     }
 
@@ -69,17 +74,19 @@ impl Integration for Hue {
         self.bridge_state = Some(bridge_state.clone());
 
         for (id, bridge_light) in bridge_state.lights {
-            let capabilities = Capability {
-                Hsv: bridge_light.state.hue.is_some(),
-                Cct: bridge_light.state.ct.is_some(),
-            };
-            let mut device = bridge_light_to_device(id, self.id.clone(), bridge_light);
-            device.capabilities = Some(capabilities);
+            let device = bridge_light_to_device(id, self.id.clone(), bridge_light);
             self.event_tx
                 .send(Message::IntegrationDeviceRefresh { device });
         }
 
         for (id, bridge_sensor) in bridge_state.sensors {
+            if let Some(device) =
+                bridge_sensor_to_battery_device(&id, self.id.clone(), &bridge_sensor)
+            {
+                self.event_tx
+                    .send(Message::IntegrationDeviceRefresh { device });
+            }
+
             let device = bridge_sensor_to_device(id, self.id.clone(), bridge_sensor);
             self.event_tx
                 .send(Message::IntegrationDeviceRefresh { device });
@@ -103,9 +110,11 @@ impl Integration for Hue {
             let integration_id = self.id.clone();
             let sender = self.event_tx.clone();
 
-            tokio::spawn(async {
+            let handle = tokio::spawn(async {
                 poll_sensors(config, integration_id, sender, init_bridge_sensors).await
             });
+
+            self.poll_handles.push(handle);
         }
 
         {
@@ -113,13 +122,31 @@ impl Integration for Hue {
             let integration_id = self.id.clone();
             let sender = self.event_tx.clone();
 
-            tokio::spawn(async { poll_lights(config, integration_id, sender).await });
+            let handle = tokio::spawn(async { poll_lights(config, integration_id, sender).await });
+
+            self.poll_handles.push(handle);
         }
 
 
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        for poll_handle in self.poll_handles.drain(..) {
+            poll_handle.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.poll_handles.iter_mut().any(is_task_finished) {
+            return Err(anyhow!("Hue poll task exited unexpectedly"));
+        }
+
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
         match set_device_state(self.config.clone(), device).await {
             Ok(_) => {}
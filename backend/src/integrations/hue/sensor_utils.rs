@@ -1,8 +1,9 @@
 use super::bridge::{
-    BridgeButtonEvent, BridgeSensor, BridgeSensorId, BridgeSensors, ZLLSwitchState,
+    BridgeButtonEvent, BridgeSensor, BridgeSensorId, BridgeSensors, ZLLLightLevelState,
+    ZLLSwitchState, ZLLTemperatureState,
 };
 use homectl_types::{
-    device::{Device, DeviceId, DeviceState, SensorKind},
+    device::{Device, DeviceAvailability, DeviceId, DeviceState, SensorKind},
     integration::IntegrationId,
 };
 
@@ -87,16 +88,65 @@ pub fn find_bridge_sensor(
 fn get_bridge_sensor_name(bridge_sensor: BridgeSensor) -> String {
     match bridge_sensor {
         BridgeSensor::Daylight { name } => name,
-        BridgeSensor::ZLLLightLevel { name } => name,
+        BridgeSensor::ZLLLightLevel { name, .. } => name,
         BridgeSensor::ZLLPresence { name, .. } => name,
         BridgeSensor::ZLLSwitch { name, .. } => name,
-        BridgeSensor::ZLLTemperature { name } => name,
+        BridgeSensor::ZLLTemperature { name, .. } => name,
         BridgeSensor::CLIPPresence { name } => name,
         BridgeSensor::CLIPGenericStatus { name } => name,
         BridgeSensor::CLIPGenericFlag { name } => name,
     }
 }
 
+/// Returns whether the bridge can currently reach the sensor. CLIP sensors
+/// are virtual and thus always reachable.
+fn get_bridge_sensor_availability(bridge_sensor: &BridgeSensor) -> DeviceAvailability {
+    let reachable = match bridge_sensor {
+        BridgeSensor::ZLLLightLevel { config, .. } => config.reachable,
+        BridgeSensor::ZLLPresence { config, .. } => config.reachable,
+        BridgeSensor::ZLLSwitch { config, .. } => config.reachable,
+        BridgeSensor::ZLLTemperature { config, .. } => config.reachable,
+        _ => None,
+    };
+
+    DeviceAvailability::new(reachable.unwrap_or(true))
+}
+
+/// Returns battery level of BridgeSensor, if it is battery powered
+pub fn get_bridge_sensor_battery(bridge_sensor: &BridgeSensor) -> Option<u8> {
+    match bridge_sensor {
+        BridgeSensor::ZLLLightLevel { config, .. } => config.battery,
+        BridgeSensor::ZLLPresence { config, .. } => config.battery,
+        BridgeSensor::ZLLSwitch { config, .. } => config.battery,
+        BridgeSensor::ZLLTemperature { config, .. } => config.battery,
+        _ => None,
+    }
+}
+
+/// Converts the battery level of a battery powered BridgeSensor into a
+/// separate battery level sensor Device
+pub fn bridge_sensor_to_battery_device(
+    id: &DeviceId,
+    integration_id: IntegrationId,
+    bridge_sensor: &BridgeSensor,
+) -> Option<Device> {
+    let battery = get_bridge_sensor_battery(bridge_sensor)?;
+    let name = get_bridge_sensor_name(bridge_sensor.clone());
+
+    Some(Device {
+        id: DeviceId::new(&format!("sensors/{}/battery", id)),
+        name: format!("{} battery", name),
+        integration_id,
+        scene: None,
+        state: DeviceState::Sensor(SensorKind::BatteryLevelSensor {
+            value: f32::from(battery),
+        }),
+        capabilities: None,
+        availability: get_bridge_sensor_availability(bridge_sensor),
+        manual_override: None,
+    })
+}
+
 /// Converts BridgeSensor into Device
 pub fn bridge_sensor_to_device(
     id: DeviceId,
@@ -106,6 +156,7 @@ pub fn bridge_sensor_to_device(
     let id = DeviceId::new(&format!("sensors/{}", id));
     let name = get_bridge_sensor_name(bridge_sensor.clone());
     let scene = None;
+    let availability = get_bridge_sensor_availability(&bridge_sensor);
 
     match bridge_sensor {
         BridgeSensor::ZLLPresence { state, .. } => {
@@ -120,6 +171,8 @@ pub fn bridge_sensor_to_device(
                 scene,
                 state: kind,
                 capabilities: None,
+                availability,
+                manual_override: None,
             }
         }
 
@@ -154,6 +207,56 @@ pub fn bridge_sensor_to_device(
                 scene,
                 state: kind,
                 capabilities: None,
+                availability,
+                manual_override: None,
+            }
+        }
+
+        BridgeSensor::ZLLLightLevel {
+            state:
+                ZLLLightLevelState {
+                    lightlevel: Some(lightlevel),
+                    ..
+                },
+            ..
+        } => {
+            let kind = DeviceState::Sensor(SensorKind::IlluminanceSensor {
+                value: f32::powf(10.0, (lightlevel as f32 - 1.0) / 10000.0),
+            });
+
+            Device {
+                id,
+                name,
+                integration_id,
+                scene,
+                state: kind,
+                capabilities: None,
+                availability,
+                manual_override: None,
+            }
+        }
+
+        BridgeSensor::ZLLTemperature {
+            state:
+                ZLLTemperatureState {
+                    temperature: Some(temperature),
+                    ..
+                },
+            ..
+        } => {
+            let kind = DeviceState::Sensor(SensorKind::TemperatureSensor {
+                value: temperature as f32 / 100.0,
+            });
+
+            Device {
+                id,
+                name,
+                integration_id,
+                scene,
+                state: kind,
+                capabilities: None,
+                availability,
+                manual_override: None,
             }
         }
 
@@ -167,6 +270,8 @@ pub fn bridge_sensor_to_device(
                 scene,
                 state: kind,
                 capabilities: None,
+                availability,
+                manual_override: None,
             }
         }
     }
@@ -216,6 +321,11 @@ pub fn extrapolate_sensor_updates(
         // need to worry about missing out on updates
         (_, BridgeSensor::ZLLPresence { .. }) => vec![next_bridge_sensor],
 
+        // Same goes for numeric sensors, we're only interested in the most
+        // recent reading
+        (_, BridgeSensor::ZLLLightLevel { .. }) => vec![next_bridge_sensor],
+        (_, BridgeSensor::ZLLTemperature { .. }) => vec![next_bridge_sensor],
+
         // ZLLSwitches can be pressed quickly, and a naive polling implementation would
         // miss out on a lot of button state transition events.
         (
@@ -234,6 +344,7 @@ pub fn extrapolate_sensor_updates(
                         lastupdated: next_lastupdated,
                     },
                 name,
+                config,
             },
         ) => {
             let mut updates = Vec::new();
@@ -260,6 +371,7 @@ pub fn extrapolate_sensor_updates(
                         lastupdated: next_lastupdated.clone(),
                     },
                     name: name.clone(),
+                    config: config.clone(),
                 });
             }
 
@@ -274,6 +386,7 @@ pub fn extrapolate_sensor_updates(
                         lastupdated: next_lastupdated.clone(),
                     },
                     name: name.clone(),
+                    config: config.clone(),
                 });
             }
 
@@ -288,6 +401,7 @@ pub fn extrapolate_sensor_updates(
                         lastupdated: next_lastupdated,
                     },
                     name,
+                    config,
                 });
             }
 
@@ -299,3 +413,57 @@ pub fn extrapolate_sensor_updates(
         _ => Vec::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bridge_sensor(json: &str) -> BridgeSensor {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn maps_battery_level() {
+        let sensor = bridge_sensor(
+            r#"{
+                "type": "ZLLPresence",
+                "name": "Hallway motion sensor",
+                "state": { "presence": false, "lastupdated": "2021-03-01T12:00:00" },
+                "config": { "reachable": true, "battery": 85 }
+            }"#,
+        );
+        let integration_id = IntegrationId::from("hue1".to_string());
+        let device =
+            bridge_sensor_to_battery_device(&DeviceId::new("5"), integration_id, &sensor).unwrap();
+
+        assert_eq!(device.id, DeviceId::new("sensors/5/battery"));
+        assert_eq!(device.name, "Hallway motion sensor battery");
+        assert_eq!(
+            device.state,
+            DeviceState::Sensor(SensorKind::BatteryLevelSensor { value: 85.0 })
+        );
+    }
+
+    #[test]
+    fn skips_sensors_without_battery() {
+        let integration_id = IntegrationId::from("hue1".to_string());
+        let mains_powered = bridge_sensor(
+            r#"{
+                "type": "ZLLSwitch",
+                "name": "Dimmer",
+                "state": { "buttonevent": null, "lastupdated": "none" },
+                "config": { "reachable": true, "battery": null }
+            }"#,
+        );
+        let virtual_sensor = bridge_sensor(r#"{ "type": "CLIPPresence", "name": "Virtual" }"#);
+
+        for sensor in [mains_powered, virtual_sensor] {
+            let device = bridge_sensor_to_battery_device(
+                &DeviceId::new("1"),
+                integration_id.clone(),
+                &sensor,
+            );
+            assert!(device.is_none());
+        }
+    }
+}
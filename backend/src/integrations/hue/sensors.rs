@@ -1,6 +1,9 @@
 use super::{
     bridge::BridgeSensors,
-    sensor_utils::{bridge_sensor_to_device, extrapolate_sensor_updates, find_bridge_sensor},
+    sensor_utils::{
+        bridge_sensor_to_battery_device, bridge_sensor_to_device, extrapolate_sensor_updates,
+        find_bridge_sensor,
+    },
     HueConfig,
 };
 use anyhow::anyhow;
@@ -50,6 +53,15 @@ pub async fn do_refresh_sensors(
     for (sensor_id, bridge_sensor) in result {
         let prev_bridge_sensor = find_bridge_sensor(&prev_bridge_sensors, &sensor_id);
 
+        if prev_bridge_sensor.as_ref() != Some(&bridge_sensor) {
+            let battery_device =
+                bridge_sensor_to_battery_device(&sensor_id, integration_id.clone(), &bridge_sensor);
+
+            if let Some(device) = battery_device {
+                sender.send(Message::IntegrationDeviceRefresh { device });
+            }
+        }
+
         let events = extrapolate_sensor_updates(prev_bridge_sensor, bridge_sensor)
             .into_iter()
             .map(|bridge_sensor| Message::IntegrationDeviceRefresh {
@@ -1,20 +1,34 @@
 use anyhow::{Context, Result};
 
 use super::{
-    utils::{from_lifx_state, read_lifx_msg, LifxMsg},
+    utils::{from_lifx_multizone, from_lifx_state, read_lifx_msg, LifxColor, LifxMsg, LifxState},
     LifxConfig,
 };
 use homectl_types::{
+    device::{Device, DeviceAvailability},
     event::{Message, TxEventChannel},
     integration::IntegrationId,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{net::SocketAddr, time::Duration};
-use tokio::{time, sync::mpsc::UnboundedSender};
+use tokio::{time, sync::mpsc::UnboundedSender, task::JoinHandle};
 use tokio::{net::UdpSocket};
 
 const MAX_UDP_PACKET_SIZE: usize = 1 << 16;
 
+/// How often lights are asked to report their state
+const POLL_RATE_MS: u64 = 1000;
+
+/// Lights that haven't responded to this many consecutive polls are reported
+/// offline
+const OFFLINE_AFTER_POLLS: u64 = 5;
+
+/// Message type of GetColorZones, which regular lights respond to with
+/// StateUnhandled
+const GET_COLOR_ZONES_MSG_TYPE: u16 = 502;
+
 pub async fn init_udp_socket(_config: &LifxConfig) -> Result<UdpSocket> {
     // Setup the UDP socket. LIFX uses port 56700.
     let addr: SocketAddr = "0.0.0.0:56700".parse()?;
@@ -27,52 +41,260 @@ pub async fn init_udp_socket(_config: &LifxConfig) -> Result<UdpSocket> {
     Ok(socket)
 }
 
-pub async fn handle_lifx_msg(msg: LifxMsg, integration_id: IntegrationId, sender: TxEventChannel) {
-    if let LifxMsg::State(state) = msg {
-        let device = from_lifx_state(state, integration_id);
+/// Multizone devices respond to both Get and GetColorZones, and the zone
+/// colors may arrive over several messages. Keeps track of what has been
+/// received so far, so that each device is reported as a single kind of
+/// light.
+#[derive(Default)]
+pub struct LifxDeviceTracker {
+    /// Most recent State message of each device
+    states: HashMap<SocketAddr, LifxState>,
+
+    /// Zone colors received so far, only contains multizone devices
+    zones: HashMap<SocketAddr, Vec<Option<LifxColor>>>,
+
+    /// Whether each device is known to be a multizone device. Devices are
+    /// missing from here until they've responded to GetColorZones.
+    multizone: HashMap<SocketAddr, bool>,
+
+    /// When each device last responded with a State message
+    last_seen: HashMap<SocketAddr, Instant>,
+
+    /// Most recently reported device of each address
+    devices: HashMap<SocketAddr, Device>,
+}
+
+impl LifxDeviceTracker {
+    fn report(&mut self, addr: SocketAddr, device: Device, sender: &TxEventChannel) {
+        self.devices.insert(addr, device.clone());
         sender.send(Message::IntegrationDeviceRefresh { device });
     }
+
+    /// Reports devices that have stopped responding to polls as offline,
+    /// along with their last known state
+    fn report_offline_devices(&mut self, sender: &TxEventChannel, now: Instant) {
+        let offline_after = Duration::from_millis(POLL_RATE_MS * OFFLINE_AFTER_POLLS);
+
+        for (addr, device) in self.devices.iter_mut() {
+            let responding = match self.last_seen.get(addr) {
+                Some(last_seen) => now.duration_since(*last_seen) < offline_after,
+                None => false,
+            };
+
+            if !responding && device.availability.online {
+                device.availability = DeviceAvailability::new(false);
+
+                sender.send(Message::IntegrationDeviceRefresh {
+                    device: device.clone(),
+                });
+            }
+        }
+    }
+}
+
+pub async fn handle_lifx_msg(
+    msg: LifxMsg,
+    tracker: &mut LifxDeviceTracker,
+    integration_id: IntegrationId,
+    sender: TxEventChannel,
+) {
+    match msg {
+        LifxMsg::State(state) => {
+            let addr = state.addr;
+            let seen_before = tracker.states.insert(addr, state.clone()).is_some();
+            tracker.last_seen.insert(addr, Instant::now());
+
+            let multizone = match tracker.multizone.get(&addr) {
+                Some(multizone) => *multizone,
+
+                // Give devices one poll interval to respond to GetColorZones,
+                // so multizone devices don't briefly show up as regular
+                // lights. Older firmware may not respond at all to
+                // unsupported messages, so assume a regular light after that.
+                None if seen_before => {
+                    tracker.multizone.insert(addr, false);
+                    false
+                }
+                None => return,
+            };
+
+            // Multizone devices are reported once all zone colors arrive
+            if !multizone {
+                let device = from_lifx_state(state, integration_id);
+                tracker.report(addr, device, &sender);
+            }
+        }
+        LifxMsg::StateMultiZone(zones) => {
+            tracker.multizone.insert(zones.addr, true);
+
+            let buffer = tracker.zones.entry(zones.addr).or_default();
+            buffer.resize(usize::from(zones.count), None);
+
+            for (i, color) in zones.colors.into_iter().enumerate() {
+                if let Some(zone) = buffer.get_mut(usize::from(zones.index) + i) {
+                    *zone = Some(color);
+                }
+            }
+
+            if buffer.is_empty() || buffer.iter().any(Option::is_none) {
+                return;
+            }
+
+            let colors: Vec<LifxColor> = buffer.drain(..).flatten().collect();
+
+            if let Some(state) = tracker.states.get(&zones.addr) {
+                let device = from_lifx_multizone(state.clone(), &colors, integration_id);
+                tracker.report(zones.addr, device, &sender);
+            }
+        }
+        LifxMsg::StateUnhandled(addr, GET_COLOR_ZONES_MSG_TYPE) => {
+            let known = tracker.multizone.insert(addr, false).is_some();
+
+            // Report devices whose State reply arrived before this one
+            // without waiting for the next poll
+            if !known {
+                if let Some(state) = tracker.states.get(&addr) {
+                    let device = from_lifx_state(state.clone(), integration_id);
+                    tracker.report(addr, device, &sender);
+                }
+            }
+        }
+        _ => {}
+    }
 }
 
 pub fn listen_udp_stream(
     socket: Arc<UdpSocket>,
     integration_id: IntegrationId,
     sender: TxEventChannel,
-) {
+) -> JoinHandle<()> {
     let mut buf: [u8; MAX_UDP_PACKET_SIZE] = [0; MAX_UDP_PACKET_SIZE];
     tokio::spawn(async move {
+        let mut tracker = LifxDeviceTracker::default();
+        let poll_rate = Duration::from_millis(POLL_RATE_MS);
+
         loop {
-            let res = socket.recv_from(&mut buf).await;
+            // Wake up at least once per poll interval, so that devices that
+            // stopped responding are noticed even if no other messages arrive
+            let res = time::timeout(poll_rate, socket.recv_from(&mut buf)).await;
 
             match res {
                 // FIXME: should probably do some sanity checks on bytes_read
-                Ok((_bytes_read, addr)) => {
+                Ok(Ok((_bytes_read, addr))) => {
                     let msg = read_lifx_msg(&buf, addr);
 
-                    handle_lifx_msg(msg, integration_id.clone(), sender.clone()).await;
+                    handle_lifx_msg(msg, &mut tracker, integration_id.clone(), sender.clone())
+                        .await;
                 }
-                Err(e) => {
+                Ok(Err(e)) => {
                     println!("Error in udp recv_from {}", e);
                 }
+                Err(_) => {}
             }
+
+            tracker.report_offline_devices(&sender, Instant::now());
         }
-    });
+    })
 }
 
 pub async fn poll_lights(udp_sender_tx: UnboundedSender<LifxMsg>) -> Result<()> {
-    let poll_rate = Duration::from_millis(1000);
+    let poll_rate = Duration::from_millis(POLL_RATE_MS);
     let mut interval = time::interval(poll_rate);
 
     // TODO: find and use the subnet broadcast address instead
     let broadcast_addr: SocketAddr = "255.255.255.255:56700".parse()?;
 
-    let msg = LifxMsg::Get(broadcast_addr);
+    // Ask for zones first, so that whether a device is a multizone device is
+    // usually known by the time its State reply arrives
+    let msgs = [
+        LifxMsg::GetColorZones(broadcast_addr),
+        LifxMsg::Get(broadcast_addr),
+    ];
 
     loop {
         interval.tick().await;
 
-        udp_sender_tx
-            .send(msg.clone())
-            .expect("Expected to be able to send to lifx channel");
+        for msg in &msgs {
+            udp_sender_tx
+                .send(msg.clone())
+                .expect("Expected to be able to send to lifx channel");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use homectl_types::event::{mk_channel, RxEventChannel};
+
+    fn mk_state(addr: SocketAddr) -> LifxState {
+        LifxState {
+            hue: 0,
+            sat: 0,
+            bri: 65535,
+            power: 65535,
+            label: "Lamp".to_string(),
+            addr,
+            transition: None,
+        }
+    }
+
+    /// Returns availability of each device refresh sent so far
+    async fn refreshes(receiver: &mut RxEventChannel) -> Vec<bool> {
+        let mut online = vec![];
+
+        while let Ok(Some(msg)) = time::timeout(Duration::from_millis(10), receiver.recv()).await {
+            if let Message::IntegrationDeviceRefresh { device } = msg {
+                online.push(device.availability.online);
+            }
+        }
+
+        online
+    }
+
+    async fn handle(msg: LifxMsg, tracker: &mut LifxDeviceTracker, sender: &TxEventChannel) {
+        let integration_id = IntegrationId::from("lifx".to_string());
+        handle_lifx_msg(msg, tracker, integration_id, sender.clone()).await;
+    }
+
+    #[tokio::test]
+    async fn reports_unresponsive_devices_offline_once() {
+        let (sender, mut receiver) = mk_channel();
+        let mut tracker = LifxDeviceTracker::default();
+        let addr: SocketAddr = "192.168.1.10:56700".parse().unwrap();
+        let offline_after = Duration::from_millis(POLL_RATE_MS * OFFLINE_AFTER_POLLS);
+
+        let start = Instant::now();
+        handle(LifxMsg::StateUnhandled(addr, 502), &mut tracker, &sender).await;
+        handle(LifxMsg::State(mk_state(addr)), &mut tracker, &sender).await;
+        assert_eq!(refreshes(&mut receiver).await, vec![true]);
+
+        // Missing a few polls isn't enough to be considered offline
+        tracker.report_offline_devices(&sender, start + offline_after - Duration::from_millis(1));
+        assert_eq!(refreshes(&mut receiver).await, Vec::<bool>::new());
+
+        let later = Instant::now() + offline_after;
+        tracker.report_offline_devices(&sender, later);
+        assert_eq!(refreshes(&mut receiver).await, vec![false]);
+
+        tracker.report_offline_devices(&sender, later + offline_after);
+        assert_eq!(refreshes(&mut receiver).await, Vec::<bool>::new());
+    }
+
+    #[tokio::test]
+    async fn reports_devices_online_when_they_respond_again() {
+        let (sender, mut receiver) = mk_channel();
+        let mut tracker = LifxDeviceTracker::default();
+        let addr: SocketAddr = "192.168.1.10:56700".parse().unwrap();
+        let offline_after = Duration::from_millis(POLL_RATE_MS * OFFLINE_AFTER_POLLS);
+
+        handle(LifxMsg::StateUnhandled(addr, 502), &mut tracker, &sender).await;
+        handle(LifxMsg::State(mk_state(addr)), &mut tracker, &sender).await;
+        tracker.report_offline_devices(&sender, Instant::now() + offline_after);
+        assert_eq!(refreshes(&mut receiver).await, vec![true, false]);
+
+        handle(LifxMsg::State(mk_state(addr)), &mut tracker, &sender).await;
+        tracker.report_offline_devices(&sender, Instant::now());
+        assert_eq!(refreshes(&mut receiver).await, vec![true]);
     }
 }
@@ -1,7 +1,7 @@
 pub mod lights;
 pub mod utils;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use homectl_types::{
     device::Device,
@@ -11,8 +11,13 @@ use homectl_types::{
 use lights::{init_udp_socket, listen_udp_stream, poll_lights};
 use serde::Deserialize;
 use std::sync::Arc;
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
-use utils::{mk_lifx_udp_msg, to_lifx_state, LifxMsg};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    task::JoinHandle,
+};
+use utils::{mk_lifx_udp_msg, to_lifx_msgs, LifxMsg};
+
+use crate::utils::is_task_finished;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct LifxConfig {}
@@ -22,6 +27,7 @@ pub struct Lifx {
     config: LifxConfig,
     event_tx: TxEventChannel,
     udp_tx: Option<UnboundedSender<LifxMsg>>,
+    task_handles: Vec<JoinHandle<()>>,
 }
 
 #[async_trait]
@@ -37,6 +43,7 @@ impl Integration for Lifx {
             config,
             event_tx,
             udp_tx: None,
+            task_handles: Vec::new(),
         })
     }
 
@@ -56,11 +63,15 @@ impl Integration for Lifx {
         let socket = init_udp_socket(&config).await?;
         let socket = Arc::new(socket);
 
-        listen_udp_stream(Arc::clone(&socket), integration_id, sender);
+        let listen_handle = listen_udp_stream(Arc::clone(&socket), integration_id, sender);
 
-        tokio::spawn(async move { poll_lights(udp_sender_tx).await });
+        let poll_handle = tokio::spawn(async move {
+            if let Err(e) = poll_lights(udp_sender_tx).await {
+                println!("Error while polling lifx lights: {}", e);
+            }
+        });
 
-        tokio::spawn(async move {
+        let send_handle = tokio::spawn(async move {
             loop {
                 let res = { udp_sender_rx.recv().await };
 
@@ -70,6 +81,10 @@ impl Integration for Lifx {
                         LifxMsg::SetColor(state) => state.addr,
                         LifxMsg::State(state) => state.addr,
                         LifxMsg::SetPower(state) => state.addr,
+                        LifxMsg::GetColorZones(addr) => addr,
+                        LifxMsg::SetColorZones(zones) => zones.addr,
+                        LifxMsg::StateMultiZone(zones) => zones.addr,
+                        LifxMsg::StateUnhandled(addr, _) => addr,
                         _ => panic!("Send unknown LifxMsg not supported"),
                     };
 
@@ -84,6 +99,8 @@ impl Integration for Lifx {
             }
         });
 
+        self.task_handles = vec![listen_handle, poll_handle, send_handle];
+
         Ok(())
     }
 
@@ -93,23 +110,33 @@ impl Integration for Lifx {
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        // Aborting the tasks also drops the UDP socket they share
+        for task_handle in self.task_handles.drain(..) {
+            task_handle.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.task_handles.iter_mut().any(is_task_finished) {
+            return Err(anyhow!("Lifx task exited unexpectedly"));
+        }
+
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
-        let lifx_state = to_lifx_state(device);
-
-        match lifx_state {
-            Ok(lifx_state) => {
-                self.udp_tx
-                    .as_ref()
-                    .expect("Expected udp_tx to be set")
-                    .send(LifxMsg::SetPower(lifx_state.clone()))
-                    .expect("Expected to be able to send to lifx channel");
-
-                // don't bother setting color if power is off
-                if lifx_state.power != 0 {
+        let lifx_msgs = to_lifx_msgs(device);
+
+        match lifx_msgs {
+            Ok(lifx_msgs) => {
+                for lifx_msg in lifx_msgs {
                     self.udp_tx
                         .as_ref()
                         .expect("Expected udp_tx to be set")
-                        .send(LifxMsg::SetColor(lifx_state))
+                        .send(lifx_msg)
                         .expect("Expected to be able to send to lifx channel");
                 }
             }
@@ -1,6 +1,9 @@
 use anyhow::{anyhow, Result};
 use byteorder::{ByteOrder, LittleEndian};
-use homectl_types::device::{Capability, Device, DeviceColor, DeviceId, DeviceState, Light};
+use homectl_types::color::{ColorGamut, ColorXy};
+use homectl_types::device::{
+    Capability, Device, DeviceColor, DeviceId, DeviceState, Light, MultiSourceLight,
+};
 use homectl_types::integration::IntegrationId;
 use palette::Hsv;
 use std::net::SocketAddr;
@@ -16,12 +19,46 @@ pub struct LifxState {
     pub transition: Option<u32>,
 }
 
+/// Color of a single zone of a multizone device, e.g. LIFX Z or Beam
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LifxColor {
+    pub hue: u16,
+    pub sat: u16,
+    pub bri: u16,
+}
+
+/// Colors of consecutive zones of a multizone device, starting from `index`
+#[derive(Clone, Debug)]
+pub struct LifxZones {
+    pub addr: SocketAddr,
+    pub count: u8,
+    pub index: u8,
+    pub colors: Vec<LifxColor>,
+}
+
+/// Sets zones `start_index` to `end_index` (inclusive) of a multizone device
+#[derive(Clone, Debug)]
+pub struct LifxSetZones {
+    pub addr: SocketAddr,
+    pub start_index: u8,
+    pub end_index: u8,
+    pub color: LifxColor,
+    pub transition: Option<u32>,
+
+    /// Devices buffer zone changes until a message with `apply` set arrives
+    pub apply: bool,
+}
+
 #[derive(Clone, Debug)]
 pub enum LifxMsg {
     Get(SocketAddr),
     SetColor(LifxState),
     State(LifxState),
     SetPower(LifxState),
+    GetColorZones(SocketAddr),
+    SetColorZones(LifxSetZones),
+    StateMultiZone(LifxZones),
+    StateUnhandled(SocketAddr, u16),
     Unknown,
 }
 
@@ -31,6 +68,10 @@ pub fn lifx_msg_type_to_u16(msg_type: LifxMsg) -> u16 {
         LifxMsg::SetColor(_) => 102,
         LifxMsg::State(_) => 107,
         LifxMsg::SetPower(_) => 117,
+        LifxMsg::GetColorZones(_) => 502,
+        LifxMsg::SetColorZones(_) => 501,
+        LifxMsg::StateMultiZone(_) => 506,
+        LifxMsg::StateUnhandled(_, _) => 223,
         LifxMsg::Unknown => panic!("Cannot convert LifxMsg::Unknown to u16"),
     }
 }
@@ -68,6 +109,27 @@ fn mk_lifx_msg_payload(lifx_msg: LifxMsg) -> Option<Vec<u8>> {
 
             Some(buf.to_vec())
         }
+        LifxMsg::GetColorZones(_) => {
+            // Request all zones, devices clamp end_index to their zone count
+            Some(vec![0, 255])
+        }
+        LifxMsg::SetColorZones(zones) => {
+            let mut buf: [u8; 15] = [0; 15];
+
+            buf[0] = zones.start_index;
+            buf[1] = zones.end_index;
+            LittleEndian::write_u16(&mut buf[2..], zones.color.hue);
+            LittleEndian::write_u16(&mut buf[4..], zones.color.sat);
+            LittleEndian::write_u16(&mut buf[6..], zones.color.bri);
+            LittleEndian::write_u16(&mut buf[8..], 6500);
+
+            let t = zones.transition.unwrap_or(500);
+            LittleEndian::write_u32(&mut buf[10..], t);
+
+            buf[14] = if zones.apply { 1 } else { 0 };
+
+            Some(buf.to_vec())
+        }
         _ => None,
     }
 }
@@ -93,7 +155,7 @@ pub fn mk_lifx_udp_msg(lifx_msg: LifxMsg) -> Vec<u8> {
     let mut frame_address: [u8; 16] = [0; 16];
     let ack_required = 0;
     let res_required = match lifx_msg {
-        LifxMsg::Get(_) => 1,
+        LifxMsg::Get(_) | LifxMsg::GetColorZones(_) => 1,
         _ => 0,
     };
 
@@ -156,25 +218,103 @@ pub fn read_lifx_msg(buf: &[u8], addr: SocketAddr) -> LifxMsg {
 
             LifxMsg::State(state)
         }
+        503 => {
+            // StateZone (503) message, sent instead of StateMultiZone when
+            // only one zone was requested
+            // https://lan.developer.lifx.com/docs/multizone-messages#statezone---packet-503
+
+            LifxMsg::StateMultiZone(LifxZones {
+                addr,
+                count: payload[0],
+                index: payload[1],
+                colors: vec![read_lifx_color(&payload[2..])],
+            })
+        }
+        506 => {
+            // StateMultiZone (506) message, response to GetColorZones (502).
+            // Contains colors of up to 8 zones.
+            // https://lan.developer.lifx.com/docs/multizone-messages#statemultizone---packet-506
+
+            let count = payload[0];
+            let index = payload[1];
+            let num_colors = usize::from(count.saturating_sub(index)).min(8);
+
+            let colors = (0..num_colors)
+                .map(|i| read_lifx_color(&payload[(2 + i * 8)..]))
+                .collect();
+
+            LifxMsg::StateMultiZone(LifxZones {
+                addr,
+                count,
+                index,
+                colors,
+            })
+        }
+        223 => {
+            // StateUnhandled (223) message, sent in response to messages the
+            // device doesn't support, e.g. GetColorZones on regular bulbs
+            // https://lan.developer.lifx.com/docs/device-messages#stateunhandled---packet-223
+
+            let unhandled_type = LittleEndian::read_u16(payload);
+
+            LifxMsg::StateUnhandled(addr, unhandled_type)
+        }
         _ => LifxMsg::Unknown,
     }
 }
 
-pub fn from_lifx_state(lifx_state: LifxState, integration_id: IntegrationId) -> Device {
-    let hue = from_lifx_hue((f32::from(lifx_state.hue) / 65535.0) * 360.0);
-    let sat = f32::from(lifx_state.sat) / 65535.0;
-    let bri = f32::from(lifx_state.bri) / 65535.0;
+fn read_lifx_color(buf: &[u8]) -> LifxColor {
+    LifxColor {
+        hue: LittleEndian::read_u16(buf),
+        sat: LittleEndian::read_u16(&buf[2..]),
+        bri: LittleEndian::read_u16(&buf[4..]),
+    }
+}
+
+fn from_lifx_color(color: LifxColor) -> Hsv {
+    let hue = from_lifx_hue((f32::from(color.hue) / 65535.0) * 360.0);
+    let sat = f32::from(color.sat) / 65535.0;
+    let bri = f32::from(color.bri) / 65535.0;
+
+    Hsv::new(hue, sat, bri)
+}
+
+fn to_lifx_color(color: &ColorXy, brightness: Option<f32>) -> LifxColor {
+    let color = color.to_hsv();
 
+    LifxColor {
+        hue: ((to_lifx_hue(color.hue.to_positive_degrees()) / 360.0) * 65535.0).floor() as u16,
+        sat: (color.saturation * 65535.0).floor() as u16,
+        bri: (brightness.unwrap_or(1.0) * color.value * 65535.0).floor() as u16,
+    }
+}
+
+pub fn from_lifx_state(lifx_state: LifxState, integration_id: IntegrationId) -> Device {
     let power = lifx_state.power == 65535;
 
-    let color = Hsv::new(hue, sat, bri);
+    let color = from_lifx_color(LifxColor {
+        hue: lifx_state.hue,
+        sat: lifx_state.sat,
+        bri: lifx_state.bri,
+    });
 
     let transition_ms = lifx_state.transition.map(|transition| transition as u64);
 
+    // Color temperatures are converted to colors by homectl core, as support
+    // for Lifx color temperature mode is not implemented
+    let capabilities = Capability {
+        hsv: true,
+        cct: false,
+        color_gamut: Some(ColorGamut::Lifx),
+        dimmable: true,
+        transitions: true,
+        ..Default::default()
+    };
+
     let state = DeviceState::Light(Light::new(
         power,
         None,
-        Some(DeviceColor::Color(color)),
+        Some(DeviceColor::Color(color.into())),
         transition_ms,
     ));
 
@@ -184,10 +324,36 @@ pub fn from_lifx_state(lifx_state: LifxState, integration_id: IntegrationId) ->
         integration_id,
         scene: None,
         state,
-        capabilities: None,
+        capabilities: Some(capabilities),
+        availability: Default::default(),
+        manual_override: None,
     }
 }
 
+/// Creates a device with one light source per zone of a multizone device
+pub fn from_lifx_multizone(
+    lifx_state: LifxState,
+    colors: &[LifxColor],
+    integration_id: IntegrationId,
+) -> Device {
+    let power = lifx_state.power == 65535;
+    let transition_ms = lifx_state.transition.map(|transition| transition as u64);
+
+    let mut device = from_lifx_state(lifx_state, integration_id);
+
+    device.state = DeviceState::MultiSourceLight(MultiSourceLight {
+        power,
+        brightness: None,
+        lights: colors
+            .iter()
+            .map(|color| DeviceColor::Color(from_lifx_color(*color).into()))
+            .collect(),
+        transition_ms,
+    });
+
+    device
+}
+
 pub fn to_lifx_state(device: &Device) -> Result<LifxState> {
     let light_state = match device.state.clone() {
         DeviceState::Light(Light {
@@ -201,6 +367,8 @@ pub fn to_lifx_state(device: &Device) -> Result<LifxState> {
             color,
             transition_ms,
         }),
+        // Power and transition are shared by all zones
+        DeviceState::MultiSourceLight(state) => Ok(state.to_light()),
         _ => Err(anyhow!("Unsupported device state")),
     }?;
 
@@ -211,11 +379,7 @@ pub fn to_lifx_state(device: &Device) -> Result<LifxState> {
 
     match light_state.color {
         Some(DeviceColor::Color(color)) => {
-            let hue =
-                ((to_lifx_hue(color.hue.to_positive_degrees()) / 360.0) * 65535.0).floor() as u16;
-            let sat = (color.saturation * 65535.0).floor() as u16;
-            let bri =
-                (light_state.brightness.unwrap_or(1.0) * color.value * 65535.0).floor() as u16;
+            let LifxColor { hue, sat, bri } = to_lifx_color(&color, light_state.brightness);
 
             Ok(LifxState {
                 hue,
@@ -228,6 +392,8 @@ pub fn to_lifx_state(device: &Device) -> Result<LifxState> {
             })
         }
         Some(DeviceColor::Cct(_)) => Err(anyhow!(
+            // Should not happen as our capabilities tell homectl core to
+            // convert color temperatures to colors.
             // TODO: https://community.lifx.com/t/lan-protocol-switching-between-colour-and-white/1383
             // https://lan.developer.lifx.com/docs/representing-color-with-hsbk
             "Support for Lifx color temperature mode not implemented"
@@ -244,6 +410,49 @@ pub fn to_lifx_state(device: &Device) -> Result<LifxState> {
     }
 }
 
+/// Converts a device state into the messages needed to apply it. Multizone
+/// devices get one SetColorZones message per run of identically colored zones.
+pub fn to_lifx_msgs(device: &Device) -> Result<Vec<LifxMsg>> {
+    let lifx_state = to_lifx_state(device)?;
+    let mut msgs = vec![LifxMsg::SetPower(lifx_state.clone())];
+
+    // Don't bother setting colors of lights that are powered off
+    if lifx_state.power == 0 {
+        return Ok(msgs);
+    }
+
+    match &device.state {
+        DeviceState::MultiSourceLight(state) => {
+            let colors: Vec<LifxColor> = state
+                .lights
+                .iter()
+                .map(|color| to_lifx_color(&color.to_xy(), state.brightness))
+                .collect();
+
+            let mut start_index = 0;
+            for index in 0..colors.len() {
+                let is_last = index + 1 == colors.len();
+
+                if is_last || colors[index] != colors[index + 1] {
+                    msgs.push(LifxMsg::SetColorZones(LifxSetZones {
+                        addr: lifx_state.addr,
+                        start_index: start_index as u8,
+                        end_index: index as u8,
+                        color: colors[index],
+                        transition: lifx_state.transition,
+                        apply: is_last,
+                    }));
+
+                    start_index = index + 1;
+                }
+            }
+        }
+        _ => msgs.push(LifxMsg::SetColor(lifx_state)),
+    }
+
+    Ok(msgs)
+}
+
 // NOTE: this is complete trial-and-error, but seems to produce a wider range of
 // yellow hues which matches my other HA systems better.
 pub fn to_lifx_hue(h: f32) -> f32 {
@@ -0,0 +1,213 @@
+pub mod utils;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use homectl_types::{
+    device::{Device, DeviceAvailability},
+    event::{Message, TxEventChannel},
+    integration::{Integration, IntegrationActionPayload, IntegrationId},
+};
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::{task::JoinHandle, time};
+
+use utils::{device_to_mqtt, mqtt_to_device, MqttDevice};
+
+use crate::utils::is_task_finished;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MqttConfig {
+    host: String,
+    port: u16,
+    client_id: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+
+    /// Topic to subscribe to for device state updates, may contain MQTT
+    /// wildcards (e.g. "homectl/devices/+")
+    topic: String,
+
+    /// Topic used when publishing device state changes. "{id}" is replaced
+    /// with the id of the device
+    topic_set: String,
+}
+
+pub struct Mqtt {
+    id: IntegrationId,
+    config: MqttConfig,
+    event_tx: TxEventChannel,
+    client: Option<AsyncClient>,
+    poll_handle: Option<JoinHandle<()>>,
+}
+
+#[async_trait]
+impl Integration for Mqtt {
+    fn new(id: &IntegrationId, config: &config::Value, event_tx: TxEventChannel) -> Result<Self> {
+        let config = config
+            .clone()
+            .try_into()
+            .context("Failed to deserialize config of Mqtt integration")?;
+
+        Ok(Mqtt {
+            id: id.clone(),
+            config,
+            event_tx,
+            client: None,
+            poll_handle: None,
+        })
+    }
+
+    async fn register(&mut self) -> Result<()> {
+        let client_id = self
+            .config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("homectl-{}", self.id));
+
+        let mut options = MqttOptions::new(client_id, &self.config.host, self.config.port);
+        options.set_keep_alive(Duration::from_secs(5));
+
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            options.set_credentials(username, password);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 10);
+        self.client = Some(client.clone());
+
+        let config = self.config.clone();
+        let integration_id = self.id.clone();
+        let sender = self.event_tx.clone();
+
+        let poll_handle = tokio::spawn(async move {
+            poll_eventloop(eventloop, client, config, integration_id, sender).await
+        });
+        self.poll_handle = Some(poll_handle);
+
+        println!("registered mqtt integration {}", self.id);
+
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<()> {
+        println!("started mqtt integration {}", self.id);
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<()> {
+        // Dropping the eventloop closes the connection to the broker
+        if let Some(poll_handle) = self.poll_handle.take() {
+            poll_handle.abort();
+        }
+
+        self.client = None;
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.poll_handle.as_mut().map_or(false, is_task_finished) {
+            return Err(anyhow!("MQTT event loop exited unexpectedly"));
+        }
+
+        Ok(())
+    }
+
+    async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
+        let mqtt_device = match device_to_mqtt(device) {
+            Some(mqtt_device) => mqtt_device,
+            // Nothing to publish for sensors
+            None => return Ok(()),
+        };
+
+        let client = self
+            .client
+            .as_ref()
+            .context("Expected MQTT client to exist when set_integration_device_state is called")?;
+
+        let topic = self
+            .config
+            .topic_set
+            .replace("{id}", &device.id.to_string());
+        let payload = serde_json::to_vec(&mqtt_device)?;
+
+        client
+            .publish(topic, QoS::AtLeastOnce, false, payload)
+            .await
+            .map_err(|err| anyhow!(err))?;
+
+        Ok(())
+    }
+
+    async fn run_integration_action(&mut self, _: &IntegrationActionPayload) -> Result<()> {
+        // do nothing
+        Ok(())
+    }
+}
+
+async fn poll_eventloop(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    config: MqttConfig,
+    integration_id: IntegrationId,
+    sender: TxEventChannel,
+) {
+    // Most recently reported device of each id
+    let mut devices: HashMap<String, Device> = HashMap::new();
+
+    loop {
+        match eventloop.poll().await {
+            // (Re-)subscribe whenever a connection to the broker is established
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                let result = client.subscribe(&config.topic, QoS::AtLeastOnce).await;
+
+                if let Err(e) = result {
+                    println!(
+                        "Error while subscribing to MQTT topic {}: {}",
+                        config.topic, e
+                    );
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(msg))) => {
+                let mqtt_device: Result<MqttDevice, _> = serde_json::from_slice(&msg.payload);
+
+                match mqtt_device {
+                    Ok(mqtt_device) => {
+                        let id = mqtt_device.id.clone();
+                        let prev_device = devices.get(&id);
+
+                        let device = match prev_device {
+                            Some(prev_device) if mqtt_device.is_availability_only() => Device {
+                                availability: DeviceAvailability::new(
+                                    mqtt_device.online.unwrap_or(true),
+                                ),
+                                ..prev_device.clone()
+                            },
+                            // Availability of a device we know nothing else about
+                            None if mqtt_device.is_availability_only() => continue,
+                            _ => mqtt_to_device(mqtt_device, &integration_id),
+                        };
+
+                        devices.insert(id, device.clone());
+                        sender.send(Message::IntegrationDeviceRefresh { device });
+                    }
+                    Err(e) => {
+                        println!("Could not parse MQTT message on topic {}: {}", msg.topic, e)
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                println!(
+                    "MQTT connection error in integration {}: {}",
+                    integration_id, e
+                );
+
+                // The eventloop reconnects on the next call to poll(), avoid
+                // hammering the broker while it's unreachable
+                time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use homectl_types::{
+    device::{
+        Capability, CorrelatedColorTemperature, Device, DeviceAvailability, DeviceColor, DeviceId,
+        DeviceState, Light, OnOffDevice, SensorKind,
+    },
+    integration::IntegrationId,
+};
+use palette::Hsv;
+use serde::{Deserialize, Serialize};
+
+/// JSON payload format used for both incoming device state messages and
+/// outgoing set messages.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MqttDevice {
+    pub id: String,
+    pub name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Hsv>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cct: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition_ms: Option<u64>,
+
+    /// If set, the device is treated as an on/off sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensor_value: Option<bool>,
+
+    /// Temperature in degrees Celsius, if set the device is treated as a
+    /// temperature sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// Illuminance in lux, if set the device is treated as an illuminance
+    /// sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub illuminance: Option<f32>,
+
+    /// Relative humidity in percent, if set the device is treated as a
+    /// humidity sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f32>,
+
+    /// Battery level in percent, if set the device is treated as a battery
+    /// level sensor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_level: Option<f32>,
+
+    /// Whether the device is reachable, assumed true if omitted. Messages
+    /// containing only `id` and `online` (e.g. MQTT last will messages) keep
+    /// the previously reported device state.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<bool>,
+}
+
+impl MqttDevice {
+    /// Returns true if the message only reports availability of the device
+    pub fn is_availability_only(&self) -> bool {
+        self.power.is_none()
+            && self.brightness.is_none()
+            && self.color.is_none()
+            && self.cct.is_none()
+            && get_sensor_kind(self).is_none()
+    }
+}
+
+/// Returns which kind of sensor the MqttDevice is, or None if it doesn't
+/// report any sensor readings
+fn get_sensor_kind(mqtt_device: &MqttDevice) -> Option<SensorKind> {
+    if let Some(value) = mqtt_device.sensor_value {
+        return Some(SensorKind::OnOffSensor { value });
+    }
+
+    mqtt_device
+        .temperature
+        .map(|value| SensorKind::TemperatureSensor { value })
+        .or_else(|| {
+            mqtt_device
+                .illuminance
+                .map(|value| SensorKind::IlluminanceSensor { value })
+        })
+        .or_else(|| {
+            mqtt_device
+                .humidity
+                .map(|value| SensorKind::HumiditySensor { value })
+        })
+        .or_else(|| {
+            mqtt_device
+                .battery_level
+                .map(|value| SensorKind::BatteryLevelSensor { value })
+        })
+}
+
+/// Converts MqttDevice into Device
+pub fn mqtt_to_device(mqtt_device: MqttDevice, integration_id: &IntegrationId) -> Device {
+    let name = mqtt_device
+        .name
+        .clone()
+        .unwrap_or_else(|| mqtt_device.id.clone());
+    let power = mqtt_device.power.unwrap_or(true);
+
+    let color = match (mqtt_device.color, mqtt_device.cct) {
+        (Some(color), _) => Some(DeviceColor::Color(color.into())),
+        (None, Some(cct)) => Some(DeviceColor::Cct(CorrelatedColorTemperature::new(
+            cct,
+            2000.0..6500.0,
+        ))),
+        (None, None) => None,
+    };
+
+    let (state, capabilities) = if let Some(kind) = get_sensor_kind(&mqtt_device) {
+        (DeviceState::Sensor(kind), None)
+    } else if mqtt_device.brightness.is_some() || color.is_some() {
+        // We can only guess capabilities from the fields the device reports.
+        // Transitions are passed along regardless, devices may ignore them.
+        let capabilities = Capability {
+            hsv: mqtt_device.color.is_some(),
+            cct: mqtt_device.cct.is_some(),
+            // Brightness can be given either directly, or through the value
+            // of a color or color temperature
+            dimmable: mqtt_device.brightness.is_some()
+                || mqtt_device.color.is_some()
+                || mqtt_device.cct.is_some(),
+            transitions: true,
+            ..Default::default()
+        };
+
+        let state = DeviceState::Light(Light::new(
+            power,
+            mqtt_device.brightness,
+            color,
+            mqtt_device.transition_ms,
+        ));
+
+        (state, Some(capabilities))
+    } else {
+        (DeviceState::OnOffDevice(OnOffDevice { power }), None)
+    };
+
+    let mut device = Device::new(
+        integration_id.clone(),
+        DeviceId::new(&mqtt_device.id),
+        name,
+        state,
+        capabilities,
+    );
+    device.availability = DeviceAvailability::new(mqtt_device.online.unwrap_or(true));
+
+    device
+}
+
+/// Converts Device into MqttDevice, returns None for devices that can't be
+/// controlled (sensors)
+pub fn device_to_mqtt(device: &Device) -> Option<MqttDevice> {
+    let mqtt_device = MqttDevice {
+        id: device.id.to_string(),
+        name: Some(device.name.clone()),
+        ..Default::default()
+    };
+
+    match &device.state {
+        DeviceState::OnOffDevice(state) => Some(MqttDevice {
+            power: Some(state.power),
+            ..mqtt_device
+        }),
+        DeviceState::Light(state) => {
+            let (color, cct) = match &state.color {
+                Some(DeviceColor::Color(color)) => (Some(color.to_hsv()), None),
+                Some(DeviceColor::Cct(cct)) => (None, Some(cct.get_cct())),
+                None => (None, None),
+            };
+
+            Some(MqttDevice {
+                power: Some(state.power),
+                brightness: state.brightness,
+                color,
+                cct,
+                transition_ms: state.transition_ms,
+                ..mqtt_device
+            })
+        }
+        DeviceState::MultiSourceLight(state) => Some(MqttDevice {
+            power: Some(state.power),
+            brightness: state.brightness,
+            color: state.average_color().map(|color| color.to_hsv()),
+            transition_ms: state.transition_ms,
+            ..mqtt_device
+        }),
+        DeviceState::Sensor(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(payload: &str) -> serde_json::Result<MqttDevice> {
+        serde_json::from_str(payload)
+    }
+
+    fn to_device(payload: &str) -> Device {
+        mqtt_to_device(
+            parse(payload).unwrap(),
+            &IntegrationId::from("mqtt".to_string()),
+        )
+    }
+
+    #[test]
+    fn rejects_malformed_payloads() {
+        assert!(parse("").is_err());
+        assert!(parse("{\"id\": \"lamp\"").is_err());
+        assert!(parse("[\"lamp\"]").is_err());
+
+        // id is the only required field
+        assert!(parse("{}").is_err());
+        assert!(parse("{\"power\": true}").is_err());
+
+        // Fields of the wrong type
+        assert!(parse("{\"id\": 1}").is_err());
+        assert!(parse("{\"id\": \"lamp\", \"brightness\": \"bright\"}").is_err());
+        assert!(parse("{\"id\": \"lamp\", \"color\": {\"hue\": 10}}").is_err());
+    }
+
+    #[test]
+    fn defaults_missing_fields() {
+        let device = to_device("{\"id\": \"relay\"}");
+
+        assert_eq!(device.id, DeviceId::new("relay"));
+        assert_eq!(device.name, "relay");
+        assert_eq!(
+            device.state,
+            DeviceState::OnOffDevice(OnOffDevice { power: true })
+        );
+        assert!(device.capabilities.is_none());
+        assert!(device.availability.online);
+    }
+
+    #[test]
+    fn maps_lights() {
+        let device = to_device(
+            "{\"id\": \"lamp\", \"name\": \"Lamp\", \"power\": false, \"brightness\": 0.5, \
+             \"color\": {\"hue\": 120, \"saturation\": 1, \"value\": 1}}",
+        );
+
+        assert_eq!(device.name, "Lamp");
+
+        match &device.state {
+            DeviceState::Light(light) => {
+                assert!(!light.power);
+                assert_eq!(light.brightness, Some(0.5));
+                assert!(matches!(light.color, Some(DeviceColor::Color(_))));
+            }
+            state => panic!("Expected a light, got {:?}", state),
+        }
+
+        let capabilities = device.capabilities.unwrap();
+        assert!(capabilities.hsv);
+        assert!(!capabilities.cct);
+        assert!(capabilities.dimmable);
+    }
+
+    #[test]
+    fn maps_cct_only_lights() {
+        let device = to_device("{\"id\": \"lamp\", \"power\": true, \"cct\": 2700}");
+
+        match &device.state {
+            DeviceState::Light(light) => {
+                assert_eq!(light.brightness, None);
+                assert!(matches!(light.color, Some(DeviceColor::Cct(_))));
+            }
+            state => panic!("Expected a light, got {:?}", state),
+        }
+
+        let capabilities = device.capabilities.unwrap();
+        assert!(!capabilities.hsv);
+        assert!(capabilities.cct);
+        assert!(capabilities.dimmable);
+    }
+
+    #[test]
+    fn maps_sensors() {
+        let sensor = |payload| match to_device(payload).state {
+            DeviceState::Sensor(kind) => kind,
+            state => panic!("Expected a sensor, got {:?}", state),
+        };
+
+        assert_eq!(
+            sensor("{\"id\": \"door\", \"sensor_value\": true}"),
+            SensorKind::OnOffSensor { value: true }
+        );
+        assert_eq!(
+            sensor("{\"id\": \"thermometer\", \"temperature\": 21.5}"),
+            SensorKind::TemperatureSensor { value: 21.5 }
+        );
+        assert_eq!(
+            sensor("{\"id\": \"lux\", \"illuminance\": 300}"),
+            SensorKind::IlluminanceSensor { value: 300.0 }
+        );
+        assert_eq!(
+            sensor("{\"id\": \"hygrometer\", \"humidity\": 45}"),
+            SensorKind::HumiditySensor { value: 45.0 }
+        );
+        assert_eq!(
+            sensor("{\"id\": \"remote\", \"battery_level\": 80}"),
+            SensorKind::BatteryLevelSensor { value: 80.0 }
+        );
+    }
+
+    #[test]
+    fn detects_availability_only_messages() {
+        assert!(parse("{\"id\": \"lamp\", \"online\": false}")
+            .unwrap()
+            .is_availability_only());
+        assert!(
+            !parse("{\"id\": \"lamp\", \"online\": true, \"power\": true}")
+                .unwrap()
+                .is_availability_only()
+        );
+        assert!(!parse("{\"id\": \"door\", \"sensor_value\": false}")
+            .unwrap()
+            .is_availability_only());
+
+        let device = to_device("{\"id\": \"lamp\", \"power\": true, \"online\": false}");
+        assert!(!device.availability.online);
+    }
+
+    #[test]
+    fn round_trips_lights() {
+        let device = to_device("{\"id\": \"lamp\", \"power\": true, \"brightness\": 0.25}");
+        let mqtt_device = device_to_mqtt(&device).unwrap();
+
+        assert_eq!(mqtt_device.id, "lamp");
+        assert_eq!(mqtt_device.power, Some(true));
+        assert_eq!(mqtt_device.brightness, Some(0.25));
+
+        let sensor = to_device("{\"id\": \"door\", \"sensor_value\": true}");
+        assert!(device_to_mqtt(&sensor).is_none());
+    }
+}
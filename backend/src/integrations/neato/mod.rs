@@ -76,6 +76,16 @@ impl Integration for Neato {
         Ok(())
     }
 
+    async fn stop(&mut self) -> anyhow::Result<()> {
+        // do nothing
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        // nothing to check
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, _: &Device) -> Result<()> {
         Ok(())
     }
@@ -1,32 +1,58 @@
-use std::process::Command;
-use std::time::Duration;
+pub mod reachability;
 
-use anyhow::{Context, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 
 use homectl_types::{
-    device::{Device, DeviceId, DeviceState, OnOffDevice},
+    device::{Device, DeviceId, DeviceState, SensorKind},
     event::{Message, TxEventChannel},
     integration::{Integration, IntegrationActionPayload, IntegrationId},
 };
+use reachability::{is_icmp_permitted, is_reachable};
 use serde::Deserialize;
-use tokio::time;
+use tokio::{task::JoinHandle, time};
+
+use crate::utils::is_task_finished;
+
+const DEFAULT_INTERVAL_MS: u64 = 10_000;
+const DEFAULT_TIMEOUT_MS: u64 = 1_000;
+const DEFAULT_OFFLINE_AFTER_MS: u64 = 180_000;
+const DEFAULT_TCP_PORT: u16 = 80;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct PingConfig {
     machines: Vec<PingMachine>,
+
+    /// How often to check whether machines are reachable
+    interval_ms: Option<u64>,
+
+    /// How long to wait for a response from a machine
+    timeout_ms: Option<u64>,
+
+    /// How long a machine has to be unresponsive before it's considered
+    /// unreachable. Phones in particular tend to drop off Wi-Fi for short
+    /// periods while asleep.
+    offline_after_ms: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct PingMachine {
     id: String,
-    ip: String,
+    ip: IpAddr,
+
+    /// Port to connect to if ICMP is not permitted. The port doesn't need to
+    /// be open, as refused connections also count as responses.
+    tcp_port: Option<u16>,
 }
 
 pub struct Ping {
     id: IntegrationId,
     config: PingConfig,
     sender: TxEventChannel,
+    task_handles: Vec<JoinHandle<()>>,
 }
 
 #[async_trait]
@@ -35,58 +61,82 @@ impl Integration for Ping {
         let config = config
             .clone()
             .try_into()
-            .context("Failed to deserialize config of WakeOnLan integration")?;
+            .context("Failed to deserialize config of Ping integration")?;
         Ok(Ping {
             id: id.clone(),
             config,
             sender,
+            task_handles: Vec::new(),
         })
     }
 
     async fn register(&mut self) -> Result<()> {
-        let config = self.config.clone();
-        let id = self.id.clone();
-        let sender = self.sender.clone();
-        for machine in &self.config.machines {
-            let state = DeviceState::OnOffDevice(OnOffDevice { power: false });
-            let device = Device {
-                id: DeviceId::new(&machine.id),
-                name: machine.id.clone(),
-                integration_id: self.id.clone(),
-                state,
-                scene: None,
-                capabilities: None,
-            };
-            self.sender
-                .send(Message::IntegrationDeviceRefresh { device });
-        }
+        // Machines are discovered once they have been checked for the first
+        // time, which happens immediately after starting
+        Ok(())
+    }
 
-        tokio::spawn(async move {
-            loop {
-                let poll_rate = Duration::from_millis(10000);
-                let mut interval = time::interval(poll_rate);
-
-                for device in &config.machines {
-                    interval.tick().await;
-                    let status = Command::new("sh")
-                        .arg("-c")
-                        .arg("ping")
-                        .arg(&device.ip)
-                        .arg("-w 5")
-                        .status();
-
-                    update_state(device, &id, status.is_ok(), &sender)
-                }
+    async fn start(&mut self) -> Result<()> {
+        // ICMP may be permitted for only one of the address families, so
+        // probe each of them once
+        let icmp_v4 = is_icmp_permitted(&IpAddr::from(Ipv4Addr::LOCALHOST));
+        let icmp_v6 = is_icmp_permitted(&IpAddr::from(Ipv6Addr::LOCALHOST));
+
+        let machines = &self.config.machines;
+        let uses_v4 = machines.iter().any(|machine| machine.ip.is_ipv4());
+        let uses_v6 = machines.iter().any(|machine| machine.ip.is_ipv6());
+
+        for (family, permitted, used) in [("IPv4", icmp_v4, uses_v4), ("IPv6", icmp_v6, uses_v6)] {
+            if used && !permitted {
+                println!(
+                    "ICMP over {} not permitted, ping integration {} falls back to TCP connect",
+                    family, self.id
+                );
             }
-        });
+        }
+
+        self.task_handles = self
+            .config
+            .machines
+            .iter()
+            .map(|machine| {
+                let machine = machine.clone();
+                let config = self.config.clone();
+                let id = self.id.clone();
+                let sender = self.sender.clone();
+                let use_icmp = if machine.ip.is_ipv4() {
+                    icmp_v4
+                } else {
+                    icmp_v6
+                };
+
+                tokio::spawn(async move {
+                    poll_machine(machine, config, id, sender, use_icmp).await;
+                })
+            })
+            .collect();
+
         Ok(())
     }
-    async fn start(&mut self) -> Result<()> {
+
+    async fn stop(&mut self) -> Result<()> {
+        for task_handle in self.task_handles.drain(..) {
+            task_handle.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.task_handles.iter_mut().any(is_task_finished) {
+            return Err(anyhow!("Ping task exited unexpectedly"));
+        }
+
         Ok(())
     }
 
     async fn set_integration_device_state(&mut self, _device: &Device) -> Result<()> {
-        // self.devices.insert(device.id.clone(), device.clone());
+        // Reachability can't be set
         Ok(())
     }
 
@@ -95,15 +145,62 @@ impl Integration for Ping {
         Ok(())
     }
 }
-fn update_state(machine: &PingMachine, id: &IntegrationId, state_b: bool, sender: &TxEventChannel) {
-    let state = DeviceState::OnOffDevice(OnOffDevice { power: state_b });
+
+/// Periodically checks whether a machine is reachable. Machines are reported
+/// reachable as soon as they respond, but only unreachable once they have
+/// been unresponsive for `offline_after_ms`.
+async fn poll_machine(
+    machine: PingMachine,
+    config: PingConfig,
+    id: IntegrationId,
+    sender: TxEventChannel,
+    use_icmp: bool,
+) {
+    let interval_ms = config.interval_ms.unwrap_or(DEFAULT_INTERVAL_MS);
+    let timeout = Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS));
+    let offline_after =
+        Duration::from_millis(config.offline_after_ms.unwrap_or(DEFAULT_OFFLINE_AFTER_MS));
+    let tcp_port = machine.tcp_port.unwrap_or(DEFAULT_TCP_PORT);
+
+    let mut interval = time::interval(Duration::from_millis(interval_ms));
+    let mut last_response: Option<Instant> = None;
+    let mut seq: u16 = 0;
+    let mut use_icmp = use_icmp;
+
+    loop {
+        interval.tick().await;
+        seq = seq.wrapping_add(1);
+
+        if is_reachable(machine.ip, tcp_port, seq, timeout, &mut use_icmp).await {
+            last_response = Some(Instant::now());
+        }
+
+        let reachable = match last_response {
+            Some(t) => t.elapsed() < offline_after,
+            None => false,
+        };
+
+        update_state(&machine, &id, reachable, &sender);
+    }
+}
+
+fn update_state(
+    machine: &PingMachine,
+    id: &IntegrationId,
+    reachable: bool,
+    sender: &TxEventChannel,
+) {
+    // Reported as a sensor, as reachability is read-only
+    let state = DeviceState::Sensor(SensorKind::OnOffSensor { value: reachable });
     let device = Device {
-        id: DeviceId::new(&machine.id.to_string()),
-        name: machine.id.to_string(),
+        id: DeviceId::new(&machine.id),
+        name: machine.id.clone(),
         integration_id: id.clone(),
         state,
         scene: None,
         capabilities: None,
+        availability: Default::default(),
+        manual_override: None,
     };
     sender.send(Message::IntegrationDeviceRefresh { device });
 }
@@ -0,0 +1,183 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use byteorder::{BigEndian, ByteOrder};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time;
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// Opens an unprivileged ICMP socket. On Linux this requires the process'
+/// group to be within `net.ipv4.ping_group_range`.
+fn open_icmp_socket(ip: &IpAddr) -> Result<Socket> {
+    let (domain, protocol) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol))?;
+
+    Ok(socket)
+}
+
+/// Returns true if ICMP echo requests can be sent to addresses of the same
+/// family as `ip` without elevated privileges
+pub fn is_icmp_permitted(ip: &IpAddr) -> bool {
+    open_icmp_socket(ip).is_ok()
+}
+
+fn mk_echo_request(ip: &IpAddr, seq: u16) -> Vec<u8> {
+    let msg_type = match ip {
+        IpAddr::V4(_) => ICMPV4_ECHO_REQUEST,
+        IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+    };
+
+    // type, code, checksum, identifier, sequence number, payload. The kernel
+    // replaces the identifier with one unique to the socket.
+    let mut buf = vec![msg_type, 0, 0, 0, 0, 0, 0, 0];
+    BigEndian::write_u16(&mut buf[6..], seq);
+    buf.extend_from_slice(b"homectl");
+
+    // The kernel computes ICMPv6 checksums, as they depend on the IP header
+    if ip.is_ipv4() {
+        let checksum = icmp_checksum(&buf);
+        BigEndian::write_u16(&mut buf[2..], checksum);
+    }
+
+    buf
+}
+
+/// Internet checksum as described in RFC 1071
+fn icmp_checksum(buf: &[u8]) -> u16 {
+    let mut sum: u32 = buf
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [a, b] => u32::from(u16::from_be_bytes([*a, *b])),
+            [a] => u32::from(u16::from_be_bytes([*a, 0])),
+            _ => 0,
+        })
+        .sum();
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Sends an ICMP echo request and waits for the matching reply. Returns an
+/// error if an ICMP socket can't be opened.
+async fn icmp_ping(ip: IpAddr, seq: u16, timeout: Duration) -> Result<bool> {
+    let socket = open_icmp_socket(&ip).context("Failed to open ICMP socket")?;
+    socket.set_nonblocking(true)?;
+
+    // ICMP datagram sockets support the same send_to/recv_from calls as UDP
+    // sockets, which lets us use tokio's UdpSocket for async IO
+    let socket = UdpSocket::from_std(socket.into())?;
+
+    let reply_type = match ip {
+        IpAddr::V4(_) => ICMPV4_ECHO_REPLY,
+        IpAddr::V6(_) => ICMPV6_ECHO_REPLY,
+    };
+
+    let request = mk_echo_request(&ip, seq);
+    if socket
+        .send_to(&request, SocketAddr::new(ip, 0))
+        .await
+        .is_err()
+    {
+        // E.g. network unreachable
+        return Ok(false);
+    }
+
+    let wait_for_reply = async {
+        let mut buf = [0; 1024];
+
+        loop {
+            let (len, addr) = socket.recv_from(&mut buf).await?;
+
+            // Datagram ICMP sockets only receive replies to their own
+            // identifier, but may still receive replies to earlier requests
+            if len >= 8
+                && addr.ip() == ip
+                && buf[0] == reply_type
+                && BigEndian::read_u16(&buf[6..]) == seq
+            {
+                return Ok::<(), std::io::Error>(());
+            }
+        }
+    };
+
+    let res = time::timeout(timeout, wait_for_reply).await;
+
+    Ok(matches!(res, Ok(Ok(()))))
+}
+
+/// Tries connecting to given TCP port. A refused connection still means the
+/// host is up, as something had to respond with a reset.
+async fn tcp_ping(ip: IpAddr, port: u16, timeout: Duration) -> bool {
+    let res = time::timeout(timeout, TcpStream::connect(SocketAddr::new(ip, port))).await;
+
+    match res {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => e.kind() == ErrorKind::ConnectionRefused,
+        Err(_) => false,
+    }
+}
+
+/// Checks whether a host is reachable, using ICMP if `use_icmp` is set and
+/// falling back to connecting to a TCP port otherwise. If ICMP fails,
+/// `use_icmp` is cleared so that the fallback is only logged once.
+pub async fn is_reachable(
+    ip: IpAddr,
+    tcp_port: u16,
+    seq: u16,
+    timeout: Duration,
+    use_icmp: &mut bool,
+) -> bool {
+    if *use_icmp {
+        match icmp_ping(ip, seq, timeout).await {
+            Ok(reachable) => return reachable,
+            Err(e) => {
+                println!("ICMP ping to {} failed, falling back to TCP: {:#}", ip, e);
+                *use_icmp = false;
+            }
+        }
+    }
+
+    tcp_ping(ip, tcp_port, timeout).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icmp_checksum_matches_rfc_1071() {
+        // Example from RFC 1071 section 3, where the one's complement sum
+        // of the data is 0xddf2
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+
+        assert_eq!(icmp_checksum(&data), !0xddf2);
+    }
+
+    #[test]
+    fn icmp_checksum_pads_odd_length() {
+        assert_eq!(icmp_checksum(&[0x00, 0x01, 0xf2]), !0xf201);
+    }
+
+    #[test]
+    fn icmp_checksum_verifies_to_zero() {
+        let mut packet = vec![8, 0, 0, 0, 0x12, 0x34, 0x00, 0x01, 0xde, 0xad];
+        let checksum = icmp_checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+        assert_eq!(icmp_checksum(&packet), 0);
+    }
+}
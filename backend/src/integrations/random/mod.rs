@@ -1,11 +1,12 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
+use futures::future::{abortable, AbortHandle};
 use homectl_types::{
     device::{Device, DeviceColor, DeviceId, DeviceState, Light},
     event::{Message, TxEventChannel},
     integration::{Integration, IntegrationActionPayload, IntegrationId},
 };
-use palette::rgb::Rgb;
+use palette::{rgb::Rgb, Hsv};
 use rand::prelude::*;
 use serde::Deserialize;
 use std::time::Duration;
@@ -21,6 +22,7 @@ pub struct Random {
     id: IntegrationId,
     config: RandomConfig,
     event_tx: TxEventChannel,
+    poll_handle: Option<AbortHandle>,
 }
 
 #[async_trait]
@@ -35,6 +37,7 @@ impl Integration for Random {
             id: id.clone(),
             config,
             event_tx,
+            poll_handle: None,
         })
     }
 
@@ -56,11 +59,26 @@ impl Integration for Random {
 
         // FIXME: can we restructure the integrations / devices systems such
         // that polling is not needed here?
-        tokio::spawn(async { poll_sensor(random).await });
+        let (poll_task, poll_handle) = abortable(poll_sensor(random));
+        tokio::spawn(poll_task);
+        self.poll_handle = Some(poll_handle);
 
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(poll_handle) = self.poll_handle.take() {
+            poll_handle.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        // poll task runs forever and is only stopped by stop()
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, _device: &Device) -> Result<()> {
         // do nothing
         Ok(())
@@ -80,7 +98,8 @@ fn get_random_color() -> DeviceColor {
     let b: f32 = rng.gen();
 
     let rgb: Rgb = Rgb::new(r, g, b);
-    DeviceColor::Color(rgb.into())
+    let hsv: Hsv = rgb.into();
+    DeviceColor::Color(hsv.into())
 }
 
 async fn poll_sensor(random: Random) {
@@ -115,5 +134,7 @@ fn mk_random_device(random: &Random) -> Device {
         scene: None,
         state,
         capabilities: None,
+        availability: Default::default(),
+        manual_override: None,
     }
 }
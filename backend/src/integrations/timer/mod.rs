@@ -55,6 +55,19 @@ impl Integration for Timer {
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        if let Some(timer_task) = self.timer_task.take() {
+            timer_task.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        // timer task exits by itself once the timer has elapsed
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, _device: &Device) -> Result<()> {
         Ok(())
     }
@@ -99,5 +112,7 @@ fn mk_timer_device(id: &IntegrationId, config: &TimerConfig, value: bool) -> Dev
         scene: None,
         state,
         capabilities: None,
+        availability: Default::default(),
+        manual_override: None,
     }
 }
@@ -1,19 +1,21 @@
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use homectl_types::device::{Capability, CorrelatedColorTemperature, DeviceColor};
-use homectl_types::utils::{cct_to_rgb, xy_to_cct};
+use homectl_types::device::{
+    Capability, CorrelatedColorTemperature, DeviceAvailability, DeviceColor,
+};
 use homectl_types::{
     device::{Device, DeviceId, DeviceState, Light},
     event::{Message, TxEventChannel},
     integration::{Integration, IntegrationActionPayload, IntegrationId},
 };
-use palette::{Hsv, Yxy};
+use palette::Hsv;
 use rust_async_tuyapi::{tuyadevice::TuyaDevice, Payload, PayloadStruct};
 use serde::Deserialize;
 use serde_json::{json, Value};
 use std::{
     collections::HashMap,
     net::IpAddr,
+    ops::Range,
     str::FromStr,
     sync::Arc,
     time::{Duration, SystemTime},
@@ -22,6 +24,8 @@ use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio::time;
 
+use crate::utils::is_task_finished;
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct TuyaDeviceConfig {
     name: String,
@@ -35,6 +39,10 @@ pub struct TuyaDeviceConfig {
     color_temp_field: Option<String>,
 }
 
+// Range of my bulbs is from 2700K - 4100K (and they express this as a 0-1000
+// range), this is very likely not true for all Tuya bulbs
+const SUPPORTED_CT_RANGE: Range<f32> = 2700.0..4100.0;
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct TuyaConfig {
     devices: HashMap<DeviceId, TuyaDeviceConfig>,
@@ -48,6 +56,16 @@ pub struct Tuya {
     device_poll_handles: HashMap<DeviceId, JoinHandle<()>>,
 }
 
+fn tuya_capabilities(device_config: &TuyaDeviceConfig) -> Capability {
+    Capability {
+        hsv: device_config.color_field.is_some(),
+        cct: device_config.color_temp_field.is_some(),
+        cct_range: Some(SUPPORTED_CT_RANGE),
+        dimmable: device_config.brightness_field.is_some() || device_config.color_field.is_some(),
+        ..Default::default()
+    }
+}
+
 fn default_device(
     device_id: DeviceId,
     name: String,
@@ -66,6 +84,8 @@ fn default_device(
             transition_ms: None,
         }),
         capabilities,
+        availability: Default::default(),
+        manual_override: None,
     }
 }
 
@@ -96,10 +116,7 @@ impl Integration for Tuya {
             let device_config = device_config.clone();
             let event_tx = self.event_tx.clone();
             let integration_id = integration_id.clone();
-            let capabilities = Some(Capability {
-                Hsv: device_config.color_field.is_some(),
-                Cct: device_config.color_temp_field.is_some(),
-            });
+            let capabilities = Some(tuya_capabilities(&device_config));
 
             println!("Getting initial state of {}", device_config.name);
             let device = get_tuya_state(&device_id, &integration_id, &device_config).await;
@@ -143,6 +160,22 @@ impl Integration for Tuya {
         Ok(())
     }
 
+    async fn stop(&mut self) -> Result<()> {
+        for (_device_id, poll_handle) in self.device_poll_handles.drain() {
+            poll_handle.abort();
+        }
+
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> Result<()> {
+        if self.device_poll_handles.values_mut().any(is_task_finished) {
+            return Err(anyhow!("Tuya poll task exited unexpectedly"));
+        }
+
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
         {
             let device_expected_state = self.device_expected_states.get(&device.id).unwrap();
@@ -204,10 +237,8 @@ fn hsv_to_tuya(power: bool, brightness: Option<f32>, hsv: Hsv) -> TuyaState {
 }
 
 fn ct_to_tuya(power: bool, brightness: Option<f32>, ct: f32) -> TuyaState {
-    // Range of my bulbs is from 2700K - 4100K (and they express this as a
-    // 0-1000 range), this is very likely not true for all Tuya bulbs
-    let min_supported_temp = 2700.0;
-    let max_supported_temp = 4100.0;
+    let min_supported_temp = SUPPORTED_CT_RANGE.start;
+    let max_supported_temp = SUPPORTED_CT_RANGE.end;
 
     // Scale the value into 0.0 - 1.0 range
     let q = (ct - min_supported_temp) / (max_supported_temp - min_supported_temp);
@@ -260,43 +291,20 @@ fn to_tuya_state(device: &Device, device_config: &TuyaDeviceConfig) -> Result<Tu
         _ => Err(anyhow!("Unsupported device state")),
     }?;
 
-    // TODO: do this kind of conversion for the integrations in homectl core
+    // Colors have already been converted to what the device supports by
+    // homectl core, see tuya_capabilities()
     match light_state.color {
-        Some(DeviceColor::Color(color)) => {
-            if device_config.color_field.is_some() {
-                // Received color and device supports color
-                let state = hsv_to_tuya(light_state.power, light_state.brightness, color);
-                Ok(state)
-            } else if device_config.color_temp_field.is_some() {
-                // Received color but device only supports color temperature, do conversion
-                let xy: Yxy = color.into();
-                let ct = xy_to_cct(&xy);
-
-                let brightness = color.value * light_state.brightness.unwrap_or(1.0);
-                let state = ct_to_tuya(light_state.power, Some(brightness), ct);
-                Ok(state)
-            } else {
-                // No color support at all
-                Ok(power_to_tuya(light_state.power))
-            }
+        Some(DeviceColor::Color(color)) if device_config.color_field.is_some() => {
+            let state = hsv_to_tuya(light_state.power, light_state.brightness, color.to_hsv());
+            Ok(state)
         }
-        Some(DeviceColor::Cct(cct)) => {
-            if device_config.color_temp_field.is_some() {
-                // Received color temperature and device supports color temperatures
-                let state = ct_to_tuya(light_state.power, light_state.brightness, cct.get_cct());
-
-                Ok(state)
-            } else if device_config.color_field.is_some() {
-                // Received color temperature but device only supports colors, do conversion
-                let rgb = cct_to_rgb(cct.get_cct());
-                let hsv: Hsv = rgb.into();
-                let state = hsv_to_tuya(light_state.power, light_state.brightness, hsv);
-
-                Ok(state)
-            } else {
-                // No color support at all
-                Ok(power_to_tuya(light_state.power))
-            }
+        Some(DeviceColor::Cct(cct)) if device_config.color_temp_field.is_some() => {
+            let state = ct_to_tuya(light_state.power, light_state.brightness, cct.get_cct());
+            Ok(state)
+        }
+        Some(_) => {
+            // No support for this kind of color
+            Ok(power_to_tuya(light_state.power))
         }
         None => {
             // Brightness goes from 10 to 1000 ¯\_(ツ)_/¯
@@ -447,10 +455,8 @@ async fn get_tuya_state(
             Some(Value::String(s)) if s == "white" => {
                 if let Some(field) = &device_config.color_temp_field {
                     if let Some(Value::Number(value)) = dps.get(field) {
-                        // Range of my bulbs is from 2700K - 4100K (and they express this as a
-                        // 0-1000 range), this is very likely not true for all Tuya bulbs
-                        let min_supported_temp = 2700.0;
-                        let max_supported_temp = 4100.0;
+                        let min_supported_temp = SUPPORTED_CT_RANGE.start;
+                        let max_supported_temp = SUPPORTED_CT_RANGE.end;
 
                         let ct = value.as_u64().unwrap_or(1000);
 
@@ -461,7 +467,7 @@ async fn get_tuya_state(
                             q * (max_supported_temp - min_supported_temp) + min_supported_temp;
                         Some(DeviceColor::Cct(CorrelatedColorTemperature::new(
                             cct,
-                            min_supported_temp..max_supported_temp,
+                            SUPPORTED_CT_RANGE,
                         )))
                     } else {
                         None
@@ -475,11 +481,9 @@ async fn get_tuya_state(
                     if let Some(Value::String(value)) = dps.get(field) {
                         let (h, s, v) = scan_fmt!(value, "{4x}{4x}{4x}", i32, i32, i32)?;
                         print!("{}{}{}", h, s, v);
-                        Some(DeviceColor::Color(Hsv::new(
-                            h as f32,
-                            s as f32 / 1000.,
-                            v as f32 / 1000.,
-                        )))
+                        Some(DeviceColor::Color(
+                            Hsv::new(h as f32, s as f32 / 1000., v as f32 / 1000.).into(),
+                        ))
                     } else {
                         None
                     }
@@ -506,10 +510,7 @@ async fn get_tuya_state(
         } else {
             None
         };
-        let capabilities = Capability {
-            Hsv: device_config.color_mode_field_value.is_some(),
-            Cct: device_config.color_temp_field.is_some(),
-        };
+        let capabilities = tuya_capabilities(device_config);
         let state = DeviceState::Light(Light::new(power, brightness, color, Some(1000)));
 
         let device = Device {
@@ -519,6 +520,8 @@ async fn get_tuya_state(
             scene: None,
             state,
             capabilities: Some(capabilities),
+            availability: Default::default(),
+            manual_override: None,
         };
 
         Ok(device)
@@ -537,16 +540,19 @@ pub async fn poll_light(
 
     loop {
         interval.tick().await;
-        let device_expected_state = { device_expected_state.read().await.clone() };
+        let mut device_expected_state = { device_expected_state.read().await.clone() };
         let result = set_tuya_state(&device_expected_state, device_config).await;
 
-        if let Err(e) = result {
+        if let Err(e) = &result {
             eprintln!(
                 "Error while polling Tuya state for device {:?}: {:?}",
                 device_expected_state, e
             );
         }
 
+        // Device is considered offline for as long as we fail to reach it
+        device_expected_state.availability = DeviceAvailability::new(result.is_ok());
+
         // We still need to send our version of the device state to homectl core, in
         // case it has gone stale.
         sender.send(Message::IntegrationDeviceRefresh {
@@ -57,6 +57,8 @@ impl Integration for WakeOnLan {
                 scene: None,
                 state,
                 capabilities: None,
+                availability: Default::default(),
+                manual_override: None,
             };
 
             self.sender
@@ -70,6 +72,16 @@ impl Integration for WakeOnLan {
         Ok(())
     }
 
+    async fn stop(&mut self) -> anyhow::Result<()> {
+        // do nothing
+        Ok(())
+    }
+
+    async fn health_check(&mut self) -> anyhow::Result<()> {
+        // nothing to check
+        Ok(())
+    }
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()> {
         let power = match device.state {
             DeviceState::OnOffDevice(OnOffDevice { power }) => Ok(power),
@@ -7,10 +7,21 @@ mod utils;
 // use db::{actions::find_floorplans, establish_connection};
 use anyhow::{Context, Result};
 use api::init_api;
-use db::init_db;
+use db::{actions::db_get_scenes, init_db};
 use homectl_core::{
-    devices::Devices, groups::Groups, integrations::Integrations, message::handle_message,
-    rules::Rules, scenes::Scenes, state::AppState,
+    check_config::{check_config, read_error_problem},
+    config::watch_config,
+    devices::Devices,
+    event_log::EventLog,
+    groups::Groups,
+    integrations::Integrations,
+    message::handle_message,
+    metrics::MESSAGES_IN_FLIGHT,
+    presence::Presence,
+    rules::Rules,
+    scenes::Scenes,
+    state::AppState,
+    websockets::WebSockets,
 };
 use homectl_types::event::mk_channel;
 use std::{error::Error, sync::Arc};
@@ -25,23 +36,68 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Attempt connecting to Postgres
     init_db().await;
 
-    let (config, opaque_integrations_configs) = homectl_core::config::read_config()?;
+    let check_config_only = std::env::args().nth(1).as_deref() == Some("check-config");
+
+    let (config, opaque_integrations_configs) = match homectl_core::config::read_config() {
+        Ok(config) => config,
+        Err(e) if check_config_only => {
+            println!("{}", read_error_problem(&e));
+            println!("Found 1 problem(s) in config");
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let db_scenes = db_get_scenes().await.unwrap_or_default();
+    let config_problems = check_config(&config, &opaque_integrations_configs, &db_scenes);
+
+    if check_config_only {
+        if config_problems.is_empty() {
+            println!("No problems found in config");
+            return Ok(());
+        }
+
+        for problem in &config_problems {
+            println!("{}", problem);
+        }
+
+        println!("Found {} problem(s) in config", config_problems.len());
+        std::process::exit(1);
+    }
+
+    for problem in &config_problems {
+        println!("Warning: {}", problem);
+    }
 
     // println!("Using config:");
     // println!("{:#?}", config);
 
     let (sender, mut receiver) = mk_channel();
 
-    let mut integrations = Integrations::new(sender.clone());
+    let ws = WebSockets::default();
+    let event_log = EventLog::new(ws.clone());
+
+    let mut integrations = Integrations::new(sender.clone(), ws.clone());
     let groups = Groups::new(config.groups.unwrap_or_default());
     let scenes = Scenes::new(config.scenes.unwrap_or_default(), groups.clone());
     scenes.refresh_db_scenes().await;
-    let devices = Devices::new(sender.clone(), scenes.clone());
+    let devices = Devices::new(
+        sender.clone(),
+        scenes.clone(),
+        groups.clone(),
+        config.manual_override.unwrap_or_default(),
+    );
+    devices.start_manual_override_timeouts();
     let rules = Rules::new(
         config.routines.unwrap_or_default(),
+        config.location,
         groups.clone(),
         sender.clone(),
+        event_log.clone(),
     );
+    rules.start_scheduler(devices.clone());
+    let presence = Presence::new(config.presence.unwrap_or_default(), sender.clone());
+    presence.start(devices.clone());
 
     for (id, integration_config) in &config.integrations.unwrap_or_default() {
         let opaque_integration_config: &config::Value = opaque_integrations_configs
@@ -53,12 +109,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .await?;
     }
 
-    let _: Result<()> = {
-        integrations.run_register_pass().await?;
-        integrations.run_start_pass().await?;
-
-        Ok(())
-    };
+    integrations.run_start_pass();
+    integrations.start_health_checks();
 
     let state = AppState {
         integrations,
@@ -66,13 +118,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
         scenes,
         devices,
         rules,
+        presence,
         sender: sender.clone(),
-        ws: Default::default(),
+        ws,
+        event_log,
+        api_config: config.api.unwrap_or_default(),
     };
 
     let state = Arc::new(state);
 
-    init_api(&state).expect("Expected init_api to return Ok(())");
+    init_api(&state, config.server.unwrap_or_default())?;
+    watch_config(Arc::clone(&state));
 
     loop {
         let msg = receiver
@@ -82,8 +138,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
         let state = Arc::clone(&state);
 
+        MESSAGES_IN_FLIGHT.inc();
         tokio::spawn(async move {
             handle_message(state, msg).await;
+            MESSAGES_IN_FLIGHT.dec();
         });
     }
 }
@@ -1,10 +1,10 @@
-use anyhow::Result;
-use serde::{de, Deserialize};
+use futures::FutureExt;
+use tokio::task::JoinHandle;
 
-pub fn from_hh_mm<'de, D>(d: D) -> Result<chrono::NaiveTime, D::Error>
-where
-    D: de::Deserializer<'de>,
-{
-    let str = String::deserialize(d)?;
-    chrono::NaiveTime::parse_from_str(&str, "%H:%M").map_err(serde::de::Error::custom)
+pub use homectl_types::utils::from_hh_mm;
+
+/// Returns true if given background task has exited, e.g. due to a panic.
+/// Note that a finished task must not be checked again.
+pub fn is_task_finished(handle: &mut JoinHandle<()>) -> bool {
+    handle.now_or_never().is_some()
 }
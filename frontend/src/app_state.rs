@@ -34,6 +34,8 @@ pub fn use_init_app_state(cx: &Scope) {
                     set_scenes(state.scenes);
                     set_groups(state.groups);
                 }
+                WebSocketResponse::Event(_) => {}
+                WebSocketResponse::IntegrationsStatus(_) => {}
             },
         );
     }
@@ -1,8 +1,13 @@
-use crate::{save_scene_modal::SaveSceneModal, tile::Tile, util::scale_hsv_value_to_display};
+use crate::{
+    save_scene_modal::SaveSceneModal,
+    tile::Tile,
+    util::{get_device_state_gradient, scale_hsv_value_to_display},
+};
 use dioxus::prelude::*;
 use dioxus::fermi::use_read;
 use homectl_types::device::{Device, DeviceKey};
 use itertools::Itertools;
+use palette::Hsv;
 
 use crate::{app_state::DEVICES_ATOM, device_modal::DeviceModal};
 
@@ -14,19 +19,12 @@ struct DeviceTileProps<'a> {
 #[allow(non_snake_case)]
 fn DeviceTile<'a>(cx: Scope<'a, DeviceTileProps<'a>>) -> Element<'a> {
     let name = &cx.props.device.name;
-    let color = cx
-        .props
-        .device
-        .state
-        .get_color()
-        .map(scale_hsv_value_to_display);
     let (modal_open, set_modal_open) = use_state(&cx, || false);
 
-    let gradient = if let Some(color) = color {
-        vec![color]
-    } else {
-        vec![]
-    };
+    let gradient: Vec<Hsv> = get_device_state_gradient(&cx.props.device.state)
+        .into_iter()
+        .map(scale_hsv_value_to_display)
+        .collect();
 
     cx.render(rsx! {
         Tile {
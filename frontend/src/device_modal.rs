@@ -26,12 +26,12 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
     // };
 
     let (show_hsv, set_show_hsv) = use_state(&cx, || match &cx.props.device.capabilities {
-        Some(c) => c.Hsv,
+        Some(c) => c.hsv,
         None => false,
     });
 
     let show_cct = match &cx.props.device.capabilities {
-        Some(c) => c.Cct,
+        Some(c) => c.cct,
         None => false,
     };
     let (show_brightness, _set_show_brightness) = use_state(&cx, || true);
@@ -40,8 +40,17 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
     let power = cx.props.device.state.is_powered_on().unwrap_or_default();
     let color = cx.props.device.state.get_color();
 
-    let hue = color.unwrap_or_default().hue.to_positive_degrees();
     let saturation = color.unwrap_or_default().saturation;
+
+    // Unsaturated colors have no hue, so remember the last picked one
+    let has_hue = saturation > 0.01;
+    let (picked_hue, set_picked_hue) =
+        use_state(&cx, || color.unwrap_or_default().hue.to_positive_degrees());
+    let hue = if has_hue {
+        color.unwrap_or_default().hue.to_positive_degrees()
+    } else {
+        *picked_hue
+    };
     let cct = cx
         .props
         .device
@@ -52,6 +61,7 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
 
     let sat_min = {
         let mut color = color.unwrap_or_default();
+        color.hue = hue.into();
         color.value = 1.0;
         color.saturation = 0.0;
         hsv_to_css_hsl_str(&Some(color))
@@ -59,6 +69,7 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
 
     let sat_max = {
         let mut color = color.unwrap_or_default();
+        color.hue = hue.into();
         color.value = 1.0;
         color.saturation = 1.0;
         hsv_to_css_hsl_str(&Some(color))
@@ -99,8 +110,12 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
             let hue: Option<f32> = evt.data.value.parse().ok();
 
             if let Some(hue) = hue {
+                // Picking a hue for a white light should make it colored
+                let saturation = if has_hue { saturation } else { 1.0 };
+
+                set_picked_hue(hue);
                 let mut device = cx.props.device.clone();
-                device.state.set_hue(hue);
+                device.state.set_hue_saturation(hue, saturation);
                 device.scene = None;
                 ws.send_json(&WebSocketRequest::Message(Message::SetDeviceState {
                     device,
@@ -117,7 +132,7 @@ pub fn DeviceModal<'a>(cx: Scope<'a, DeviceModalProps<'a>>) -> Element<'a> {
 
             if let Some(saturation) = saturation {
                 let mut device = cx.props.device.clone();
-                device.state.set_saturation(saturation);
+                device.state.set_hue_saturation(hue, saturation);
                 device.scene = None;
                 ws.send_json(&WebSocketRequest::Message(Message::SetDeviceState {
                     device,
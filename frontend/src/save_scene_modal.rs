@@ -10,7 +10,7 @@ use dioxus::{
 use dioxus_websocket_hooks::use_ws_context;
 use dioxus::fermi::{use_read, use_set};
 use homectl_types::{
-    device::{Device, DeviceKey},
+    device::{Device, DeviceKey, DeviceState},
     event::Message,
     scene::{
         ColorConfig, SceneConfig, SceneDeviceConfig, SceneDeviceState, SceneDevicesSearchConfig,
@@ -79,6 +79,17 @@ pub fn SaveSceneModal<'a>(cx: Scope<'a, SaveSceneModalProps<'a>>) -> Element<'a>
                                     brightness: device.state.get_brightness(),
                                     cct: device.state.get_cct(),
                                     transition_ms: None,
+                                    segments: match &device.state {
+                                        DeviceState::MultiSourceLight(state) => Some(
+                                            state
+                                                .lights
+                                                .iter()
+                                                .map(|color| ColorConfig::Xy(color.to_xy()))
+                                                .collect(),
+                                        ),
+                                        _ => None,
+                                    },
+                                    gradient: None,
                                 });
 
                             (device.name, scene_device_config)
@@ -94,6 +105,7 @@ pub fn SaveSceneModal<'a>(cx: Scope<'a, SaveSceneModalProps<'a>>) -> Element<'a>
                 name: name.to_string(),
                 devices: Some(devices),
                 groups: None,
+                transition: None,
             };
 
             ws.send_json(&WebSocketRequest::Message(Message::StoreScene {
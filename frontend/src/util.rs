@@ -32,6 +32,26 @@ pub fn get_device_state_color(state: &DeviceState) -> Option<Hsv> {
     }
 }
 
+/// Returns one color per light source of the device, e.g. for displaying the
+/// zones of an LED strip as a gradient
+pub fn get_device_state_gradient(state: &DeviceState) -> Vec<Hsv> {
+    match state {
+        DeviceState::MultiSourceLight(state) if state.power => state
+            .lights
+            .iter()
+            .map(|color| {
+                let hsv = color.to_hsv();
+                Hsv::new(
+                    hsv.hue,
+                    hsv.saturation,
+                    hsv.value * state.brightness.unwrap_or(1.0),
+                )
+            })
+            .collect(),
+        _ => state.get_color().into_iter().collect(),
+    }
+}
+
 pub fn cmp_hsv(a: &Hsv, b: &Hsv) -> Ordering {
     let a = hsv_to_css_hsl_str(&Some(*a));
     let b = hsv_to_css_hsl_str(&Some(*b));
@@ -1,10 +1,54 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    group::{GroupDeviceLink, GroupId},
     integration::IntegrationActionDescriptor,
     scene::{CycleScenesDescriptor, SceneDescriptor},
 };
 
+/// Devices that an action applies to, given as individual devices and/or
+/// groups
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct DeviceTargets {
+    pub devices: Option<Vec<GroupDeviceLink>>,
+    pub groups: Option<Vec<GroupId>>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AdjustBrightnessDescriptor {
+    /// Amount to change brightness by, e.g. 0.1 or -0.1. Resulting brightness
+    /// is clamped to the 0.0 - 1.0 range.
+    pub delta: f32,
+
+    #[serde(flatten)]
+    pub targets: DeviceTargets,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SetPowerDescriptor {
+    /// Power state to set, ignored if `toggle` is set
+    pub power: Option<bool>,
+
+    /// Turns all devices off if any of them are on, otherwise turns them all
+    /// on
+    #[serde(default)]
+    pub toggle: bool,
+
+    #[serde(flatten)]
+    pub targets: DeviceTargets,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AdjustColorTemperatureDescriptor {
+    /// Amount to change color temperature by in kelvin, e.g. 500 or -500.
+    /// Lights in color mode are switched to the closest color temperature
+    /// first.
+    pub delta: f32,
+
+    #[serde(flatten)]
+    pub targets: DeviceTargets,
+}
+
 #[derive(Clone, Deserialize, Debug, Serialize)]
 #[serde(tag = "action")]
 pub enum Action {
@@ -16,6 +60,16 @@ pub enum Action {
 
     /// Runs an integration action
     IntegrationAction(IntegrationActionDescriptor),
+
+    /// Changes brightness of devices relative to their current brightness
+    AdjustBrightness(AdjustBrightnessDescriptor),
+
+    /// Turns devices on or off, or toggles them
+    SetPower(SetPowerDescriptor),
+
+    /// Changes color temperature of devices relative to their current color
+    /// temperature
+    AdjustColorTemperature(AdjustColorTemperatureDescriptor),
 }
 
 pub type Actions = Vec<Action>;
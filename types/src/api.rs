@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
+
+/// Access level granted by an API token. Scopes are ordered so that each
+/// scope also grants access to everything allowed by the scopes before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiScope {
+    /// Read device, scene, group and routine state
+    Read,
+
+    /// Control devices and trigger actions
+    Control,
+
+    /// Modify configuration, e.g. store and delete scenes
+    Admin,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiTokenConfig {
+    pub token: String,
+    pub scope: ApiScope,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ApiConfig {
+    /// API tokens by name. If no tokens are configured, authentication is
+    /// disabled and every request is granted admin scope.
+    pub tokens: Option<HashMap<String, ApiTokenConfig>>,
+}
+
+impl ApiConfig {
+    pub fn auth_enabled(&self) -> bool {
+        self.tokens
+            .as_ref()
+            .map(|tokens| !tokens.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// Finds the scope granted by given token, if any
+    pub fn find_token_scope(&self, token: &str) -> Option<ApiScope> {
+        self.tokens
+            .as_ref()?
+            .values()
+            .filter(|config| constant_time_eq(config.token.as_bytes(), token.as_bytes()))
+            .map(|config| config.scope)
+            .max()
+    }
+}
+
+/// Compares two byte slices without short-circuiting on the first mismatch,
+/// so that response times don't leak how much of a token was guessed right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn default_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+fn default_port() -> u16 {
+    45289
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServerConfig {
+    /// Address to bind the API server to, use "0.0.0.0" to listen on all
+    /// interfaces
+    #[serde(default = "default_address")]
+    pub address: IpAddr,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Serve the API over HTTPS if both certificate and key paths are given
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+
+    /// Origins allowed to make cross-origin requests, e.g.
+    /// "http://192.168.1.10:8080". Use "*" to allow any origin.
+    pub cors_allowed_origins: Option<Vec<String>>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: default_address(),
+            port: default_port(),
+            tls_cert_path: None,
+            tls_key_path: None,
+            cors_allowed_origins: None,
+        }
+    }
+}
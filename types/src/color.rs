@@ -0,0 +1,271 @@
+use palette::{Hsv, Yxy};
+use serde::{Deserialize, Serialize};
+
+/// Device independent color, stored as CIE xy chromaticity coordinates and a
+/// brightness factor in the 0.0 - 1.0 range. Unlike HSV, the same xy
+/// coordinates look the same regardless of which device displays them (as
+/// long as they are within the device's gamut).
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "ColorXyConfig")]
+pub struct ColorXy {
+    pub x: f32,
+    pub y: f32,
+    pub brightness: f32,
+}
+
+/// Also accepts HSV colors, e.g. device states that were stored in the DB
+/// before colors were represented in CIE xy
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorXyConfig {
+    Xy {
+        x: f32,
+        y: f32,
+        brightness: Option<f32>,
+    },
+    Hsv(Hsv),
+}
+
+impl From<ColorXyConfig> for ColorXy {
+    fn from(config: ColorXyConfig) -> Self {
+        match config {
+            ColorXyConfig::Xy { x, y, brightness } => ColorXy::new(x, y, brightness.unwrap_or(1.0)),
+            ColorXyConfig::Hsv(hsv) => hsv.into(),
+        }
+    }
+}
+
+impl From<Hsv> for ColorXy {
+    fn from(hsv: Hsv) -> Self {
+        // Value doesn't affect chromaticity
+        let xy: Yxy = Hsv::new(hsv.hue, hsv.saturation, 1.0).into();
+
+        ColorXy::new(xy.x, xy.y, hsv.value)
+    }
+}
+
+impl ColorXy {
+    pub fn new(x: f32, y: f32, brightness: f32) -> ColorXy {
+        ColorXy { x, y, brightness }
+    }
+
+    /// Converts the color to HSV in the sRGB color space. Colors outside the
+    /// sRGB gamut are clamped to the closest saturation.
+    pub fn to_hsv(&self) -> Hsv {
+        let mut hsv: Hsv = Yxy::new(self.x, self.y, 1.0).into();
+        hsv.saturation = hsv.saturation.clamp(0.0, 1.0);
+        hsv.value = self.brightness;
+
+        hsv
+    }
+
+    /// Linearly interpolates between two colors, `t` goes from 0.0 (self) to
+    /// 1.0 (other)
+    pub fn mix(&self, other: &ColorXy, t: f32) -> ColorXy {
+        let lerp = |a: f32, b: f32| (1.0 - t) * a + t * b;
+
+        ColorXy::new(
+            lerp(self.x, other.x),
+            lerp(self.y, other.y),
+            lerp(self.brightness, other.brightness),
+        )
+    }
+
+    /// Converts chromaticity coordinates to the CIE 1976 u'v' color space
+    fn to_uv(self) -> (f32, f32) {
+        let d = -2.0 * self.x + 12.0 * self.y + 3.0;
+
+        (4.0 * self.x / d, 9.0 * self.y / d)
+    }
+
+    /// Returns the distance between the chromaticities of two colors in the
+    /// CIE 1976 u'v' color space, where distances roughly match perceived
+    /// color differences. Brightness is not taken into account.
+    pub fn distance(&self, other: &ColorXy) -> f32 {
+        let (u1, v1) = self.to_uv();
+        let (u2, v2) = other.to_uv();
+
+        f32::hypot(u1 - u2, v1 - v2)
+    }
+}
+
+/// Range of colors that a device is able to reproduce
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorGamut {
+    /// Philips Hue gamut A, e.g. LivingColors and first generation LightStrips
+    HueA,
+
+    /// Philips Hue gamut B, e.g. first generation Hue bulbs
+    HueB,
+
+    /// Philips Hue gamut C, e.g. newer Hue bulbs and LightStrips
+    HueC,
+
+    /// LIFX bulbs
+    Lifx,
+}
+
+type Point = (f32, f32);
+
+impl ColorGamut {
+    /// Red, green and blue primaries of the gamut as CIE xy coordinates
+    pub fn primaries(&self) -> [Point; 3] {
+        match self {
+            ColorGamut::HueA => [(0.704, 0.296), (0.2151, 0.7106), (0.138, 0.08)],
+            ColorGamut::HueB => [(0.675, 0.322), (0.409, 0.518), (0.167, 0.04)],
+            ColorGamut::HueC => [(0.6915, 0.3083), (0.17, 0.7), (0.1532, 0.0475)],
+
+            // LIFX bulbs are controlled with HSV values, which we treat as
+            // sRGB, so nothing outside sRGB can be expressed
+            ColorGamut::Lifx => [(0.64, 0.33), (0.3, 0.6), (0.15, 0.06)],
+        }
+    }
+
+    /// Returns given color if it is within the gamut, otherwise the closest
+    /// color that is
+    pub fn clamp(&self, color: ColorXy) -> ColorXy {
+        let [r, g, b] = self.primaries();
+        let p = (color.x, color.y);
+
+        if is_in_triangle(p, r, g, b) {
+            return color;
+        }
+
+        let candidates = [
+            closest_point_on_segment(p, r, g),
+            closest_point_on_segment(p, g, b),
+            closest_point_on_segment(p, b, r),
+        ];
+
+        let (x, y) = candidates
+            .iter()
+            .copied()
+            .min_by(|a, b| {
+                let da = f32::hypot(a.0 - p.0, a.1 - p.1);
+                let db = f32::hypot(b.0 - p.0, b.1 - p.1);
+                da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(p);
+
+        ColorXy::new(x, y, color.brightness)
+    }
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn is_in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    let d1 = cross(a, b, p);
+    let d2 = cross(b, c, p);
+    let d3 = cross(c, a, p);
+
+    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+
+    !(has_neg && has_pos)
+}
+
+fn closest_point_on_segment(p: Point, a: Point, b: Point) -> Point {
+    let ab = (b.0 - a.0, b.1 - a.1);
+    let ap = (p.0 - a.0, p.1 - a.1);
+
+    let t = (ap.0 * ab.0 + ap.1 * ab.1) / (ab.0 * ab.0 + ab.1 * ab.1);
+    let t = t.clamp(0.0, 1.0);
+
+    (a.0 + ab.0 * t, a.1 + ab.1 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMUTS: [ColorGamut; 4] = [
+        ColorGamut::HueA,
+        ColorGamut::HueB,
+        ColorGamut::HueC,
+        ColorGamut::Lifx,
+    ];
+
+    fn assert_xy_near(actual: ColorXy, expected: Point) {
+        assert!(
+            (actual.x - expected.0).abs() < 1e-4 && (actual.y - expected.1).abs() < 1e-4,
+            "{:?} is not near {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn hue_gamut_corners() {
+        // Reference values from the Philips Hue developer documentation
+        assert_eq!(
+            ColorGamut::HueA.primaries(),
+            [(0.704, 0.296), (0.2151, 0.7106), (0.138, 0.08)]
+        );
+        assert_eq!(
+            ColorGamut::HueB.primaries(),
+            [(0.675, 0.322), (0.409, 0.518), (0.167, 0.04)]
+        );
+        assert_eq!(
+            ColorGamut::HueC.primaries(),
+            [(0.6915, 0.3083), (0.17, 0.7), (0.1532, 0.0475)]
+        );
+    }
+
+    #[test]
+    fn clamp_keeps_colors_within_gamut() {
+        for gamut in GAMUTS {
+            let [r, g, b] = gamut.primaries();
+            let centroid = ColorXy::new((r.0 + g.0 + b.0) / 3.0, (r.1 + g.1 + b.1) / 3.0, 0.5);
+            assert_eq!(gamut.clamp(centroid), centroid);
+
+            for (x, y) in gamut.primaries() {
+                let corner = ColorXy::new(x, y, 1.0);
+                assert_eq!(gamut.clamp(corner), corner);
+            }
+        }
+    }
+
+    #[test]
+    fn clamp_moves_colors_to_closest_corner() {
+        // Deep red beyond the red corner of gamut B
+        let color = ColorXy::new(0.8, 0.2, 0.7);
+        let clamped = ColorGamut::HueB.clamp(color);
+
+        assert_xy_near(clamped, (0.675, 0.322));
+        assert_eq!(clamped.brightness, 0.7);
+
+        // Gamut C green is outside gamut B, closest to its green corner
+        let color = ColorXy::new(0.17, 0.7, 1.0);
+        assert_xy_near(ColorGamut::HueB.clamp(color), (0.409, 0.518));
+    }
+
+    #[test]
+    fn clamp_moves_colors_to_closest_edge() {
+        // Just outside the midpoint of the red-blue edge of gamut A
+        let [r, _, b] = ColorGamut::HueA.primaries();
+        let midpoint = ((r.0 + b.0) / 2.0, (r.1 + b.1) / 2.0);
+        let normal = (r.1 - b.1, b.0 - r.0);
+        let color = ColorXy::new(
+            midpoint.0 + normal.0 * 0.05,
+            midpoint.1 + normal.1 * 0.05,
+            1.0,
+        );
+
+        assert_xy_near(ColorGamut::HueA.clamp(color), midpoint);
+    }
+
+    #[test]
+    fn clamp_is_idempotent() {
+        let colors = [(0.1, 0.8), (0.0, 0.0), (0.75, 0.25), (0.3, 0.1)];
+
+        for gamut in GAMUTS {
+            for (x, y) in colors {
+                let clamped = gamut.clamp(ColorXy::new(x, y, 1.0));
+                assert_xy_near(gamut.clamp(clamped), (clamped.x, clamped.y));
+            }
+        }
+    }
+}
@@ -4,9 +4,14 @@ use std::{
     ops::Range,
 };
 
-use super::{integration::IntegrationId, scene::SceneId};
+use super::{
+    color::{ColorGamut, ColorXy},
+    integration::IntegrationId,
+    scene::SceneId,
+    utils::{cct_to_xy, xy_to_cct},
+};
 use chrono::{DateTime, Utc};
-use palette::{Hsv, RgbHue};
+use palette::Hsv;
 use serde::{
     de::{self, Unexpected, Visitor},
     Deserialize, Serialize,
@@ -15,6 +20,7 @@ use serde::{
 macro_attr! {
     #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!, NewtypeFrom!)]
     /// unique identifier for the Device
+    #[serde(transparent)]
     pub struct DeviceId(String);
 }
 
@@ -40,23 +46,130 @@ pub struct OnOffDevice {
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum DeviceColor {
-    // TODO: use Lch, or Yxy?
-    Color(Hsv),
+    Color(ColorXy),
     Cct(CorrelatedColorTemperature),
 }
 
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+impl DeviceColor {
+    /// Converts color temperatures to their approximate color
+    pub fn to_xy(&self) -> ColorXy {
+        match self {
+            DeviceColor::Color(xy) => *xy,
+            DeviceColor::Cct(cct) => cct_to_xy(cct.get_cct()),
+        }
+    }
+
+    /// Converts the color to HSV, e.g. for integrations that only deal in HSV
+    pub fn to_hsv(&self) -> Hsv {
+        self.to_xy().to_hsv()
+    }
+
+    /// Converts colors to the closest color temperature
+    pub fn to_cct(&self) -> CorrelatedColorTemperature {
+        match self {
+            DeviceColor::Color(xy) => {
+                let cct = CorrelatedColorTemperature::default();
+                let range = cct.get_device_range();
+
+                // Saturated colors can be far outside any supported range
+                let kelvin = xy_to_cct(xy).clamp(range.start, range.end);
+
+                cct.set_cct(kelvin)
+            }
+            DeviceColor::Cct(cct) => cct.clone(),
+        }
+    }
+
+    /// Converts the color to a kind that a device with given capabilities
+    /// supports, clamped to what the device can reproduce. Returns None if
+    /// the device supports neither colors nor color temperatures.
+    pub fn for_capabilities(self, capabilities: &Capability) -> Option<DeviceColor> {
+        let color = match &self {
+            DeviceColor::Color(_) if !capabilities.hsv && capabilities.cct => {
+                DeviceColor::Cct(self.to_cct())
+            }
+            DeviceColor::Cct(_) if !capabilities.cct && capabilities.hsv => {
+                DeviceColor::Color(self.to_xy())
+            }
+            _ => self,
+        };
+
+        match color {
+            DeviceColor::Color(xy) if capabilities.hsv => match &capabilities.color_gamut {
+                Some(gamut) => Some(DeviceColor::Color(gamut.clamp(xy))),
+                None => Some(DeviceColor::Color(xy)),
+            },
+            DeviceColor::Cct(cct) if capabilities.cct => match &capabilities.cct_range {
+                Some(range) => {
+                    let kelvin = cct.get_cct().clamp(range.start, range.end);
+                    Some(DeviceColor::Cct(CorrelatedColorTemperature::new(
+                        kelvin,
+                        range.clone(),
+                    )))
+                }
+                None => Some(DeviceColor::Cct(cct)),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Describes what a device can do. Requested device states are adapted to
+/// these before being sent to integrations, see `Light::for_capabilities`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Capability {
-    pub Hsv: bool,
-    pub Cct: bool,
+    /// Supports colors
+    pub hsv: bool,
+
+    /// Supports color temperatures
+    pub cct: bool,
+
+    /// Range of colors the device can reproduce, if known
+    pub color_gamut: Option<ColorGamut>,
+
+    /// Supported color temperatures in kelvin, if known
+    pub cct_range: Option<Range<f32>>,
+
+    /// Supports adjusting brightness
+    pub dimmable: bool,
+
+    /// Supports transitioning between states
+    pub transitions: bool,
+
+    /// Lowest brightness the device can output while powered on, if known
+    pub min_brightness: Option<f32>,
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "CorrelatedColorTemperatureConfig")]
 pub struct CorrelatedColorTemperature {
     cct: f32,
     device_range: Range<f32>,
 }
 
+/// Allows color temperatures to be configured as plain kelvin values, e.g.
+/// `cct = 2700`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CorrelatedColorTemperatureConfig {
+    Kelvin(f32),
+    Full { cct: f32, device_range: Range<f32> },
+}
+
+impl From<CorrelatedColorTemperatureConfig> for CorrelatedColorTemperature {
+    fn from(config: CorrelatedColorTemperatureConfig) -> Self {
+        match config {
+            CorrelatedColorTemperatureConfig::Kelvin(cct) => {
+                CorrelatedColorTemperature::default().set_cct(cct)
+            }
+            CorrelatedColorTemperatureConfig::Full { cct, device_range } => {
+                CorrelatedColorTemperature { cct, device_range }
+            }
+        }
+    }
+}
+
 impl CorrelatedColorTemperature {
     pub fn new(cct: f32, device_range: Range<f32>) -> CorrelatedColorTemperature {
         CorrelatedColorTemperature { cct, device_range }
@@ -105,6 +218,54 @@ pub struct Light {
 }
 
 impl Light {
+    /// Adapts the light to what a device with given capabilities can do, e.g.
+    /// converts colors to a kind the device supports and drops unsupported
+    /// fields. Lights with unknown capabilities are left as-is.
+    pub fn for_capabilities(mut self, capabilities: Option<&Capability>) -> Light {
+        let capabilities = match capabilities {
+            Some(capabilities) => capabilities,
+            None => return self,
+        };
+
+        if let (Some(DeviceColor::Color(xy)), false) = (&self.color, capabilities.hsv) {
+            // Color temperatures have no brightness component, so apply it to
+            // brightness of the light instead
+            self.brightness = Some(self.brightness.unwrap_or(1.0) * xy.brightness);
+        }
+
+        self.color = self
+            .color
+            .and_then(|color| color.for_capabilities(capabilities));
+
+        if !capabilities.dimmable {
+            self.brightness = None;
+        } else if let (Some(brightness), Some(min_brightness)) =
+            (self.brightness, capabilities.min_brightness)
+        {
+            // Some devices turn themselves off below their minimum brightness
+            if brightness > 0.0 {
+                self.brightness = Some(brightness.max(min_brightness));
+            }
+        }
+
+        if !capabilities.transitions {
+            self.transition_ms = None;
+        }
+
+        self
+    }
+
+    /// Converts to a light with `count` light sources, all using the color of
+    /// this light
+    pub fn to_multi_source(&self, count: usize) -> MultiSourceLight {
+        MultiSourceLight {
+            power: self.power,
+            brightness: self.brightness,
+            lights: self.color.iter().cloned().cycle().take(count).collect(),
+            transition_ms: self.transition_ms,
+        }
+    }
+
     pub fn new(
         power: bool,
         brightness: Option<f32>,
@@ -130,6 +291,72 @@ pub struct MultiSourceLight {
 
     /// List of colors, one for each light in this MultiSourceLight
     pub lights: Vec<DeviceColor>,
+
+    /// Transition time in milliseconds
+    pub transition_ms: Option<u64>,
+}
+
+impl MultiSourceLight {
+    /// Adapts each light source to what a device with given capabilities can
+    /// do, see `Light::for_capabilities`
+    pub fn for_capabilities(mut self, capabilities: Option<&Capability>) -> MultiSourceLight {
+        let capabilities = match capabilities {
+            Some(capabilities) => capabilities,
+            None => return self,
+        };
+
+        // Keep unsupported colors as-is, so that each color still maps to the
+        // correct light source
+        self.lights = self
+            .lights
+            .into_iter()
+            .map(|color| {
+                color
+                    .clone()
+                    .for_capabilities(capabilities)
+                    .unwrap_or(color)
+            })
+            .collect();
+
+        if !capabilities.dimmable {
+            self.brightness = None;
+        }
+
+        if !capabilities.transitions {
+            self.transition_ms = None;
+        }
+
+        self
+    }
+
+    /// Average color of all light sources
+    pub fn average_color(&self) -> Option<ColorXy> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let n = self.lights.len() as f32;
+        let (x, y, brightness) = self
+            .lights
+            .iter()
+            .map(DeviceColor::to_xy)
+            .fold((0.0, 0.0, 0.0), |(x, y, brightness), color| {
+                (x + color.x, y + color.y, brightness + color.brightness)
+            });
+
+        Some(ColorXy::new(x / n, y / n, brightness / n))
+    }
+
+    /// Converts to a single light using the average color of all light
+    /// sources
+    pub fn to_light(&self) -> Light {
+        Light {
+            power: self.power,
+            brightness: self.brightness,
+            color: self.average_color().map(DeviceColor::Color),
+            transition_ms: self.transition_ms,
+        }
+    }
 }
 
 /// button sensors, motion sensors
@@ -144,9 +371,38 @@ pub enum SensorKind {
         down: bool,
         off: bool,
     },
+    /// Temperature in degrees Celsius
+    TemperatureSensor {
+        value: f32,
+    },
+    /// Illuminance in lux
+    IlluminanceSensor {
+        value: f32,
+    },
+    /// Relative humidity in percent
+    HumiditySensor {
+        value: f32,
+    },
+    /// Battery level in percent
+    BatteryLevelSensor {
+        value: f32,
+    },
     Unknown,
 }
 
+impl SensorKind {
+    /// Returns the reading of numeric sensors, None for other sensor kinds
+    pub fn get_numeric_value(&self) -> Option<f32> {
+        match self {
+            SensorKind::TemperatureSensor { value } => Some(*value),
+            SensorKind::IlluminanceSensor { value } => Some(*value),
+            SensorKind::HumiditySensor { value } => Some(*value),
+            SensorKind::BatteryLevelSensor { value } => Some(*value),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum DeviceState {
     OnOffDevice(OnOffDevice),
@@ -163,12 +419,7 @@ impl Display for DeviceState {
                 if !light.power {
                     "off".to_string()
                 } else if let Some(DeviceColor::Color(color)) = light.color {
-                    format!(
-                        "hsv({}, {}, {})",
-                        color.hue.to_positive_degrees(),
-                        color.saturation,
-                        color.value
-                    )
+                    format!("xy({}, {}, {})", color.x, color.y, color.brightness)
                 } else if let Some(DeviceColor::Cct(cct)) = &light.color {
                     format!("cct({})", cct.get_cct())
                 } else if let Some(bri) = light.brightness {
@@ -186,6 +437,17 @@ impl Display for DeviceState {
 }
 
 impl DeviceState {
+    /// Adapts the state to what a device with given capabilities can do
+    pub fn for_capabilities(self, capabilities: Option<&Capability>) -> DeviceState {
+        match self {
+            DeviceState::Light(state) => DeviceState::Light(state.for_capabilities(capabilities)),
+            DeviceState::MultiSourceLight(state) => {
+                DeviceState::MultiSourceLight(state.for_capabilities(capabilities))
+            }
+            state => state,
+        }
+    }
+
     pub fn is_powered_on(&self) -> Option<bool> {
         match self {
             DeviceState::OnOffDevice(device) => Some(device.power),
@@ -219,12 +481,18 @@ impl DeviceState {
                 if !state.power {
                     Some(Hsv::new(0.0, 0.0, 0.0))
                 } else if let Some(DeviceColor::Color(color)) = state.color {
-                    Some(color)
+                    Some(color.to_hsv())
                 } else {
                     None
                 }
             }
-            DeviceState::MultiSourceLight(_) => None,
+            DeviceState::MultiSourceLight(state) => {
+                if !state.power {
+                    Some(Hsv::new(0.0, 0.0, 0.0))
+                } else {
+                    state.average_color().map(|color| color.to_hsv())
+                }
+            }
             DeviceState::Sensor(_) => None,
         }
     }
@@ -233,7 +501,7 @@ impl DeviceState {
         match self {
             DeviceState::OnOffDevice(_) => None,
             DeviceState::Light(state) => state.brightness,
-            DeviceState::MultiSourceLight(_) => None,
+            DeviceState::MultiSourceLight(state) => state.brightness,
             DeviceState::Sensor(_) => None,
         }
     }
@@ -242,37 +510,34 @@ impl DeviceState {
         match self {
             DeviceState::OnOffDevice(_) => {}
             DeviceState::Light(state) => state.brightness = Some(brightness),
-            DeviceState::MultiSourceLight(_) => {}
+            DeviceState::MultiSourceLight(state) => state.brightness = Some(brightness),
             DeviceState::Sensor(_) => {}
         }
     }
 
-    pub fn set_hue(&mut self, hue: f32) {
-        match self {
-            DeviceState::OnOffDevice(_) => {}
-            DeviceState::Light(state) => {
-                if let Some(DeviceColor::Color(color)) = &mut state.color {
-                    color.hue = RgbHue::from_degrees(hue);
-                } else {
-                    state.color = Some(DeviceColor::Color(Hsv::new(hue, 0.0, 1.0)));
-                }
-            }
-            DeviceState::MultiSourceLight(_) => {}
-            DeviceState::Sensor(_) => {}
-        }
-    }
+    /// Sets the hue and saturation of the light while keeping its
+    /// brightness. Both are set at once because colors are stored as xy,
+    /// where the hue of an unsaturated color can't be recovered.
+    pub fn set_hue_saturation(&mut self, hue: f32, saturation: f32) {
+        let set_color = |color: Option<&DeviceColor>| {
+            let value = match color {
+                Some(DeviceColor::Color(color)) => color.brightness,
+                _ => 1.0,
+            };
+
+            DeviceColor::Color(Hsv::new(hue, saturation, value).into())
+        };
 
-    pub fn set_saturation(&mut self, saturation: f32) {
         match self {
             DeviceState::OnOffDevice(_) => {}
             DeviceState::Light(state) => {
-                if let Some(DeviceColor::Color(color)) = &mut state.color {
-                    color.saturation = saturation;
-                } else {
-                    state.color = Some(DeviceColor::Color(Hsv::new(0.0, saturation, 1.0)));
+                state.color = Some(set_color(state.color.as_ref()));
+            }
+            DeviceState::MultiSourceLight(state) => {
+                for color in &mut state.lights {
+                    *color = set_color(Some(color));
                 }
             }
-            DeviceState::MultiSourceLight(_) => {}
             DeviceState::Sensor(_) => {}
         }
     }
@@ -282,12 +547,18 @@ impl DeviceState {
             DeviceState::OnOffDevice(_) => {}
             DeviceState::Light(state) => {
                 if let Some(DeviceColor::Color(color)) = &mut state.color {
-                    color.value = value;
+                    color.brightness = value;
                 } else {
-                    state.color = Some(DeviceColor::Color(Hsv::new(0.0, 0.0, value)));
+                    state.color = Some(DeviceColor::Color(Hsv::new(0.0, 0.0, value).into()));
+                }
+            }
+            DeviceState::MultiSourceLight(state) => {
+                for color in &mut state.lights {
+                    let mut xy = color.to_xy();
+                    xy.brightness = value;
+                    *color = DeviceColor::Color(xy);
                 }
             }
-            DeviceState::MultiSourceLight(_) => {}
             DeviceState::Sensor(_) => {}
         }
     }
@@ -302,6 +573,18 @@ impl DeviceState {
         }
     }
 
+    /// Returns color temperature of the light in kelvin. Colors are
+    /// approximated with the closest color temperature.
+    pub fn get_approximate_cct(&self) -> Option<f32> {
+        let color = match self {
+            DeviceState::Light(state) => state.color.clone(),
+            DeviceState::MultiSourceLight(state) => state.average_color().map(DeviceColor::Color),
+            _ => None,
+        };
+
+        color.map(|color| color.to_cct().get_cct())
+    }
+
     pub fn set_cct(&mut self, cct: f32) {
         match self {
             DeviceState::OnOffDevice(_) => {}
@@ -313,7 +596,13 @@ impl DeviceState {
                     state.color = Some(DeviceColor::Cct(cct_default.set_cct(cct)));
                 }
             }
-            DeviceState::MultiSourceLight(_) => {}
+            DeviceState::MultiSourceLight(state) => {
+                let cct = CorrelatedColorTemperature::default().set_cct(cct);
+
+                for color in &mut state.lights {
+                    *color = DeviceColor::Cct(cct.clone());
+                }
+            }
             DeviceState::Sensor(_) => {}
         }
     }
@@ -346,6 +635,79 @@ pub struct DeviceRow {
     // pub capabilities: Option<Capability>,
 }
 
+/// Recorded state of a device at some point in time
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DeviceHistoryEntry {
+    pub scene_id: Option<SceneId>,
+    pub state: DeviceState,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "backend")]
+pub struct DeviceHistoryRow {
+    pub scene_id: Option<String>,
+    pub state: sqlx::types::Json<DeviceState>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "backend")]
+impl From<DeviceHistoryRow> for DeviceHistoryEntry {
+    fn from(row: DeviceHistoryRow) -> Self {
+        DeviceHistoryEntry {
+            scene_id: row.scene_id.map(SceneId::new),
+            state: row.state.0,
+            recorded_at: row.recorded_at,
+        }
+    }
+}
+
+/// Whether a device can currently be reached by its integration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeviceAvailability {
+    pub online: bool,
+
+    /// When the device was last seen online. Integrations only report
+    /// `online`, this is kept up to date by homectl core.
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl DeviceAvailability {
+    pub fn new(online: bool) -> Self {
+        DeviceAvailability {
+            online,
+            last_seen: None,
+        }
+    }
+}
+
+impl Default for DeviceAvailability {
+    fn default() -> Self {
+        DeviceAvailability::new(true)
+    }
+}
+
+/// Only `online` is compared, as `last_seen` is bumped on every poll and
+/// shouldn't count as a change in device state
+impl PartialEq for DeviceAvailability {
+    fn eq(&self, other: &Self) -> bool {
+        self.online == other.online
+    }
+}
+
+/// Set by homectl core when a device's state has been changed outside of
+/// homectl, e.g. via a vendor app or a physical switch
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ManualOverride {
+    /// Scene that was active before the device was overridden
+    pub scene_id: Option<SceneId>,
+
+    pub override_time: DateTime<Utc>,
+
+    /// When the device returns to `scene_id`. Overrides without an expiry time
+    /// last until the next scene activation.
+    pub expires: Option<DateTime<Utc>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Device {
     pub id: DeviceId,
@@ -354,6 +716,12 @@ pub struct Device {
     pub scene: Option<DeviceSceneState>,
     pub state: DeviceState,
     pub capabilities: Option<Capability>,
+
+    #[serde(default)]
+    pub availability: DeviceAvailability,
+
+    #[serde(default)]
+    pub manual_override: Option<ManualOverride>,
 }
 
 #[cfg(feature = "backend")]
@@ -366,6 +734,8 @@ impl From<DeviceRow> for Device {
             scene: row.scene_id.map(SceneId::new).map(DeviceSceneState::new),
             state: row.state.0,
             capabilities: None,
+            availability: Default::default(),
+            manual_override: None,
         }
     }
 }
@@ -385,6 +755,8 @@ impl Device {
             scene: None,
             state,
             capabilities,
+            availability: Default::default(),
+            manual_override: None,
         }
     }
 
@@ -398,6 +770,15 @@ impl Device {
     pub fn get_scene_id(&self) -> Option<&SceneId> {
         self.scene.as_ref().map(|scene| &scene.scene_id)
     }
+
+    /// Whether the device is known to support transitions natively. Devices
+    /// with unknown capabilities are faded by homectl core instead.
+    pub fn supports_transitions(&self) -> bool {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.transitions,
+            None => false,
+        }
+    }
 }
 
 #[derive(Hash, Clone, Debug, PartialEq, Eq)]
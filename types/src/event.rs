@@ -1,5 +1,9 @@
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
 use crate::scene::{SceneId, SceneConfig};
 
@@ -52,23 +56,55 @@ pub enum Message {
 #[derive(Clone)]
 pub struct Sender<T> {
     sender: UnboundedSender<T>,
+    backlog: Arc<AtomicUsize>,
 }
 
 impl<T: std::fmt::Debug> Sender<T> {
     pub fn send(&self, msg: T) {
+        self.backlog.fetch_add(1, Ordering::Relaxed);
         self.sender
             .send(msg)
             .expect("Receiver end of channel closed");
     }
+
+    /// Number of messages sent but not yet received
+    pub fn backlog(&self) -> usize {
+        self.backlog.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Receiver<T> {
+    receiver: UnboundedReceiver<T>,
+    backlog: Arc<AtomicUsize>,
+}
+
+impl<T> Receiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        let msg = self.receiver.recv().await;
+
+        if msg.is_some() {
+            self.backlog.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        msg
+    }
 }
 
 pub type TxEventChannel = Sender<Message>;
-pub type RxEventChannel = UnboundedReceiver<Message>;
+pub type RxEventChannel = Receiver<Message>;
 
 pub fn mk_channel() -> (TxEventChannel, RxEventChannel) {
     let (tx, rx) = unbounded_channel::<Message>();
+    let backlog: Arc<AtomicUsize> = Default::default();
 
-    let sender = Sender { sender: tx };
+    let sender = Sender {
+        sender: tx,
+        backlog: backlog.clone(),
+    };
+    let receiver = Receiver {
+        receiver: rx,
+        backlog,
+    };
 
-    (sender, rx)
+    (sender, receiver)
 }
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::Actions,
+    event::Message,
+    integration::IntegrationId,
+    rule::{RoutineId, Rules},
+};
+
+/// Where an event originated from
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventOrigin {
+    /// Sent by a WebSocket peer
+    WebSocket { user_id: usize },
+
+    /// Sent through the REST API
+    Api,
+
+    /// Dispatched by a routine whose rules matched
+    Routine { routine_id: RoutineId },
+
+    /// Raised by an integration
+    Integration { integration_id: IntegrationId },
+
+    /// Raised by homectl itself
+    Core,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// All rules of a routine matched, and its actions were dispatched
+    RoutineTriggered {
+        routine_id: RoutineId,
+        name: String,
+        rules: Rules,
+        actions: Actions,
+    },
+
+    /// A message was sent to homectl, e.g. an action or a device state change
+    Message { message: Message },
+
+    /// Handling a message failed
+    Error { message: Message, error: String },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventLogEntry {
+    pub origin: EventOrigin,
+    pub event: Event,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "backend")]
+pub struct EventLogRow {
+    pub origin: sqlx::types::Json<EventOrigin>,
+    pub event: sqlx::types::Json<Event>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(feature = "backend")]
+impl From<EventLogRow> for EventLogEntry {
+    fn from(row: EventLogRow) -> Self {
+        EventLogEntry {
+            origin: row.origin.0,
+            event: row.event.0,
+            created_at: row.created_at,
+        }
+    }
+}
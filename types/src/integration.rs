@@ -10,6 +10,7 @@ use std::{collections::HashMap, str::FromStr, convert::Infallible};
 
 macro_attr! {
     #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!, NewtypeFrom!)]
+    #[serde(transparent)]
     pub struct IntegrationId(String);
 }
 
@@ -32,6 +33,31 @@ pub struct IntegrationConfig {
 
 pub type IntegrationsConfig = HashMap<IntegrationId, IntegrationConfig>;
 
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrationHealth {
+    /// Integration is being registered and started
+    Starting,
+
+    /// Integration is running normally
+    Healthy,
+
+    /// Integration is running, but recently failed to set device state or run
+    /// an action
+    Degraded,
+
+    /// Integration failed to start or stopped working, and will be restarted
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct IntegrationStatus {
+    pub health: IntegrationHealth,
+    pub last_error: Option<String>,
+}
+
+pub type IntegrationsStatus = HashMap<IntegrationId, IntegrationStatus>;
+
 macro_attr! {
     #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!)]
     pub struct IntegrationActionPayload(String);
@@ -52,6 +78,16 @@ pub trait Integration {
 
     async fn register(&mut self) -> Result<()>;
     async fn start(&mut self) -> Result<()>;
+
+    /// Stops any background tasks spawned by the integration. Called before
+    /// the integration is unloaded, e.g. when its config has been changed.
+    async fn stop(&mut self) -> Result<()>;
+
+    /// Returns an error if the integration has stopped working, e.g. because
+    /// one of its background tasks has exited. Failing integrations are
+    /// restarted.
+    async fn health_check(&mut self) -> Result<()>;
+
     async fn set_integration_device_state(&mut self, device: &Device) -> Result<()>;
     async fn run_integration_action(&mut self, payload: &IntegrationActionPayload) -> Result<()>;
 }
@@ -5,11 +5,17 @@ extern crate macro_attr;
 extern crate newtype_derive;
 
 pub mod action;
+pub mod api;
+pub mod color;
 pub mod device;
 pub mod event;
+pub mod event_log;
 pub mod group;
 pub mod integration;
+pub mod manual_override;
+pub mod presence;
 pub mod rule;
 pub mod scene;
+pub mod solar;
 pub mod utils;
 pub mod websockets;
@@ -0,0 +1,32 @@
+use crate::group::{GroupDeviceLink, GroupId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct OverridePolicy {
+    /// How long a manually changed device keeps its state before returning to
+    /// its previous scene. Without a timeout the device is left alone until
+    /// the next scene activation.
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct DeviceOverridePolicy {
+    #[serde(flatten)]
+    pub device: GroupDeviceLink,
+
+    #[serde(flatten)]
+    pub policy: OverridePolicy,
+}
+
+/// Devices whose state may be changed outside of homectl. Any other devices
+/// are restored to their expected state whenever they drift from it.
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct ManualOverrideConfig {
+    /// Policies for devices of these groups
+    pub groups: Option<HashMap<GroupId, OverridePolicy>>,
+
+    /// Policies for individual devices, these take precedence over group
+    /// policies
+    pub devices: Option<Vec<DeviceOverridePolicy>>,
+}
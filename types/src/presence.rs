@@ -0,0 +1,86 @@
+use crate::{device::DeviceId, group::GroupDeviceLink, integration::IntegrationId};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, convert::Infallible};
+
+/// Integration id of the virtual sensor devices exposed by the presence
+/// subsystem
+pub const PRESENCE_INTEGRATION_ID: &str = "presence";
+
+macro_attr! {
+    #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!)]
+    #[serde(transparent)]
+    pub struct PersonId(String);
+}
+
+impl std::str::FromStr for PersonId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(PersonId(s.to_string()))
+    }
+}
+
+macro_attr! {
+    #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!)]
+    #[serde(transparent)]
+    pub struct RoomId(String);
+}
+
+impl std::str::FromStr for RoomId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(RoomId(s.to_string()))
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct PersonConfig {
+    pub name: String,
+
+    /// Devices that indicate the person is home while they are active, e.g.
+    /// phones tracked by the ping integration
+    pub devices: Vec<GroupDeviceLink>,
+
+    /// How long the person is still considered home after all of their
+    /// devices have become inactive
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct RoomConfig {
+    pub name: String,
+
+    /// Devices that indicate the room is occupied while they are active, e.g.
+    /// motion sensors or switches
+    pub devices: Vec<GroupDeviceLink>,
+
+    /// How long the room is still considered occupied after all of its
+    /// devices have become inactive
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct PresenceConfig {
+    pub people: Option<HashMap<PersonId, PersonConfig>>,
+    pub rooms: Option<HashMap<RoomId, RoomConfig>>,
+}
+
+pub fn presence_integration_id() -> IntegrationId {
+    IntegrationId::from(PRESENCE_INTEGRATION_ID.to_string())
+}
+
+/// Id of the virtual sensor that is on while anyone is home
+pub fn someone_home_device_id() -> DeviceId {
+    DeviceId::new("someone_home")
+}
+
+/// Id of the virtual sensor that is on while given person is home
+pub fn person_device_id(person_id: &PersonId) -> DeviceId {
+    DeviceId::new(&format!("person/{}", person_id))
+}
+
+/// Id of the virtual sensor that is on while given room is occupied
+pub fn room_device_id(room_id: &RoomId) -> DeviceId {
+    DeviceId::new(&format!("room/{}", room_id))
+}
@@ -1,21 +1,70 @@
-use crate::{group::GroupId, scene::SceneId};
+use crate::{
+    group::{GroupDeviceLink, GroupId},
+    presence::{PersonId, RoomId},
+    scene::SceneId,
+    solar::TimeOfDay,
+};
 
 use super::{action::Actions, device::DeviceId, integration::IntegrationId};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom};
 
 macro_attr! {
     #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!)]
+    #[serde(transparent)]
     pub struct RoutineId(String);
 }
 
-/// button sensors, motion sensors
-#[derive(Clone, Deserialize, Debug)]
+/// Matches numeric sensor readings, all given conditions must hold
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(try_from = "NumericSensorRuleStateConfig")]
+pub struct NumericSensorRuleState {
+    pub above: Option<f32>,
+    pub below: Option<f32>,
+
+    /// Inclusive range
+    pub between: Option<(f32, f32)>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NumericSensorRuleStateConfig {
+    above: Option<f32>,
+    below: Option<f32>,
+    between: Option<(f32, f32)>,
+}
+
+impl TryFrom<NumericSensorRuleStateConfig> for NumericSensorRuleState {
+    type Error = String;
+
+    // A rule without any conditions would match every reading, and would
+    // prevent empty states from being parsed as DimmerSwitch
+    fn try_from(config: NumericSensorRuleStateConfig) -> Result<Self, Self::Error> {
+        if config.above.is_none() && config.below.is_none() && config.between.is_none() {
+            return Err(String::from(
+                "Numeric sensor rule must have at least one of above, below or between",
+            ));
+        }
+
+        Ok(NumericSensorRuleState {
+            above: config.above,
+            below: config.below,
+            between: config.between,
+        })
+    }
+}
+
+/// button sensors, motion sensors, numeric sensors
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 pub enum SensorRuleState {
     OnOffSensor {
         value: bool,
     },
+    // NOTE: must come before DimmerSwitch, which would otherwise match any
+    // table since all of its fields are optional. Empty tables are rejected
+    // here and thus still parsed as DimmerSwitch.
+    NumericSensor(NumericSensorRuleState),
     DimmerSwitch {
         on: Option<bool>,
         up: Option<bool>,
@@ -25,7 +74,7 @@ pub enum SensorRuleState {
     Unknown,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct SensorRule {
     pub integration_id: IntegrationId,
     pub device_id: Option<DeviceId>,
@@ -33,37 +82,180 @@ pub struct SensorRule {
     pub state: SensorRuleState,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct DeviceRule {
     pub integration_id: IntegrationId,
     pub device_id: Option<DeviceId>,
     pub name: Option<String>,
     pub power: Option<bool>,
     pub scene: Option<SceneId>,
+
+    /// Match on whether the device is reachable by its integration
+    pub online: Option<bool>,
+
+    /// Match on whether the device's state has been changed outside of
+    /// homectl
+    pub manually_overridden: Option<bool>,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct GroupRule {
     pub group_id: GroupId,
     pub power: Option<bool>,
     pub scene: Option<SceneId>,
+
+    /// Match on whether all devices in the group are reachable by their
+    /// integrations
+    pub online: Option<bool>,
+
+    /// Match on whether all devices in the group have been changed outside of
+    /// homectl
+    pub manually_overridden: Option<bool>,
+}
+
+/// Matches on whether anyone is home
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SomeoneHomeRule {
+    pub someone_home: bool,
+}
+
+/// Matches on whether a person is home
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct PersonRule {
+    pub person: PersonId,
+    pub home: bool,
+}
+
+/// Matches on whether a room is occupied
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct RoomRule {
+    pub room: RoomId,
+    pub room_occupied: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ScheduleRule {
+    /// Time of day when the rule triggers, either in HH:MM format or relative
+    /// to a solar event (e.g. "sunset - 30min")
+    pub time: TimeOfDay,
+
+    /// Only trigger on these days, defaults to every day
+    pub weekdays: Option<Vec<chrono::Weekday>>,
+}
+
+/// Matches while the time of day is within a window. If `after` is later than
+/// `before`, the window spans midnight.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(try_from = "TimeWindowRuleConfig")]
+pub struct TimeWindowRule {
+    /// Start of the window (inclusive), either in HH:MM format or relative to
+    /// a solar event. Defaults to midnight.
+    pub after: Option<TimeOfDay>,
+
+    /// End of the window (exclusive), defaults to midnight
+    pub before: Option<TimeOfDay>,
+
+    /// Only match on these days, defaults to every day
+    pub weekdays: Option<Vec<chrono::Weekday>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeWindowRuleConfig {
+    after: Option<TimeOfDay>,
+    before: Option<TimeOfDay>,
+    weekdays: Option<Vec<chrono::Weekday>>,
+}
+
+impl TryFrom<TimeWindowRuleConfig> for TimeWindowRule {
+    type Error = String;
+
+    fn try_from(config: TimeWindowRuleConfig) -> Result<Self, Self::Error> {
+        if config.after.is_none() && config.before.is_none() {
+            return Err(String::from(
+                "Time window rule must have at least one of after or before",
+            ));
+        }
+
+        Ok(TimeWindowRule {
+            after: config.after,
+            before: config.before,
+            weekdays: config.weekdays,
+        })
+    }
+}
+
+/// Triggered if any of the contained rules are triggered
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AnyRule {
+    pub any: Rules,
+}
+
+/// Triggered if all of the contained rules are triggered
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AllRule {
+    pub all: Rules,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+/// Triggered if the contained rule is not triggered
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct NotRule {
+    pub not: Box<Rule>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 pub enum Rule {
     Sensor(SensorRule),
     Device(DeviceRule),
     Group(GroupRule),
+    SomeoneHome(SomeoneHomeRule),
+    Person(PersonRule),
+    Room(RoomRule),
+    Schedule(ScheduleRule),
+    TimeWindow(TimeWindowRule),
+    Any(AnyRule),
+    All(AllRule),
+    Not(NotRule),
 }
 
 pub type Rules = Vec<Rule>;
 
-#[derive(Clone, Deserialize, Debug)]
+/// Turns on a group of lights when motion is detected, and turns them off
+/// again once no motion has been detected for a while
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct MotionLighting {
+    /// Motion sensors, or any other on/off sensors (e.g. room occupancy)
+    pub sensors: Vec<GroupDeviceLink>,
+
+    /// Lights to control
+    pub group_id: GroupId,
+
+    /// Scene activated when motion is detected
+    pub on_scene: SceneId,
+
+    /// Scene activated once the timeout has elapsed
+    pub off_scene: SceneId,
+
+    /// How long to wait after motion was last detected before activating
+    /// `off_scene`
+    pub timeout_ms: u64,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct Routine {
     pub name: String,
+
+    #[serde(default)]
     pub rules: Rules,
+
+    #[serde(default)]
     pub actions: Actions,
+
+    /// Makes this a motion lighting routine. `rules` are then treated as
+    /// conditions that must hold for lights to be turned on, and `actions`
+    /// are not used.
+    pub motion_lighting: Option<MotionLighting>,
 }
 
 pub type RoutinesConfig = HashMap<RoutineId, Routine>;
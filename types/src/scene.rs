@@ -1,16 +1,18 @@
 use crate::device::{CorrelatedColorTemperature, DeviceKey, DeviceState};
 
 use super::{
+    color::ColorXy,
     device::{DeviceColor, DeviceId},
     group::GroupId,
     integration::IntegrationId,
 };
 use palette::{rgb::Rgb, Hsv, Lch};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::Infallible};
 
 macro_attr! {
     #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq, Hash, NewtypeDisplay!, NewtypeFrom!)]
+    #[serde(transparent)]
     pub struct SceneId(String);
 }
 
@@ -20,19 +22,35 @@ impl SceneId {
     }
 }
 
+impl std::str::FromStr for SceneId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(SceneId(s.to_string()))
+    }
+}
+
 #[derive(Clone, Deserialize, Debug, Serialize)]
 #[serde(untagged)]
 pub enum ColorConfig {
     Lch(Lch),
     Hsv(Hsv),
     Rgb(Rgb),
+    Xy(ColorXy),
 }
 
 pub fn color_config_as_device_color(color_config: ColorConfig) -> DeviceColor {
     DeviceColor::Color(match color_config {
-        ColorConfig::Lch(lch) => lch.into(),
-        ColorConfig::Hsv(hsv) => hsv,
-        ColorConfig::Rgb(rgb) => rgb.into(),
+        ColorConfig::Lch(lch) => {
+            let hsv: Hsv = lch.into();
+            hsv.into()
+        }
+        ColorConfig::Hsv(hsv) => hsv.into(),
+        ColorConfig::Rgb(rgb) => {
+            let hsv: Hsv = rgb.into();
+            hsv.into()
+        }
+        ColorConfig::Xy(xy) => xy,
     })
 }
 
@@ -64,6 +82,14 @@ pub struct SceneDeviceState {
     pub brightness: Option<f32>,
     pub cct: Option<CorrelatedColorTemperature>,
     pub transition_ms: Option<u64>,
+
+    /// Colors of each light source of devices with multiple light sources,
+    /// e.g. LED strips. Repeated if the device has more light sources.
+    pub segments: Option<Vec<ColorConfig>>,
+
+    /// Colors to fade between across all light sources of devices with
+    /// multiple light sources
+    pub gradient: Option<Vec<ColorConfig>>,
 }
 
 #[derive(Clone, Deserialize, Debug, Serialize)]
@@ -87,11 +113,78 @@ pub type SceneGroupsConfig = HashMap<GroupId, SceneDeviceConfig>;
 /// Device "search" config as used directly in the configuration file. We use device names instead of device id as key.
 pub type SceneDevicesSearchConfig = HashMap<IntegrationId, HashMap<String, SceneDeviceConfig>>;
 
+/// How a transition progresses over time
+#[derive(Clone, Copy, Deserialize, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionEasing {
+    Linear,
+
+    /// Starts and ends slowly
+    EaseInOut,
+
+    /// Linear, but in terms of perceived brightness instead of light output.
+    /// Avoids most of the change happening at low brightness levels.
+    Perceptual,
+}
+
+// `#[default]` on enum variants requires Rust 1.62
+#[allow(clippy::derivable_impls)]
+impl Default for TransitionEasing {
+    fn default() -> Self {
+        TransitionEasing::Linear
+    }
+}
+
+impl TransitionEasing {
+    /// Maps linear progress `t` in 0..1 to eased progress
+    pub fn ease(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            TransitionEasing::Linear => t,
+            TransitionEasing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            // Brightness is interpolated perceptually, see
+            // `interpolate_brightness`
+            TransitionEasing::Perceptual => t,
+        }
+    }
+
+    /// Interpolates between two brightness values at progress `t`
+    pub fn interpolate_brightness(&self, from: f32, to: f32, t: f32) -> f32 {
+        let t = self.ease(t);
+
+        match self {
+            // Perceived lightness is roughly proportional to the cube root of
+            // light output (CIE L*)
+            TransitionEasing::Perceptual => {
+                let from = from.max(0.0).cbrt();
+                let to = to.max(0.0).cbrt();
+                (from + (to - from) * t).powi(3)
+            }
+            _ => from + (to - from) * t,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, Serialize)]
+pub struct SceneTransition {
+    pub duration_ms: u64,
+
+    /// Only applies to devices without native transitions, which are faded by
+    /// homectl. Devices with native transitions fade by themselves.
+    #[serde(default)]
+    pub easing: TransitionEasing,
+}
+
 #[derive(Clone, Deserialize, Debug, Serialize)]
 pub struct SceneConfig {
     pub name: String,
     pub devices: Option<SceneDevicesSearchConfig>,
     pub groups: Option<SceneGroupsConfig>,
+
+    /// Transition used when activating the scene, unless overridden by
+    /// `transition_ms` of a device
+    pub transition: Option<SceneTransition>,
 }
 
 pub type ScenesConfig = HashMap<SceneId, SceneConfig>;
@@ -0,0 +1,408 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use serde::{de, Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// Geographic location used for computing solar events
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Location {
+    /// Degrees, north is positive
+    pub latitude: f64,
+
+    /// Degrees, east is positive
+    pub longitude: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SolarEvent {
+    AstronomicalDawn,
+    NauticalDawn,
+    CivilDawn,
+    Sunrise,
+    SolarNoon,
+    Sunset,
+    CivilDusk,
+    NauticalDusk,
+    AstronomicalDusk,
+}
+
+impl SolarEvent {
+    /// Elevation of the sun's center (in degrees) at which the event occurs
+    fn elevation(&self) -> f64 {
+        match self {
+            SolarEvent::AstronomicalDawn | SolarEvent::AstronomicalDusk => -18.0,
+            SolarEvent::NauticalDawn | SolarEvent::NauticalDusk => -12.0,
+            SolarEvent::CivilDawn | SolarEvent::CivilDusk => -6.0,
+            // Accounts for atmospheric refraction and the sun's apparent radius
+            SolarEvent::Sunrise | SolarEvent::Sunset => -0.833,
+            SolarEvent::SolarNoon => 0.0,
+        }
+    }
+
+    fn is_morning(&self) -> bool {
+        matches!(
+            self,
+            SolarEvent::AstronomicalDawn
+                | SolarEvent::NauticalDawn
+                | SolarEvent::CivilDawn
+                | SolarEvent::Sunrise
+        )
+    }
+}
+
+impl FromStr for SolarEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .collect::<String>()
+            .to_lowercase();
+
+        match normalized.as_str() {
+            "astronomicaldawn" => Ok(SolarEvent::AstronomicalDawn),
+            "nauticaldawn" => Ok(SolarEvent::NauticalDawn),
+            "civildawn" | "dawn" => Ok(SolarEvent::CivilDawn),
+            "sunrise" => Ok(SolarEvent::Sunrise),
+            "solarnoon" | "noon" => Ok(SolarEvent::SolarNoon),
+            "sunset" => Ok(SolarEvent::Sunset),
+            "civildusk" | "dusk" => Ok(SolarEvent::CivilDusk),
+            "nauticaldusk" => Ok(SolarEvent::NauticalDusk),
+            "astronomicaldusk" => Ok(SolarEvent::AstronomicalDusk),
+            _ => Err(format!("Unknown solar event {}", s)),
+        }
+    }
+}
+
+/// Time of day, either as a fixed time or relative to a solar event.
+///
+/// Deserialized from strings such as "18:30", "sunset", "sunset - 30min" or
+/// "civil dawn + 1h".
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeOfDay {
+    Fixed(NaiveTime),
+    Solar {
+        event: SolarEvent,
+        offset: chrono::Duration,
+    },
+}
+
+impl TimeOfDay {
+    /// Resolves the local time at which this TimeOfDay occurs on given date.
+    /// Returns None if the sun never reaches the required elevation on that
+    /// date (e.g. during polar summer)
+    pub fn resolve(&self, date: NaiveDate, location: &Location) -> Option<NaiveTime> {
+        match self {
+            TimeOfDay::Fixed(time) => Some(*time),
+            TimeOfDay::Solar { event, offset } => {
+                let utc = get_solar_event_time(*event, date, location)?;
+                let local = chrono::Local.from_utc_datetime(&utc).naive_local();
+
+                Some((local + *offset).time())
+            }
+        }
+    }
+}
+
+fn parse_offset(s: &str) -> Result<chrono::Duration, String> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or(format!("Missing unit in time offset {}", s))?;
+    let (value, unit) = s.split_at(unit_start);
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("Invalid time offset {}", s))?;
+
+    match unit {
+        "h" => Ok(chrono::Duration::hours(value)),
+        "m" | "min" => Ok(chrono::Duration::minutes(value)),
+        "s" => Ok(chrono::Duration::seconds(value)),
+        _ => Err(format!("Unknown unit in time offset {}", s)),
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Ok(time) = NaiveTime::parse_from_str(s, "%H:%M") {
+            return Ok(TimeOfDay::Fixed(time));
+        }
+
+        let (event, offset) = match s.find(['+', '-']) {
+            Some(i) => {
+                let (event, offset) = s.split_at(i);
+                let offset_sign = if offset.starts_with('-') { -1 } else { 1 };
+                let offset = parse_offset(&offset[1..])? * offset_sign;
+
+                (event, offset)
+            }
+            None => (s, chrono::Duration::zero()),
+        };
+
+        let event = event.parse()?;
+
+        Ok(TimeOfDay::Solar { event, offset })
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeOfDay::Fixed(time) => write!(f, "{}", time.format("%H:%M")),
+            TimeOfDay::Solar { event, offset } => {
+                write!(f, "{:?}", event)?;
+
+                let seconds = offset.num_seconds();
+                let sign = if seconds < 0 { '-' } else { '+' };
+                let seconds = seconds.abs();
+
+                // Offsets with sub-minute precision are written in seconds
+                // so that they survive a round-trip through parsing
+                if seconds == 0 {
+                    Ok(())
+                } else if seconds % 60 == 0 {
+                    write!(f, " {} {}min", sign, seconds / 60)
+                } else {
+                    write!(f, " {} {}s", sign, seconds)
+                }
+            }
+        }
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let str = String::deserialize(deserializer)?;
+        str.parse().map_err(de::Error::custom)
+    }
+}
+
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+
+/// Computes the UTC time of a solar event on given date using the sunrise
+/// equation, see https://en.wikipedia.org/wiki/Sunrise_equation
+pub fn get_solar_event_time(
+    event: SolarEvent,
+    date: NaiveDate,
+    location: &Location,
+) -> Option<NaiveDateTime> {
+    let n = (date - NaiveDate::from_ymd(2000, 1, 1)).num_days() as f64;
+
+    // Mean solar time
+    let j_star = n - location.longitude / 360.0;
+
+    // Solar mean anomaly
+    let m = (357.5291 + 0.98560028 * j_star).rem_euclid(360.0);
+    let m_rad = m.to_radians();
+
+    // Equation of the center
+    let c = 1.9148 * m_rad.sin() + 0.02 * (2.0 * m_rad).sin() + 0.0003 * (3.0 * m_rad).sin();
+
+    // Ecliptic longitude
+    let lambda = (m + c + 180.0 + 102.9372).rem_euclid(360.0);
+    let lambda_rad = lambda.to_radians();
+
+    // Solar transit
+    let j_transit = J2000 + j_star + 0.0053 * m_rad.sin() - 0.0069 * (2.0 * lambda_rad).sin();
+
+    // Declination of the sun
+    let sin_declination = lambda_rad.sin() * 23.4397_f64.to_radians().sin();
+    let cos_declination = sin_declination.asin().cos();
+
+    let julian_date = if event == SolarEvent::SolarNoon {
+        j_transit
+    } else {
+        // Hour angle
+        let latitude = location.latitude.to_radians();
+        let cos_hour_angle = (event.elevation().to_radians().sin()
+            - latitude.sin() * sin_declination)
+            / (latitude.cos() * cos_declination);
+
+        if !(-1.0..=1.0).contains(&cos_hour_angle) {
+            return None;
+        }
+
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+
+        if event.is_morning() {
+            j_transit - hour_angle / 360.0
+        } else {
+            j_transit + hour_angle / 360.0
+        }
+    };
+
+    let unix_ms = ((julian_date - UNIX_EPOCH_JULIAN_DAY) * 86_400_000.0).round() as i64;
+
+    Some(NaiveDateTime::from_timestamp(
+        unix_ms.div_euclid(1000),
+        (unix_ms.rem_euclid(1000) * 1_000_000) as u32,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.0060,
+    };
+
+    const HELSINKI: Location = Location {
+        latitude: 60.1699,
+        longitude: 24.9384,
+    };
+
+    const SYDNEY: Location = Location {
+        latitude: -33.8688,
+        longitude: 151.2093,
+    };
+
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn utc(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    /// Asserts that a computed event time is within two minutes of the
+    /// reference value from the NOAA solar calculator
+    fn assert_near(event: SolarEvent, date: NaiveDate, location: &Location, expected: &str) {
+        let actual = get_solar_event_time(event, date, location).unwrap();
+        let diff = (actual - utc(expected)).num_seconds().abs();
+
+        assert!(
+            diff <= 120,
+            "{:?} on {} was {}, expected {}",
+            event,
+            date,
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn matches_noaa_summer_solstice() {
+        let date = NaiveDate::from_ymd(2020, 6, 20);
+
+        assert_near(
+            SolarEvent::CivilDawn,
+            date,
+            &NEW_YORK,
+            "2020-06-20 08:51:28",
+        );
+        assert_near(SolarEvent::Sunrise, date, &NEW_YORK, "2020-06-20 09:24:54");
+        assert_near(
+            SolarEvent::SolarNoon,
+            date,
+            &NEW_YORK,
+            "2020-06-20 16:57:46",
+        );
+        assert_near(SolarEvent::Sunset, date, &NEW_YORK, "2020-06-21 00:30:38");
+
+        assert_near(SolarEvent::Sunrise, date, &HELSINKI, "2020-06-20 00:53:56");
+        assert_near(SolarEvent::Sunset, date, &HELSINKI, "2020-06-20 19:49:57");
+    }
+
+    #[test]
+    fn matches_noaa_winter_solstice() {
+        let date = NaiveDate::from_ymd(2020, 12, 21);
+
+        assert_near(SolarEvent::Sunrise, date, &NEW_YORK, "2020-12-21 12:16:49");
+        assert_near(SolarEvent::Sunset, date, &NEW_YORK, "2020-12-21 21:32:04");
+
+        assert_near(
+            SolarEvent::CivilDawn,
+            date,
+            &HELSINKI,
+            "2020-12-21 06:25:39",
+        );
+        assert_near(SolarEvent::Sunrise, date, &HELSINKI, "2020-12-21 07:24:04");
+        assert_near(SolarEvent::Sunset, date, &HELSINKI, "2020-12-21 13:12:59");
+
+        // Sunrise in Sydney is on the previous day in UTC
+        assert_near(SolarEvent::Sunrise, date, &SYDNEY, "2020-12-20 18:40:53");
+        assert_near(SolarEvent::Sunset, date, &SYDNEY, "2020-12-21 09:05:40");
+    }
+
+    #[test]
+    fn matches_noaa_equinox() {
+        let date = NaiveDate::from_ymd(2021, 3, 20);
+
+        assert_near(SolarEvent::Sunrise, date, &NEW_YORK, "2021-03-20 10:58:57");
+        assert_near(SolarEvent::Sunset, date, &NEW_YORK, "2021-03-20 23:08:25");
+        assert_near(SolarEvent::SolarNoon, date, &SYDNEY, "2021-03-20 02:02:40");
+    }
+
+    #[test]
+    fn polar_day_and_night_have_no_sunrise() {
+        let midsummer = NaiveDate::from_ymd(2020, 6, 20);
+        let midwinter = NaiveDate::from_ymd(2020, 12, 21);
+
+        assert_eq!(
+            get_solar_event_time(SolarEvent::Sunset, midsummer, &TROMSO),
+            None
+        );
+        assert_eq!(
+            get_solar_event_time(SolarEvent::Sunrise, midwinter, &TROMSO),
+            None
+        );
+        assert!(get_solar_event_time(SolarEvent::SolarNoon, midwinter, &TROMSO).is_some());
+    }
+
+    #[test]
+    fn parses_time_of_day() {
+        assert_eq!(
+            "18:30".parse::<TimeOfDay>(),
+            Ok(TimeOfDay::Fixed(NaiveTime::from_hms(18, 30, 0)))
+        );
+        assert_eq!(
+            "civil dawn + 1h".parse::<TimeOfDay>(),
+            Ok(TimeOfDay::Solar {
+                event: SolarEvent::CivilDawn,
+                offset: chrono::Duration::hours(1)
+            })
+        );
+        assert_eq!(
+            "sunset - 30min".parse::<TimeOfDay>(),
+            Ok(TimeOfDay::Solar {
+                event: SolarEvent::Sunset,
+                offset: chrono::Duration::minutes(-30)
+            })
+        );
+        assert!("sunset + 30".parse::<TimeOfDay>().is_err());
+        assert!("moonrise".parse::<TimeOfDay>().is_err());
+    }
+
+    #[test]
+    fn time_of_day_round_trips() {
+        for s in ["18:30", "Sunset", "Sunset - 30min", "CivilDawn + 90s"] {
+            let time: TimeOfDay = s.parse().unwrap();
+
+            assert_eq!(time.to_string(), s);
+            assert_eq!(time.to_string().parse::<TimeOfDay>(), Ok(time));
+        }
+    }
+}
@@ -1,6 +1,17 @@
-use palette::{rgb::Rgb, Yxy};
+use palette::rgb::Rgb;
+use serde::{de, Deserialize};
 
-pub fn xy_to_cct(color: &Yxy) -> f32 {
+use crate::color::ColorXy;
+
+pub fn from_hh_mm<'de, D>(d: D) -> Result<chrono::NaiveTime, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let str = String::deserialize(d)?;
+    chrono::NaiveTime::parse_from_str(&str, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+pub fn xy_to_cct(color: &ColorXy) -> f32 {
     let x = color.x;
     let y = color.y;
 
@@ -9,18 +20,43 @@ pub fn xy_to_cct(color: &Yxy) -> f32 {
     437.0 * n.powf(3.0) + 3601.0 * n.powf(2.0) + 6861.0 * n + 5517.0
 }
 
+/// Approximates the chromaticity of a black body radiator at given
+/// temperature (the Planckian locus), valid from 1667K to 25000K
+pub fn cct_to_xy(kelvin: f32) -> ColorXy {
+    let t = kelvin.clamp(1667.0, 25000.0) as f64;
+
+    // Kim et al. cubic spline approximation
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t.powi(3) - 0.2343589e6 / t.powi(2) + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t.powi(3) + 2.1070379e6 / t.powi(2) + 0.2226347e3 / t + 0.240390
+    };
+
+    let y = if t <= 2222.0 {
+        -1.1063814 * x.powi(3) - 1.3481102 * x.powi(2) + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x.powi(3) - 1.37418593 * x.powi(2) + 2.09137015 * x - 0.16748867
+    } else {
+        3.081758 * x.powi(3) - 5.8733867 * x.powi(2) + 3.75112997 * x - 0.37001483
+    };
+
+    ColorXy::new(x as f32, y as f32, 1.0)
+}
+
+/// Approximates the color of a black body radiator at given temperature,
+/// returning RGB components in the 0.0 - 1.0 range
 pub fn cct_to_rgb(kelvin: f32) -> Rgb {
     let temp = kelvin as f64 / 100.0;
 
     let (red, green, blue) = if temp <= 66.0 {
         let red = 255.0;
 
-        let green = 99.4708025861 * f64::log10(temp) - 161.1195681661;
+        let green = 99.4708025861 * f64::ln(temp) - 161.1195681661;
 
         let blue = if temp <= 19.0 {
             0.0
         } else {
-            138.5177312231 * f64::log10(temp - 10.0) - 305.0447927307
+            138.5177312231 * f64::ln(temp - 10.0) - 305.0447927307
         };
 
         (red, green, blue)
@@ -29,12 +65,79 @@ pub fn cct_to_rgb(kelvin: f32) -> Rgb {
         let red = 329.698727446 * f64::powf(red, -0.1332047592);
 
         let green = temp - 60.0;
-        let green = 288.1221695283 * f64::log(green, -0.0755148492);
+        let green = 288.1221695283 * f64::powf(green, -0.0755148492);
 
         let blue = 255.0;
 
         (red, green, blue)
     };
 
-    Rgb::new(red as f32, green as f32, blue as f32)
+    let scale = |c: f64| (c.clamp(0.0, 255.0) / 255.0) as f32;
+
+    Rgb::new(scale(red), scale(green), scale(blue))
+}
+
+/// Interpolates between two color temperatures in the mired scale, which
+/// unlike kelvin is roughly perceptually uniform. `t` goes from 0.0 (a) to
+/// 1.0 (b).
+pub fn interpolate_cct(a: f32, b: f32, t: f32) -> f32 {
+    let a_mired = 1_000_000.0 / a;
+    let b_mired = 1_000_000.0 / b;
+
+    let mired = (1.0 - t) * a_mired + t * b_mired;
+
+    1_000_000.0 / mired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cct_to_xy_follows_planckian_locus() {
+        // CIE 1931 chromaticity of the Planckian locus
+        let reference = [
+            (2000.0, 0.5267, 0.4133),
+            (2700.0, 0.4599, 0.4106),
+            (4000.0, 0.3805, 0.3768),
+            (5000.0, 0.3451, 0.3516),
+            (6500.0, 0.3135, 0.3236),
+            (10000.0, 0.2807, 0.2884),
+        ];
+
+        for (kelvin, x, y) in reference {
+            let color = cct_to_xy(kelvin);
+
+            assert!(
+                (color.x - x).abs() < 1e-3 && (color.y - y).abs() < 1e-3,
+                "{}K was {:?}, expected ({}, {})",
+                kelvin,
+                color,
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn xy_to_cct_inverts_cct_to_xy() {
+        // D65 white point is approximately 6504K
+        let d65 = ColorXy::new(0.3127, 0.329, 1.0);
+        assert!((xy_to_cct(&d65) - 6504.0).abs() < 10.0);
+
+        for kelvin in [2700.0, 4000.0, 6500.0] {
+            let cct = xy_to_cct(&cct_to_xy(kelvin));
+            assert!((cct - kelvin).abs() < 50.0, "{}K was {}K", kelvin, cct);
+        }
+    }
+
+    #[test]
+    fn interpolate_cct_uses_mired_scale() {
+        assert_eq!(interpolate_cct(5000.0, 2000.0, 0.0), 5000.0);
+        assert_eq!(interpolate_cct(5000.0, 2000.0, 1.0), 2000.0);
+
+        // 200 and 500 mireds, halfway is 350 mireds
+        let halfway = interpolate_cct(5000.0, 2000.0, 0.5);
+        assert!((halfway - 2857.14).abs() < 0.1, "was {}K", halfway);
+    }
 }
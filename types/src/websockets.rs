@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    device::DevicesState, event::Message, group::FlattenedGroupsConfig,
-    scene::FlattenedScenesConfig,
+    device::DevicesState, event::Message, event_log::EventLogEntry, group::FlattenedGroupsConfig,
+    integration::IntegrationsStatus, scene::FlattenedScenesConfig,
 };
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -20,4 +20,6 @@ pub struct StateUpdate {
 #[derive(Deserialize, Serialize, Debug)]
 pub enum WebSocketResponse {
     State(StateUpdate),
+    Event(Box<EventLogEntry>),
+    IntegrationsStatus(IntegrationsStatus),
 }